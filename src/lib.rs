@@ -0,0 +1,8829 @@
+//! Renaming and reporting utilities behind the `ram-utils` command line tool
+//!
+//! Names are converted by a `Transform`, e.g. `Transform::Case(LetterCase::UpperCase)`, with
+//! `ConvertOptions`. Renames are planned up front with `plan_entry` or `convert_dir_plan` and
+//! carried out with `apply_rename`, or planned and applied at once by `convert_entry`,
+//! `convert_children` and `convert_file_or_dir`. Reports like `find_unique_extensions` and
+//! `find_duplicates` walk a tree with `WalkOptions` and return plain data to present.
+//! Progress and errors are written through a `Logger`.
+
+extern crate blake3;
+extern crate chrono;
+extern crate csv;
+extern crate failure;
+extern crate globset;
+extern crate ignore;
+extern crate md5;
+extern crate notify;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+extern crate termcolor;
+extern crate unicode_normalization;
+
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{self, Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use failure::{err_msg, Error};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+use md5::Md5;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use termcolor::{Color, ColorSpec, WriteColor};
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Clone, Copy)]
+pub enum LetterCase {
+    UpperCase,
+    LowerCase,
+}
+
+/// A word based naming convention, applied to the file stem
+pub enum CaseStyle {
+    /// `snake_case`
+    Snake,
+    /// `kebab-case`
+    Kebab,
+    /// `camelCase`
+    Camel,
+    /// `PascalCase`
+    Pascal,
+    /// `Title Case`
+    Title,
+}
+
+/// A transformation applied to the final component of a path
+pub enum Transform {
+    Case(LetterCase),
+    /// Separate letter cases for the stem and the extension, each left as is if `None`
+    StemExt {
+        stem: Option<LetterCase>,
+        ext: Option<LetterCase>,
+    },
+    Style(CaseStyle),
+    /// Trim and collapse whitespace, optionally also removing whitespace before the extension
+    Trim {
+        before_ext: bool,
+    },
+    /// Remove accents and replace each run of other non-ASCII characters with `placeholder`
+    StripDiacritics {
+        placeholder: String,
+    },
+    /// Only normalize the name to a Unicode form
+    Normalize(Normalization),
+    /// Replace each run of whitespace with `separator`, optionally also lower casing the name
+    Despace {
+        separator: char,
+        lower: bool,
+    },
+    /// Collapse a repeated extension into one, lower cased
+    FixDuplicateExt,
+    /// Replace characters that are invalid on some platforms with `replacement`
+    Sanitize {
+        replacement: String,
+    },
+    /// Replace every match of `pattern` with `replacement`, which may refer to capture groups
+    /// as `$1` or `${name}`
+    Replace {
+        pattern: Regex,
+        replacement: String,
+    },
+}
+
+/// What to do when a rename or copy targets an existing entry, selected by `--on-conflict`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConflictPolicy {
+    /// Leave the source in place and report an error
+    #[default]
+    Error,
+    /// Leave the source in place and carry on
+    Skip,
+    /// Append `_1`, `_2`, ... to the target stem until it's unique
+    Number,
+    /// Replace the existing entry
+    Overwrite,
+    /// Leave the source in place and stop at the first conflict
+    Fail,
+}
+
+impl ConflictPolicy {
+    /// Returns true if `error` should end the whole run rather than just the one rename
+    pub fn stops_on(self, error: &Error) -> bool {
+        self == ConflictPolicy::Fail && error.downcast_ref::<Conflict>().is_some()
+    }
+}
+
+/// The target of a rename or copy already exists
+#[derive(Debug)]
+pub struct Conflict {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Can't rename {:?}, {:?} already exists",
+            self.from, self.to
+        )
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+/// Order of the renames planned by `convert_dir_plan`
+///
+/// Either way a directory is always renamed after everything inside it.
+#[derive(Clone, Copy, Default)]
+pub enum TraversalOrder {
+    /// Each subtree in turn, children before their directory
+    #[default]
+    DepthFirst,
+    /// Level by level, starting with the deepest entries
+    BreadthFirst,
+}
+
+/// Unicode normalization form applied to names before and after a transform
+#[derive(Clone, Copy)]
+pub enum Normalization {
+    /// Composed, e.g. `é` as a single code point, as typed on most systems
+    Nfc,
+    /// Decomposed, e.g. `e` followed by a combining accent, as stored by macOS
+    Nfd,
+}
+
+/// Language-specific letter case rules, which only change how `i` and `I` are cased
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CaseLocale {
+    /// The locale-independent Unicode mappings
+    #[default]
+    Default,
+    /// Turkish and Azeri, with dotted `i`/`İ` and dotless `ı`/`I` pairs
+    Turkish,
+}
+
+/// Name `unique_ext --roll-rare` lists the extensions below `--min-count` under
+const RARE_EXTENSIONS: &str = "(other)";
+
+/// Name `unique_ext` counts the files without an extension under
+const NO_EXTENSION: &str = "(none)";
+
+/// Name `unique_ext` counts dotfiles without another extension under, e.g. `.bashrc`
+const DOTFILE_EXTENSION: &str = "(dotfile)";
+
+/// Extensions counted as one by `unique_ext --compound-ext` unless set in the config file
+pub const DEFAULT_COMPOUND_EXTENSIONS: &[&str] = &["tar.gz", "tar.bz2", "tar.xz", "tar.zst"];
+
+/// Built-in extensions of each `categorize` category, any other extension is in `other`
+const DEFAULT_CATEGORIES: &[(&str, &[&str])] = &[
+    (
+        "images",
+        &[
+            "bmp", "cr2", "gif", "heic", "ico", "jpeg", "jpg", "nef", "png", "raw", "svg", "tif",
+            "tiff", "webp",
+        ],
+    ),
+    (
+        "audio",
+        &[
+            "aac", "aiff", "flac", "m4a", "mid", "mp3", "ogg", "opus", "wav", "wma",
+        ],
+    ),
+    (
+        "video",
+        &[
+            "avi", "flv", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "webm", "wmv",
+        ],
+    ),
+    (
+        "documents",
+        &[
+            "csv", "doc", "docx", "epub", "md", "odp", "ods", "odt", "pdf", "ppt", "pptx", "rtf",
+            "txt", "xls", "xlsx",
+        ],
+    ),
+    (
+        "code",
+        &[
+            "c", "cpp", "cs", "css", "go", "h", "hpp", "html", "java", "js", "json", "kt", "py",
+            "rb", "rs", "sh", "sql", "swift", "toml", "ts", "xml", "yaml", "yml",
+        ],
+    ),
+];
+
+/// Category of the extensions not in any other `categorize` category
+const OTHER_CATEGORY: &str = "other";
+
+/// Characters separating the words of a name unless `--word-sep` is given
+const DEFAULT_WORD_SEPARATORS: &str = " _-";
+
+/// Options controlling which entries are visited while walking a directory tree
+#[derive(Default)]
+pub struct WalkOptions {
+    /// Visit hidden entries (names starting with a `.`), which are skipped by default
+    pub include_hidden: bool,
+    /// Skip entries matched by `.gitignore` files in the walked directories
+    pub respect_gitignore: bool,
+    /// Don't descend into directories whose name matches one of these patterns
+    pub exclude_dirs: GlobSet,
+    /// Only visit files whose path below the walked directory matches one of these patterns,
+    /// or every file if empty
+    pub include: GlobSet,
+    /// Skip files and directories whose path below the walked directory matches one of these
+    /// patterns, e.g. `*.tmp` or `node_modules/**`
+    pub exclude: GlobSet,
+    /// Skip files smaller than this many bytes
+    pub min_size: Option<u64>,
+    /// Skip files larger than this many bytes
+    pub max_size: Option<u64>,
+    /// Skip files last modified before this time
+    pub newer_than: Option<SystemTime>,
+    /// Skip files last modified after this time
+    pub older_than: Option<SystemTime>,
+    /// Only descend this many levels below the walked directory, 1 meaning only its entries
+    /// are visited, or without limit if `None`
+    pub max_depth: Option<usize>,
+    /// Descend into symlinked directories, unless they link back to a directory being walked
+    pub follow_symlinks: bool,
+}
+
+/// Decides which entries of a directory are skipped while walking it
+///
+/// Holds the `.gitignore` matchers of the directory and its walked ancestors, innermost last,
+/// so nested `.gitignore` files are honored relative to their own directory.
+#[derive(Clone, Default)]
+struct EntryFilter {
+    gitignores: Vec<Rc<Gitignore>>,
+    /// The directory the walk started from, which `--include`/`--exclude` are relative to
+    root: Option<Rc<PathBuf>>,
+    /// How many levels below the walked directory the entries are, 1 for its own entries
+    depth: usize,
+    /// Canonical paths of the directory and its walked ancestors, kept with `follow_symlinks`
+    /// to avoid following a link back into them forever
+    ancestors: Vec<PathBuf>,
+}
+
+/// Options controlling which entries are converted by `convert_dir_plan` and how
+#[derive(Default)]
+pub struct ConvertOptions {
+    /// Convert the children of directories as well as the directories themselves
+    pub recursive: bool,
+    pub ignore_files: bool,
+    pub ignore_dirs: bool,
+    /// Only convert files with one of these (lower case) extensions, or all files if empty
+    pub only_ext: Vec<String>,
+    /// Never convert files with one of these (lower case) extensions, even if in `only_ext`
+    pub ignore_ext: Vec<String>,
+    /// Words whose casing is kept as given, e.g. `NASA` or `iPhone`
+    pub keep: Vec<String>,
+    /// Characters separating words in the original name, `DEFAULT_WORD_SEPARATORS` if `None`
+    pub word_sep: Option<String>,
+    /// Rejoin the words of the file stem with this separator
+    pub out_sep: Option<String>,
+    /// Normalize names to this form, or leave them as they are if `None`
+    pub normalize: Option<Normalization>,
+    pub locale: CaseLocale,
+    pub order: TraversalOrder,
+    pub on_conflict: ConflictPolicy,
+    pub walk: WalkOptions,
+    /// Carry on past renames that fail, rather than ending the run at the first one
+    pub keep_going: bool,
+}
+
+impl ConvertOptions {
+    /// Returns true if the failed rename `error` should end the run: any failure unless
+    /// `keep_going` is set, and a conflict under `ConflictPolicy::Fail` even then
+    pub fn stops_on(&self, error: &Error) -> bool {
+        !self.keep_going || self.on_conflict.stops_on(error)
+    }
+}
+
+/// Options controlling how `flatten` moves files
+#[derive(Default)]
+pub struct FlattenOptions {
+    /// Print the planned moves without touching the filesystem
+    pub dry_run: bool,
+    /// Remove source directories left empty after flattening
+    pub prune: bool,
+    pub walk: WalkOptions,
+}
+
+/// How `organize` picks the subdirectory a file is moved into
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OrganizeBy {
+    /// The lower cased extension, e.g. `pdf/`, or `none/` without one
+    #[default]
+    Ext,
+    /// The year and month of the (local) modified time, e.g. `2023/05/`
+    Date,
+}
+
+/// Options controlling how `organize` moves files
+#[derive(Default)]
+pub struct OrganizeOptions {
+    /// Print the planned moves without touching the filesystem
+    pub dry_run: bool,
+    pub by: OrganizeBy,
+    pub walk: WalkOptions,
+}
+
+/// Options controlling which entries `tree` prints and how
+#[derive(Default)]
+pub struct TreeOptions {
+    pub ignore_files: bool,
+    pub ignore_dirs: bool,
+    /// Annotate entries with their name after this transform, e.g. `abc -> ABC`
+    pub preview: Option<Transform>,
+    /// Annotate files with their size, e.g. `[512 bytes]`
+    pub sizes: bool,
+    /// Write the sizes in K, M, G, ... rather than bytes, see `format_size`
+    pub human: bool,
+    /// Annotate files with their extension as `unique_ext` counts them, e.g. `[jpg]`
+    pub extensions: bool,
+    pub walk: WalkOptions,
+}
+
+/// A placeholder in a `template` rename pattern
+#[derive(Debug, PartialEq)]
+pub enum Placeholder {
+    Name,
+    Ext,
+    Year,
+    Month,
+    Day,
+    /// `year-month-day`
+    Date,
+    /// `hourminutesecond`, e.g. `142233`
+    Time,
+    Size,
+    /// Camera model of a photo, empty for other files
+    Model,
+    /// Name of the directory containing the file
+    Parent,
+    /// Position of the file among those renamed, from 1, zero padded to `width` digits
+    Counter {
+        width: usize,
+    },
+}
+
+/// A piece of a parsed `template` rename pattern
+#[derive(Debug, PartialEq)]
+pub enum TemplatePart {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// The values substituted for the placeholders of a template for a single file
+pub struct TemplateValues {
+    /// File stem
+    pub name: String,
+    /// Extension without the leading `.`, empty if there is none
+    pub ext: String,
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// Size in bytes
+    pub size: u64,
+    /// Name of the directory containing the file
+    pub parent: String,
+    /// Camera model of a photo, empty for other files
+    pub model: String,
+}
+
+/// What `photo-rename` reads from the EXIF data of a photo
+#[derive(Debug, Default, PartialEq)]
+pub struct PhotoInfo {
+    /// `DateTimeOriginal`, when the photo was taken
+    pub taken: Option<NaiveDateTime>,
+    pub model: Option<String>,
+}
+
+/// Something the `watch` loop reacts to
+pub enum WatchEvent {
+    /// An entry was created, moved into place or written to
+    Changed(PathBuf),
+    /// Watching should stop, e.g. after Ctrl-C
+    Stop,
+}
+
+/// Bytes hashed from the start of same-sized files before hashing them in full
+const QUICK_HASH_BYTES: u64 = 4096;
+
+/// Bytes read from the start of a photo to find its EXIF data
+const EXIF_READ_BYTES: u64 = 1024 * 1024;
+
+/// Extensions of the files renamed by `photo-rename`: JPEGs and TIFF based raw formats
+pub const PHOTO_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "tif", "tiff", "dng", "cr2", "nef", "nrw", "arw", "orf", "rw2", "pef", "srw",
+];
+
+/// Algorithm used by `find-dupes` to compare file contents
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HashAlgo {
+    #[default]
+    Blake3,
+    Sha256,
+    Md5,
+}
+
+/// A set of files with identical contents
+#[derive(Clone)]
+pub struct DuplicateGroup {
+    /// Size in bytes of each file
+    pub size: u64,
+    /// Paths of the files, sorted
+    pub paths: Vec<PathBuf>,
+}
+
+/// File counts of a `categorize` category and of each of its extensions
+#[derive(Debug, PartialEq)]
+pub struct Category {
+    pub name: String,
+    pub total: u32,
+    /// Extensions with their counts, most common first
+    pub extensions: Vec<(String, u32)>,
+}
+
+/// File counts by extension below a top-level subdirectory, for `unique_ext --group-by-dir`
+#[derive(Debug, PartialEq)]
+pub struct DirExtensions {
+    /// Relative to the directory searched, `.` for the files directly inside it
+    pub dir: PathBuf,
+    pub extensions: HashMap<String, u32>,
+}
+
+/// Entries of a directory whose names only differ in case, e.g. `File.txt` and `file.TXT`
+#[derive(Debug, PartialEq)]
+pub struct NameClash {
+    pub dir: PathBuf,
+    /// Names of the entries, sorted
+    pub names: Vec<PathBuf>,
+}
+
+/// A symlink found by `links`
+#[derive(Debug, PartialEq)]
+pub struct Link {
+    pub path: PathBuf,
+    /// Target as stored in the link, which may be relative to its directory
+    pub target: PathBuf,
+    /// The target doesn't exist
+    pub broken: bool,
+}
+
+/// The total size of the files below a directory, as reported by `du`
+#[derive(Debug, PartialEq)]
+pub struct DirSize {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Metadata of a file, or of a directory and everything below it, as reported by `stat`
+#[derive(Debug, Default)]
+pub struct PathStats {
+    pub path: PathBuf,
+    /// `file`, `dir` or `symlink`
+    pub kind: String,
+    /// The permission bits, e.g. `0o755`, or `None` on platforms without them
+    pub mode: Option<u32>,
+    pub readonly: bool,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    /// `None` where the filesystem doesn't record it
+    pub created: Option<SystemTime>,
+    /// Total size in bytes of the files and symlinks
+    pub size: u64,
+    pub files: usize,
+    pub dirs: usize,
+    pub symlinks: usize,
+    /// The least and most recently modified files
+    pub oldest: Option<(PathBuf, SystemTime)>,
+    pub newest: Option<(PathBuf, SystemTime)>,
+    /// The entry the most levels below the directory, and how many levels
+    pub deepest: Option<(PathBuf, usize)>,
+    /// The entry with the longest name, counted in characters
+    pub longest_name: Option<PathBuf>,
+}
+
+/// A planned rename as written by `--plan-json` and read by `apply`
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlanEntry {
+    pub from: String,
+    pub to: String,
+    /// `file`, `dir` or `symlink`
+    pub kind: String,
+    /// False if either path isn't valid UTF-8, in which case it was converted lossily
+    pub valid_utf8: bool,
+}
+
+/// Order in which extensions are listed by `unique_ext`
+pub enum ExtensionSort {
+    /// Alphabetical by extension
+    Name,
+    /// Most common extension first
+    Count,
+    /// Largest total size first, from the sizes found by `find_extension_sizes`
+    Size(HashMap<String, u64>),
+    /// The reverse of another order
+    Reversed(Box<ExtensionSort>),
+}
+
+/// Total sizes in bytes listed next to the extension counts, from `find_extension_sizes`
+pub struct ExtensionSizes {
+    pub sizes: HashMap<String, u64>,
+    /// Write them in K, M, G, ... rather than bytes, see `format_size`
+    pub human: bool,
+}
+
+impl ExtensionSizes {
+    fn size(&self, ext: &str) -> u64 {
+        self.sizes.get(ext).copied().unwrap_or(0)
+    }
+
+    fn format(&self, bytes: u64) -> String {
+        size_label(bytes, self.human)
+    }
+}
+
+/// Exit code of a command that failed, or of a conversion that renamed nothing and failed to
+/// convert at least one entry
+pub const EXIT_ERROR: i32 = 1;
+
+/// Exit code of a conversion that ran without errors but had nothing to rename
+pub const EXIT_NOTHING_TO_DO: i32 = 2;
+
+/// Exit code of a conversion that renamed some entries but failed to convert others
+pub const EXIT_PARTIAL: i32 = 3;
+
+/// Exit code for invalid command line arguments, `EX_USAGE` of `sysexits.h`
+pub const EXIT_USAGE: i32 = 64;
+
+/// Counts of what happened during a conversion run
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Summary {
+    /// Files (and symlinks) renamed
+    pub files: usize,
+    /// Directories renamed
+    pub dirs: usize,
+    /// Entries already in the target case, which aren't renamed
+    pub skipped: usize,
+    pub errors: usize,
+}
+
+impl Summary {
+    /// Returns `EXIT_PARTIAL` if anything failed but something was renamed, `EXIT_ERROR` if
+    /// anything failed and nothing was renamed, otherwise 0 if anything was renamed or
+    /// `EXIT_NOTHING_TO_DO` if not
+    pub fn exit_code(&self) -> i32 {
+        if self.errors > 0 && self.files + self.dirs > 0 {
+            EXIT_PARTIAL
+        } else if self.errors > 0 {
+            EXIT_ERROR
+        } else if self.files + self.dirs > 0 {
+            0
+        } else {
+            EXIT_NOTHING_TO_DO
+        }
+    }
+
+    /// Like `exit_code`, but for a dry run or plan of `planned` renames that weren't made
+    pub fn planned_exit_code(&self, planned: usize) -> i32 {
+        if self.errors > 0 {
+            EXIT_ERROR
+        } else if planned > 0 {
+            0
+        } else {
+            EXIT_NOTHING_TO_DO
+        }
+    }
+
+    /// Like `exit_code`, but for commands reporting on entries rather than renaming them:
+    /// `EXIT_ERROR` if anything failed, otherwise 0
+    pub fn report_exit_code(&self) -> i32 {
+        if self.errors > 0 {
+            EXIT_ERROR
+        } else {
+            0
+        }
+    }
+
+    /// Adds the counts of `other`, e.g. of another thread
+    fn add(&mut self, other: &Summary) {
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.skipped += other.skipped;
+        self.errors += other.errors;
+    }
+}
+
+impl fmt::Display for Summary {
+    /// E.g. `Renamed 42 files, 7 dirs; skipped 10; 1 error`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        write!(
+            f,
+            "Renamed {} file{}, {} dir{}; skipped {}; {} error{}",
+            self.files,
+            plural(self.files),
+            self.dirs,
+            plural(self.dirs),
+            self.skipped,
+            self.errors,
+            plural(self.errors)
+        )
+    }
+}
+
+/// Entries read from directories so far, reported by `--timings`
+pub static ENTRIES_READ: AtomicUsize = AtomicUsize::new(0);
+
+/// How long a command took and how many entries it read, printed with `--timings`
+#[derive(Debug, PartialEq)]
+pub struct Timings {
+    pub elapsed: Duration,
+    pub entries: usize,
+}
+
+impl fmt::Display for Timings {
+    /// E.g. `Took 1.500s; read 3000 entries (2000 entries/s)`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        let rate = if seconds > 0.0 {
+            self.entries as f64 / seconds
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "Took {:.3}s; read {} ({:.0} entries/s)",
+            seconds,
+            entries(self.entries),
+            rate
+        )
+    }
+}
+
+/// How often a `Progress` bar is redrawn, and how long a run takes before it first appears
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Width of a `Progress` bar, between the brackets
+const PROGRESS_WIDTH: usize = 30;
+
+/// A progress bar redrawn in place, e.g. `[=============>                ] 4500/10000 (45%) ETA 12s`
+pub struct Progress {
+    total: usize,
+    done: usize,
+    start: Instant,
+    /// When the bar was last drawn, `None` until it first is
+    drawn: Option<Instant>,
+    out: Box<dyn Write>,
+}
+
+impl Progress {
+    /// Creates a bar for `total` items, written to `out`, which should be a terminal
+    pub fn new(total: usize, out: Box<dyn Write>) -> Progress {
+        Progress {
+            total,
+            done: 0,
+            start: Instant::now(),
+            drawn: None,
+            out,
+        }
+    }
+
+    /// Counts another finished item, redrawing the bar at most every `PROGRESS_INTERVAL`
+    ///
+    /// Runs finishing within the first interval never show the bar at all.
+    pub fn inc(&mut self) {
+        self.done = (self.done + 1).min(self.total);
+
+        let now = Instant::now();
+        let last = self.drawn.unwrap_or(self.start);
+        if now.duration_since(last) >= PROGRESS_INTERVAL
+            || (self.drawn.is_some() && self.done == self.total)
+        {
+            let line = self.render(now.duration_since(self.start));
+            let _ = write!(self.out, "\r{}", line);
+            let _ = self.out.flush();
+            self.drawn = Some(now);
+        }
+    }
+
+    /// Clears the bar, so what's written next starts on an empty line
+    pub fn finish(&mut self) {
+        if self.drawn.is_some() {
+            let _ = write!(self.out, "\r\x1b[K");
+            let _ = self.out.flush();
+            self.drawn = None;
+        }
+    }
+
+    /// Renders the bar after `elapsed`, estimating the time left from the rate so far
+    fn render(&self, elapsed: Duration) -> String {
+        let fraction = if self.total > 0 {
+            self.done as f64 / self.total as f64
+        } else {
+            1.0
+        };
+        let filled = (fraction * PROGRESS_WIDTH as f64) as usize;
+        let bar = if filled < PROGRESS_WIDTH {
+            format!(
+                "{}>{}",
+                "=".repeat(filled),
+                " ".repeat(PROGRESS_WIDTH - filled - 1)
+            )
+        } else {
+            "=".repeat(PROGRESS_WIDTH)
+        };
+
+        let eta = if self.done > 0 {
+            let left = elapsed.as_secs_f64() * (self.total - self.done) as f64 / self.done as f64;
+            format_duration(left.round() as u64)
+        } else {
+            "?".to_string()
+        };
+
+        format!(
+            "[{}] {}/{} ({:.0}%) ETA {}",
+            bar,
+            self.done,
+            self.total,
+            fraction * 100.0,
+            eta
+        )
+    }
+}
+
+/// Formats a number of seconds, e.g. `12s`, `3m05s` or `1h02m`
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// How much is written while running a command
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    /// Only errors are written
+    Quiet,
+    /// Errors and a one-line summary at the end of the command
+    Normal,
+    /// Every change is written as well
+    Verbose,
+    /// Every change and skipped entry is written as well
+    VeryVerbose,
+}
+
+/// Writes messages to stdout/stderr according to the configured verbosity
+pub struct Logger {
+    pub verbosity: Verbosity,
+    pub out: Box<dyn Write>,
+    pub err: Box<dyn Write>,
+    /// Counts of the renames, skipped entries and errors logged so far
+    pub summary: Summary,
+    /// Don't write skipped entries, even when very verbose
+    pub quiet_skips: bool,
+    /// Paths inside this directory are shown relative to it
+    pub relative_to: Option<PathBuf>,
+    /// Keep the renames and errors in `renamed` and `error_messages`, for `--format json|csv`
+    pub record: bool,
+    pub renamed: Vec<PlanEntry>,
+    pub error_messages: Vec<String>,
+    /// The entries whose rename failed and why, for the report at the end of a run
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl Logger {
+    pub fn new(verbosity: Verbosity) -> Logger {
+        Logger::with_writers(verbosity, Box::new(io::stdout()), Box::new(io::stderr()))
+    }
+
+    pub fn with_writers(verbosity: Verbosity, out: Box<dyn Write>, err: Box<dyn Write>) -> Logger {
+        Logger {
+            verbosity,
+            out,
+            err,
+            summary: Summary::default(),
+            quiet_skips: false,
+            relative_to: None,
+            record: false,
+            renamed: Vec::new(),
+            error_messages: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// Logs a change made to the filesystem, only written when verbose
+    pub fn change(&mut self, message: &str) {
+        if self.verbosity >= Verbosity::Verbose {
+            let _ = writeln!(self.out, "{}", message);
+        }
+    }
+
+    /// Logs an entry that was left alone, only written when very verbose and not `quiet_skips`
+    pub fn skip(&mut self, message: &str) {
+        if self.verbosity >= Verbosity::VeryVerbose && !self.quiet_skips {
+            let _ = writeln!(self.out, "{}", message);
+        }
+    }
+
+    /// Logs a message that is written unless quiet
+    pub fn info(&mut self, message: &str) {
+        if self.verbosity >= Verbosity::Normal {
+            let _ = writeln!(self.out, "{}", message);
+        }
+    }
+
+    /// Returns `path` for display, relative to `relative_to` if it's inside it
+    pub fn path(&self, path: &Path) -> String {
+        let relative = self
+            .relative_to
+            .as_ref()
+            .and_then(|base| path.strip_prefix(base).ok());
+        match relative {
+            Some(relative) if !relative.as_os_str().is_empty() => relative.display().to_string(),
+            _ => path.display().to_string(),
+        }
+    }
+
+    /// Logs a warning, which is always written but isn't counted as an error
+    pub fn warn(&mut self, message: &str) {
+        let _ = writeln!(self.err, "Warning: {}", message);
+    }
+
+    /// Logs an error, which is always written
+    pub fn error(&mut self, message: &str) {
+        self.summary.errors += 1;
+        let _ = writeln!(self.err, "{}", message);
+        if self.record {
+            let message = message.trim_start_matches("Error: ");
+            self.error_messages.push(message.to_string());
+        }
+    }
+
+    /// Logs the failed rename of `path` as an error, keeping it for `report_failures`
+    pub fn fail(&mut self, path: &Path, error: &Error) {
+        self.error(&format!("Error: {}", error));
+        self.failures.push((path.to_path_buf(), error.to_string()));
+    }
+
+    /// Writes every failed rename with its error, if there were any
+    pub fn report_failures(&mut self) {
+        if self.failures.is_empty() {
+            return;
+        }
+
+        let _ = writeln!(
+            self.err,
+            "Failed to convert {}:",
+            entries(self.failures.len())
+        );
+        for (path, error) in &self.failures {
+            let _ = writeln!(self.err, "    {}: {}", self.path(path), error);
+        }
+    }
+}
+
+/// An in-memory writer whose contents can be read after being handed to a `Logger`
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds the matcher for `--exclude-dir` names or `--include`/`--exclude` patterns
+pub fn glob_set<'a, I: IntoIterator<Item = &'a str>>(names: I) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+    for name in names {
+        builder.add(Glob::new(name)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Parses a duration before `now` (e.g. `30m`, `24h`, `7d` or `2w`) or a local date
+/// (`2023-01-01`, meaning its midnight) into a point in time
+pub fn parse_time(time: &str, now: SystemTime) -> Result<SystemTime, Error> {
+    let time = time.trim();
+    let invalid = || err_msg(format!("Invalid duration or date: {}", time));
+
+    if let Ok(date) = NaiveDate::parse_from_str(time, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        let local = Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .ok_or_else(invalid)?;
+        return Ok(local.into());
+    }
+
+    let split = time
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(time.len());
+    let (number, unit) = time.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    now.checked_sub(Duration::from_secs(number * seconds))
+        .ok_or_else(invalid)
+}
+
+/// Parses a human readable size into bytes, e.g. `1024`, `512K`, `1.5M` or `2GB`
+///
+/// Units are powers of 1024 and case-insensitive, optionally followed by `B` or `iB`.
+pub fn parse_size(size: &str) -> Result<u64, Error> {
+    let size = size.trim();
+    let invalid = || err_msg(format!("Invalid size: {}", size));
+
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let multiplier: u64 = match unit
+        .to_lowercase()
+        .trim_end_matches("ib")
+        .trim_end_matches('b')
+    {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Returns the transform for an `upper`, `lower` or case style (`snake`, `kebab`, `camel`,
+/// `pascal`, `title`) argument
+pub fn style_transform(style: &str) -> Option<Transform> {
+    match style {
+        "upper" => Some(Transform::Case(LetterCase::UpperCase)),
+        "lower" => Some(Transform::Case(LetterCase::LowerCase)),
+        "snake" => Some(Transform::Style(CaseStyle::Snake)),
+        "kebab" => Some(Transform::Style(CaseStyle::Kebab)),
+        "camel" => Some(Transform::Style(CaseStyle::Camel)),
+        "pascal" => Some(Transform::Style(CaseStyle::Pascal)),
+        "title" => Some(Transform::Style(CaseStyle::Title)),
+        _ => None,
+    }
+}
+
+/// Plans moving each file below `root` to its path with every component below `root`
+/// converted, e.g. `Photos/Summer/IMG.JPG` => `photos/summer/img.jpg`
+///
+/// Directories aren't renamed themselves, so ones converting to the same name (e.g. `Photos`
+/// and `PHOTOS`) are merged. Errors are logged rather than returned.
+pub fn plan_path_moves(
+    root: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut plan = Vec::new();
+    let filter = EntryFilter::default();
+    if let Err(e) = plan_copies(root, root, transform, options, &filter, log, &mut plan) {
+        log.error(&format!("Error: {}", e));
+    }
+
+    plan.retain(|(from, to)| {
+        if from == to {
+            log.summary.skipped += 1;
+            log.skip(&format!("Skipping {:?}", from));
+        }
+        from != to
+    });
+    plan
+}
+
+/// Removes the directories left empty by moving the files of `plan`, deepest first, stopping
+/// at the `roots` the files were moved within
+pub fn remove_emptied_dirs(plan: &[(PathBuf, PathBuf)], roots: &[PathBuf], log: &mut Logger) {
+    let mut dirs = HashSet::new();
+    for (from, _) in plan {
+        // The innermost root the file was moved within, should the roots be nested
+        let root = match roots
+            .iter()
+            .filter(|root| from.starts_with(root))
+            .max_by_key(|root| root.components().count())
+        {
+            Some(root) => root,
+            None => continue,
+        };
+        for dir in from.ancestors().skip(1) {
+            if dir == root.as_path() {
+                break;
+            }
+            dirs.insert(dir.to_path_buf());
+        }
+    }
+
+    let mut dirs: Vec<PathBuf> = dirs.into_iter().collect();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+
+    for dir in dirs {
+        let is_empty = fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_none());
+        if is_empty {
+            log.change(&format!("Removing {}", log.path(&dir)));
+            if let Err(e) = fs::remove_dir(&dir) {
+                log.error(&format!("Error: Failed to remove {:?}: {}", dir, e));
+            }
+        }
+    }
+}
+
+/// Returns the directory logged paths are shown relative to when converting `path`, which is
+/// `path` itself if it's a directory or else its parent
+pub fn display_base(path: &Path) -> Option<PathBuf> {
+    if path.is_dir() {
+        Some(path.to_path_buf())
+    } else {
+        path.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+    }
+}
+
+/// Reads a list of paths separated by newlines (or NUL bytes if `null_delimited` is set)
+///
+/// Empty entries are ignored, as is a trailing `\r` in newline separated input.
+pub fn read_paths<R: Read>(mut reader: R, null_delimited: bool) -> Result<Vec<PathBuf>, Error> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+
+    let separator = if null_delimited { '\0' } else { '\n' };
+
+    Ok(input
+        .split(separator)
+        .map(|line| {
+            if null_delimited {
+                line
+            } else {
+                line.trim_end_matches('\r')
+            }
+        })
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// E.g. `1 entry`, `42 entries`
+pub fn entries(count: usize) -> String {
+    format!("{} {}", count, if count == 1 { "entry" } else { "entries" })
+}
+
+/// Converts a single file or directory, along with its children if `options.recursive` is set
+pub fn convert_entry(
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) {
+    for (from, to) in plan_entry(path, transform, options, log) {
+        if let Err(e) = apply_rename(&from, &to, options.on_conflict, log) {
+            log.fail(&from, &e);
+            if options.on_conflict.stops_on(&e) {
+                break;
+            }
+        }
+    }
+}
+
+/// Converts the children of `path`, planning every rename before applying any of them
+///
+/// The first failed rename is returned, unless `options.keep_going` is set, in which case each
+/// failure is logged (see `Logger::fail`) and the rest are still renamed.
+pub fn convert_children(
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) -> Result<(), Error> {
+    for (from, to) in convert_dir_plan(path, transform, options, log)? {
+        match apply_rename(&from, &to, options.on_conflict, log) {
+            Err(e) if !options.stops_on(&e) => log.fail(&from, &e),
+            result => {
+                result?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the final component of `path`, skipping it if the name is unchanged
+pub fn convert_file_or_dir(
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) -> Result<(), Error> {
+    match convert_path(path, transform, options) {
+        Some(target_path) => {
+            apply_rename(path, &target_path, options.on_conflict, log)?;
+            Ok(())
+        }
+        None => {
+            log.summary.skipped += 1;
+            log.skip(&format!("Skipping {:?}", path));
+            Ok(())
+        }
+    }
+}
+
+/// Plans the renames converting a single file or directory, along with its children if
+/// `options.recursive` is set
+///
+/// Errors are logged rather than returned so the other paths of a run can still be converted.
+pub fn plan_entry(
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut plan = Vec::new();
+
+    if !path.exists() {
+        log.error(&format!(
+            "File/Directory does not exist: {}",
+            path.display()
+        ));
+        return plan;
+    }
+
+    let path = match renameable_path(path) {
+        Ok(path) => path,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return plan;
+        }
+    };
+    let path = path.as_path();
+
+    if path.is_dir() && options.recursive {
+        match convert_dir_plan(path, transform, options, log) {
+            Ok(children) => plan.extend(children),
+            Err(e) => log.error(&format!("Error: {}", e)),
+        }
+    }
+
+    if path.is_dir()
+        || (path.is_file() && is_selected_file(path, options) && options.walk.selects_file(path))
+    {
+        match convert_path(path, transform, options) {
+            Some(target_path) => plan.push((path.to_path_buf(), target_path)),
+            None => {
+                log.summary.skipped += 1;
+                log.skip(&format!("Skipping {:?}", path));
+            }
+        }
+    }
+
+    plan
+}
+
+/// Returns `path` without trailing separators or `.` components, resolving paths ending in
+/// `.` or `..` to the directory they refer to
+///
+/// E.g. `photos/` => `photos`, `.` => `/home/ralph/photos`. Paths without a name to convert,
+/// like the filesystem root, are an error rather than silently left alone.
+fn renameable_path(path: &Path) -> Result<PathBuf, Error> {
+    let path: PathBuf = path.components().collect();
+    let path = if path.file_name().is_some() {
+        path
+    } else {
+        fs::canonicalize(&path)?
+    };
+
+    match path.file_name() {
+        Some(_) => Ok(path),
+        None => Err(err_msg(format!(
+            "{} has no name to convert",
+            path.display()
+        ))),
+    }
+}
+
+/// Describes each planned rename for `--plan-json`
+///
+/// Paths that aren't valid UTF-8 are converted lossily and marked with `valid_utf8: false`
+/// rather than dropped.
+pub fn plan_entries(plan: &[(PathBuf, PathBuf)]) -> Result<Vec<PlanEntry>, Error> {
+    plan.iter()
+        .map(|(from, to)| {
+            let file_type = fs::symlink_metadata(from)?.file_type();
+            Ok(PlanEntry::new(from, to, &file_type))
+        })
+        .collect()
+}
+
+impl PlanEntry {
+    pub fn new(from: &Path, to: &Path, file_type: &fs::FileType) -> PlanEntry {
+        PlanEntry {
+            from: from.to_string_lossy().into_owned(),
+            to: to.to_string_lossy().into_owned(),
+            kind: entry_kind(file_type).to_string(),
+            valid_utf8: from.to_str().is_some() && to.to_str().is_some(),
+        }
+    }
+}
+
+/// The outcome of a conversion run as written by `--format json`
+#[derive(Serialize)]
+struct RunReport<'a> {
+    renamed: &'a [PlanEntry],
+    summary: &'a Summary,
+    errors: &'a [String],
+}
+
+/// Writes the renames, counts and errors of a conversion run as a JSON object
+pub fn write_run_json<W: Write>(
+    out: &mut W,
+    renamed: &[PlanEntry],
+    summary: &Summary,
+    errors: &[String],
+) -> Result<(), Error> {
+    let report = RunReport {
+        renamed,
+        summary,
+        errors,
+    };
+    serde_json::to_writer_pretty(&mut *out, &report)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Writes a `from,to,kind,valid_utf8` header followed by one row per rename
+pub fn write_renames_csv<W: Write>(out: &mut W, renames: &[PlanEntry]) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    if renames.is_empty() {
+        writer.write_record(["from", "to", "kind", "valid_utf8"])?;
+    }
+    for entry in renames {
+        writer.serialize(entry)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Returns the `kind` of a plan entry, `file`, `dir` or `symlink`
+fn entry_kind(file_type: &fs::FileType) -> &'static str {
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "dir"
+    } else {
+        "file"
+    }
+}
+
+/// Copies each of `paths` into `into` under its converted name, leaving the originals untouched
+///
+/// The files inside a directory are copied directly into `into`, recreating the directories
+/// below it (with converted names) if `options.recursive` is set. Existing files are handled
+/// according to `options.on_conflict`. Returns the number of files copied.
+pub fn copy_converted(
+    paths: &[PathBuf],
+    into: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) -> usize {
+    let mut plan = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let result = plan_copies(
+                path,
+                into,
+                transform,
+                options,
+                &EntryFilter::default(),
+                log,
+                &mut plan,
+            );
+            if let Err(e) = result {
+                log.error(&format!("Error: {}", e));
+            }
+        } else if path.is_file() {
+            plan.push((
+                path.clone(),
+                into.join(converted_name(path, transform, options)),
+            ));
+        } else {
+            log.error(&format!(
+                "File/Directory does not exist: {}",
+                path.display()
+            ));
+        }
+    }
+
+    let mut copied = 0;
+    for (from, to) in plan {
+        let to = match resolve_conflict(&from, &to, options.on_conflict, log) {
+            Ok(Some(to)) => to,
+            Ok(None) => continue,
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                if options.on_conflict.stops_on(&e) {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        log.change(&format!("Copying {:?} => {:?}", from, to));
+        let result = fs::create_dir_all(to.parent().unwrap_or(into))
+            .map_err(Error::from)
+            .and_then(|_| {
+                if options.on_conflict == ConflictPolicy::Overwrite && to.is_file() {
+                    fs::remove_file(&to)?;
+                }
+                copy_path(&from, &to)
+            });
+        match result {
+            Ok(()) => copied += 1,
+            Err(e) => log.error(&format!("Error: Failed to copy {:?}: {}", from, e)),
+        }
+    }
+
+    log.info(&format!("Copied {} files", copied));
+    copied
+}
+
+/// Plans the copies of the files in `dir` to `target_dir` under their converted names
+fn plan_copies(
+    dir: &Path,
+    target_dir: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    parent_filter: &EntryFilter,
+    log: &mut Logger,
+    plan: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), Error> {
+    let filter = parent_filter.for_dir(dir, &options.walk);
+
+    for entry in read_dir_sorted(dir)? {
+        let entry_path = entry.path();
+        let is_dir = entry.file_type()?.is_dir();
+
+        if let Some(reason) = filter.exclusion(&entry_path, is_dir, &options.walk) {
+            log.skip(&format!("Skipping {:?}, {}", entry_path, reason));
+            continue;
+        }
+        if is_dir && (options.ignore_dirs || !options.recursive || !filter.descends(&options.walk))
+        {
+            continue;
+        }
+
+        if !is_dir && (options.ignore_files || !is_selected_file(&entry_path, options)) {
+            log.skip(&format!("Skipping {:?}, filtered by extension", entry_path));
+            continue;
+        }
+
+        let target = target_dir.join(converted_name(&entry_path, transform, options));
+        if is_dir {
+            plan_copies(&entry_path, &target, transform, options, &filter, log, plan)?;
+        } else {
+            plan.push((entry_path, target));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the final component of `path` after converting it
+fn converted_name(path: &Path, transform: &Transform, options: &ConvertOptions) -> PathBuf {
+    let converted = convert_path(path, transform, options).unwrap_or_else(|| path.to_path_buf());
+    PathBuf::from(converted.file_name().unwrap_or(OsStr::new("")))
+}
+
+/// Plans the renames converting the directories leading to each of `paths`, deepest first
+///
+/// Only directories below `root` are converted, which defaults to the current directory. For
+/// relative paths that means the directories named in the path itself, e.g. `a/b/c` converts
+/// `a/b` and `a` but never `.` or `..`. Renames are ordered bottom-up, so they can be applied
+/// after converting `paths` themselves without invalidating each other.
+pub fn plan_parents(
+    paths: &[PathBuf],
+    root: Option<&Path>,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let mut parents = HashSet::new();
+
+    for path in paths {
+        let root = match root {
+            Some(root) => root.to_path_buf(),
+            None if path.is_absolute() => env::current_dir()?,
+            None => PathBuf::new(),
+        };
+
+        if !path.starts_with(&root) {
+            return Err(err_msg(format!(
+                "{} is not inside {}, pass --parents-root to convert its parents",
+                path.display(),
+                root.display()
+            )));
+        }
+
+        for ancestor in path.ancestors().skip(1) {
+            if ancestor == root
+                || ancestor.file_name().is_none()
+                || ancestor.components().any(|c| c == Component::ParentDir)
+            {
+                break;
+            }
+            parents.insert(ancestor.to_path_buf());
+        }
+    }
+
+    let mut parents: Vec<PathBuf> = parents.into_iter().collect();
+    parents.sort_by(|a, b| {
+        b.components()
+            .count()
+            .cmp(&a.components().count())
+            .then(a.cmp(b))
+    });
+
+    let mut plan = Vec::new();
+    for parent in parents {
+        match convert_path(&parent, transform, options) {
+            Some(target_path) => plan.push((parent, target_path)),
+            None => {
+                log.summary.skipped += 1;
+                log.skip(&format!("Skipping {:?}", parent));
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Plans the renames needed to convert the children of `path`, deepest entries first
+///
+/// Each rename only changes the final component of its path and a directory is always renamed
+/// after everything inside it, so applying the plan in order never invalidates a path that is
+/// yet to be renamed. `options.order` decides whether subtrees are planned one at a time or all
+/// entries of a level together.
+pub fn convert_dir_plan(
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    dir_plan(path, transform, options, &EntryFilter::default(), log)
+}
+
+/// Plans the renames of the children of `path` as `convert_dir_plan` does, filtering its
+/// entries with `parent_filter` extended by `path`
+fn dir_plan(
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    parent_filter: &EntryFilter,
+    log: &mut Logger,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let mut plan = Vec::new();
+    plan_children(path, transform, options, parent_filter, log, &mut plan)?;
+
+    if let TraversalOrder::BreadthFirst = options.order {
+        // The sort is stable, so entries of a level keep their depth-first (sorted) order
+        plan.sort_by_key(|(from, _)| std::cmp::Reverse(from.components().count()));
+    }
+
+    Ok(plan)
+}
+
+fn plan_children(
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    parent_filter: &EntryFilter,
+    log: &mut Logger,
+    plan: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), Error> {
+    let filter = parent_filter.for_dir(path, &options.walk);
+
+    for entry in read_dir_sorted(path)? {
+        let file_type = entry.file_type()?;
+        let entry_path = entry.path();
+        let is_dir = file_type.is_dir();
+
+        if let Some(reason) = filter.exclusion(&entry_path, is_dir, &options.walk) {
+            log.skip(&format!("Skipping {:?}, {}", entry_path, reason));
+            continue;
+        }
+
+        if is_dir && options.ignore_dirs {
+            continue;
+        }
+
+        if !is_dir && (options.ignore_files || !is_selected_file(&entry_path, options)) {
+            log.skip(&format!("Skipping {:?}, filtered by extension", entry_path));
+            continue;
+        }
+
+        if filter.enters(&entry_path, &file_type, &options.walk) && filter.descends(&options.walk) {
+            plan_children(&entry_path, transform, options, &filter, log, plan)?;
+        }
+
+        match convert_path(&entry_path, transform, options) {
+            Some(target_path) => plan.push((entry_path, target_path)),
+            None => {
+                log.summary.skipped += 1;
+                log.skip(&format!("Skipping {:?}", entry_path));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if a file passes the extension filters in `options`
+///
+/// Extensions are compared case-insensitively, so `--only-ext mp3` selects `SONG.MP3`. A file
+/// matching both `--only-ext` and `--ignore-ext` is ignored.
+fn is_selected_file(path: &Path, options: &ConvertOptions) -> bool {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+
+    if let Some(ref ext) = ext {
+        if options.ignore_ext.contains(ext) {
+            return false;
+        }
+    }
+
+    if options.only_ext.is_empty() {
+        return true;
+    }
+
+    match ext {
+        Some(ref ext) => options.only_ext.contains(ext),
+        None => false,
+    }
+}
+
+/// Recursively walks the children of `path`, calling `visit` for each entry
+///
+/// Directories are visited after their children so callers are free to rename or remove them.
+/// Entries excluded by `options` are neither visited nor descended into.
+pub fn walk<F>(path: &Path, options: &WalkOptions, visit: &mut F) -> Result<(), Error>
+where
+    F: FnMut(&Path, &fs::FileType) -> Result<(), Error>,
+{
+    walk_filtered(
+        path,
+        options,
+        &EntryFilter::default(),
+        visit,
+        &mut |_, e| Err(e),
+    )
+}
+
+/// Like `walk`, but directories and entries that can't be read (e.g. without permission) are
+/// passed to `on_error` and skipped rather than ending the walk
+fn walk_skipping_errors<F, E>(
+    path: &Path,
+    options: &WalkOptions,
+    visit: &mut F,
+    on_error: &mut E,
+) -> Result<(), Error>
+where
+    F: FnMut(&Path, &fs::FileType) -> Result<(), Error>,
+    E: FnMut(&Path, Error),
+{
+    walk_filtered(
+        path,
+        options,
+        &EntryFilter::default(),
+        visit,
+        &mut |path, e| {
+            on_error(path, e);
+            Ok(())
+        },
+    )
+}
+
+/// Walks `path` as `walk` does, passing read errors to `on_error`, which decides whether to
+/// skip the directory or entry (`Ok`) or end the walk (`Err`)
+fn walk_filtered<F, E>(
+    path: &Path,
+    options: &WalkOptions,
+    parent_filter: &EntryFilter,
+    visit: &mut F,
+    on_error: &mut E,
+) -> Result<(), Error>
+where
+    F: FnMut(&Path, &fs::FileType) -> Result<(), Error>,
+    E: FnMut(&Path, Error) -> Result<(), Error>,
+{
+    let filter = parent_filter.for_dir(path, options);
+
+    let entries = match read_dir_sorted(path) {
+        Ok(entries) => entries,
+        Err(e) => return on_error(path, e),
+    };
+
+    for entry in entries {
+        let entry_path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                on_error(&entry_path, e.into())?;
+                continue;
+            }
+        };
+
+        if filter.is_excluded(&entry_path, file_type.is_dir(), options) {
+            continue;
+        }
+
+        if filter.enters(&entry_path, &file_type, options) && filter.descends(options) {
+            walk_filtered(&entry_path, options, &filter, visit, on_error)?;
+        }
+
+        visit(&entry_path, &file_type)?;
+    }
+
+    Ok(())
+}
+
+impl EntryFilter {
+    /// Returns the filter for the entries of `dir`, adding its `.gitignore` if it has one
+    fn for_dir(&self, dir: &Path, options: &WalkOptions) -> EntryFilter {
+        let mut filter = self.clone();
+        let gitignore_path = dir.join(".gitignore");
+
+        if filter.root.is_none() {
+            filter.root = Some(Rc::new(dir.to_path_buf()));
+        }
+        filter.depth += 1;
+
+        if options.follow_symlinks {
+            if let Ok(dir) = fs::canonicalize(dir) {
+                filter.ancestors.push(dir);
+            }
+        }
+
+        if options.respect_gitignore && gitignore_path.is_file() {
+            // Invalid lines are skipped, like git itself does
+            let (gitignore, _) = Gitignore::new(&gitignore_path);
+            filter.gitignores.push(Rc::new(gitignore));
+        }
+
+        filter
+    }
+
+    /// Returns true if the directories among the entries should be walked, as they're above
+    /// `max_depth`
+    fn descends(&self, options: &WalkOptions) -> bool {
+        options.max_depth.is_none_or(|max| self.depth < max)
+    }
+
+    /// Returns true if the entry at `path` should be walked into: a directory, or with
+    /// `follow_symlinks` a link to a directory that isn't one of the walked ancestors
+    fn enters(&self, path: &Path, file_type: &fs::FileType, options: &WalkOptions) -> bool {
+        if file_type.is_dir() {
+            return true;
+        }
+        if !options.follow_symlinks || !file_type.is_symlink() {
+            return false;
+        }
+
+        match fs::canonicalize(path) {
+            Ok(target) => target.is_dir() && !self.ancestors.contains(&target),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns true if the entry at `path` is hidden or ignored and shouldn't be visited
+    fn is_excluded(&self, path: &Path, is_dir: bool, options: &WalkOptions) -> bool {
+        self.exclusion(path, is_dir, options).is_some()
+    }
+
+    /// Returns why the entry at `path` shouldn't be visited, e.g. `hidden`, or `None` if it
+    /// should be
+    fn exclusion(&self, path: &Path, is_dir: bool, options: &WalkOptions) -> Option<&'static str> {
+        if !options.include_hidden && is_hidden(path) {
+            return Some("hidden");
+        }
+
+        if is_dir
+            && path
+                .file_name()
+                .is_some_and(|name| options.exclude_dirs.is_match(name))
+        {
+            return Some("excluded directory");
+        }
+
+        let relative = self
+            .root
+            .as_ref()
+            .and_then(|root| path.strip_prefix(root.as_path()).ok())
+            .unwrap_or(path);
+        // `node_modules/**` only matches the entries inside the directory, so directories are
+        // also matched with a trailing separator to skip them as a whole
+        if options.exclude.is_match(relative)
+            || (is_dir && options.exclude.is_match(relative.join("")))
+        {
+            return Some("excluded");
+        }
+        if !is_dir && !options.include.is_empty() && !options.include.is_match(relative) {
+            return Some("not included");
+        }
+
+        // The innermost `.gitignore` with a matching pattern decides, so `!keep.log` in a
+        // nested `.gitignore` overrides `*.log` in its parent
+        for gitignore in self.gitignores.iter().rev() {
+            let matched = gitignore.matched(path, is_dir);
+            if matched.is_ignore() {
+                return Some("ignored by .gitignore");
+            } else if matched.is_whitelist() {
+                break;
+            }
+        }
+
+        if !is_dir && !options.selects_file(path) {
+            return Some("filtered by size or modified time");
+        }
+        None
+    }
+}
+
+impl WalkOptions {
+    /// Returns true if the file at `path` passes the size and modified time filters
+    ///
+    /// Files whose metadata can't be read are selected so the error surfaces when they're used.
+    fn selects_file(&self, path: &Path) -> bool {
+        if self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.newer_than.is_none()
+            && self.older_than.is_none()
+        {
+            return true;
+        }
+
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return true,
+        };
+
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+        {
+            return false;
+        }
+
+        if self.newer_than.is_none() && self.older_than.is_none() {
+            return true;
+        }
+
+        match metadata.modified() {
+            Ok(modified) => {
+                self.newer_than.is_none_or(|time| modified >= time)
+                    && self.older_than.is_none_or(|time| modified <= time)
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// Reads all entries of a directory up front, sorted by file name
+///
+/// Snapshotting the entries means renames made while processing a directory can never be
+/// picked up by its (live) `ReadDir` iterator, which is especially ambiguous on case-insensitive
+/// filesystems where `abc` and `ABC` are the same entry. Sorting makes the order deterministic.
+fn read_dir_sorted(path: &Path) -> Result<Vec<fs::DirEntry>, Error> {
+    let mut entries = fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    ENTRIES_READ.fetch_add(entries.len(), Ordering::Relaxed);
+    Ok(entries)
+}
+
+/// Returns true if the final component of a path is a hidden (dot) file or directory
+///
+/// Only the file name is checked, so `/home/ralph/.config/foo` is not hidden but
+/// `/home/ralph/.config` is.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// Returns `path` with its final component converted, or `None` if the name is unchanged
+///
+/// E.g.
+/// `/home/ralph/test/12345/abcd` => `/home/ralph/test/12345/ABCD`
+/// `/foo/bar/baz.zip` => `/foo/bar/BAZ.ZIP`
+///
+/// Names are compared exactly, so a name that only differs by case is still converted even
+/// though both paths refer to the same entry on a case-insensitive filesystem. Names the
+/// transform would empty or turn into a path, e.g. by replacing with `a/b`, are left alone.
+fn convert_path(path: &Path, transform: &Transform, options: &ConvertOptions) -> Option<PathBuf> {
+    let filename = path.file_name().and_then(OsStr::to_str).unwrap_or("");
+
+    if filename.is_empty() {
+        return None;
+    }
+
+    let target_filename = transform_filename(filename, transform, options);
+    if target_filename == filename
+        || target_filename.is_empty()
+        || target_filename == "."
+        || target_filename == ".."
+        || target_filename.contains(path::is_separator)
+    {
+        return None;
+    }
+
+    Some(
+        path.parent()
+            .unwrap_or(Path::new("."))
+            .join(target_filename),
+    )
+}
+
+/// Renames `from` to `to`, handling an existing entry at `to` according to `on_conflict`
+///
+/// Returns the path `from` was renamed to, or `None` if it was skipped.
+pub fn apply_rename(
+    from: &Path,
+    to: &Path,
+    on_conflict: ConflictPolicy,
+    log: &mut Logger,
+) -> Result<Option<PathBuf>, Error> {
+    let file_type = fs::symlink_metadata(from)?.file_type();
+    let is_dir = file_type.is_dir();
+    let to = match resolve_conflict(from, to, on_conflict, log)? {
+        Some(to) => to,
+        None => return Ok(None),
+    };
+
+    let message = format!("Converting {} => {}", log.path(from), log.path(&to));
+    log.change(&message);
+    move_path(from, &to, log)?;
+
+    if log.record {
+        log.renamed.push(PlanEntry::new(from, &to, &file_type));
+    }
+
+    if is_dir {
+        log.summary.dirs += 1;
+    } else {
+        log.summary.files += 1;
+    }
+    Ok(Some(to))
+}
+
+/// The result of a rename made by `apply_renames_parallel`, or `None` if it wasn't attempted
+pub type ParallelRename = Option<Result<Option<PathBuf>, Error>>;
+
+/// Applies the renames of `plan` like `apply_rename` does, on up to `threads` threads
+///
+/// Entries are renamed a level at a time, deepest first, so a directory is only renamed once
+/// everything inside it has been. The renames within a directory are made in plan order on
+/// the same thread, so conflicts between them are resolved as they would be one at a time.
+///
+/// Returns the result of each rename in plan order. The levels after a failure that ends the
+/// run (see `ConvertOptions::stops_on`) aren't attempted.
+pub fn apply_renames_parallel(
+    plan: &[(PathBuf, PathBuf)],
+    options: &ConvertOptions,
+    threads: usize,
+    log: &mut Logger,
+) -> Vec<ParallelRename> {
+    let mut results: Vec<ParallelRename> = plan.iter().map(|_| None).collect();
+
+    let mut levels: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, (from, _)) in plan.iter().enumerate() {
+        levels.entry(from.components().count()).or_default().push(i);
+    }
+
+    for level in levels.values().rev() {
+        // Each directory's renames go to one thread, spreading the directories between them
+        let mut batches = vec![Vec::new(); threads.max(1)];
+        let mut batch_of_dir = HashMap::new();
+        for &i in level {
+            let dir = plan[i].0.parent();
+            let next = batch_of_dir.len() % batches.len();
+            let batch = *batch_of_dir.entry(dir).or_insert(next);
+            batches[batch].push(i);
+        }
+
+        let (verbosity, quiet_skips, record) = (log.verbosity, log.quiet_skips, log.record);
+        let (relative_to, on_conflict) = (&log.relative_to, options.on_conflict);
+        let outcomes: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = batches
+                .iter()
+                .filter(|batch| !batch.is_empty())
+                .map(|batch| {
+                    scope.spawn(move || {
+                        let (out, err) = (SharedBuffer::default(), SharedBuffer::default());
+                        let mut thread_log = Logger::with_writers(
+                            verbosity,
+                            Box::new(out.clone()),
+                            Box::new(err.clone()),
+                        );
+                        thread_log.quiet_skips = quiet_skips;
+                        thread_log.relative_to = relative_to.clone();
+                        thread_log.record = record;
+
+                        let renamed: Vec<_> = batch
+                            .iter()
+                            .map(|&i| {
+                                let (from, to) = &plan[i];
+                                (i, apply_rename(from, to, on_conflict, &mut thread_log))
+                            })
+                            .collect();
+                        (
+                            renamed,
+                            thread_log.summary,
+                            thread_log.renamed,
+                            thread_log.error_messages,
+                            out.contents(),
+                            err.contents(),
+                        )
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("rename thread panicked"))
+                .collect()
+        });
+
+        let mut stop = false;
+        for (renamed, summary, records, errors, out, err) in outcomes {
+            let _ = write!(log.out, "{}", out);
+            let _ = write!(log.err, "{}", err);
+            log.summary.add(&summary);
+            log.renamed.extend(records);
+            log.error_messages.extend(errors);
+
+            for (i, result) in renamed {
+                if let Err(ref e) = result {
+                    stop |= options.stops_on(e);
+                }
+                results[i] = Some(result);
+            }
+        }
+        if stop {
+            break;
+        }
+    }
+
+    results
+}
+
+/// Returns where `from` should go when its target is `to`, or `None` to skip it
+///
+/// On case-insensitive filesystems `to` already "exists" when only the case changes, e.g.
+/// `foo` => `FOO`, so the entries are compared by identity rather than by name.
+fn resolve_conflict(
+    from: &Path,
+    to: &Path,
+    on_conflict: ConflictPolicy,
+    log: &mut Logger,
+) -> Result<Option<PathBuf>, Error> {
+    if fs::symlink_metadata(to).is_err() || is_same_entry(from, to)? {
+        return Ok(Some(to.to_path_buf()));
+    }
+
+    match on_conflict {
+        ConflictPolicy::Error | ConflictPolicy::Fail => Err(Conflict {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        }
+        .into()),
+        ConflictPolicy::Skip => {
+            log.summary.skipped += 1;
+            log.info(&format!("Skipping {:?}, {:?} already exists", from, to));
+            Ok(None)
+        }
+        ConflictPolicy::Number => {
+            let dir = to.parent().unwrap_or(Path::new("."));
+            let filename = Path::new(to.file_name().unwrap_or(OsStr::new("")));
+            Ok(Some(unique_path(dir, filename, &HashSet::new())))
+        }
+        ConflictPolicy::Overwrite => Ok(Some(to.to_path_buf())),
+    }
+}
+
+/// Returns true if both paths refer to the same filesystem entry, e.g. `foo` and `FOO` on a
+/// case-insensitive filesystem
+///
+/// Symlinks aren't followed, so a link is never the same entry as its target.
+#[cfg(unix)]
+fn is_same_entry(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (a, b) = (fs::symlink_metadata(a)?, fs::symlink_metadata(b)?);
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn is_same_entry(a: &Path, b: &Path) -> io::Result<bool> {
+    // Canonical paths are resolved by the filesystem, so `foo` and `FOO` resolve to the same
+    // path when they are the same entry
+    Ok(fs::canonicalize(a)? == fs::canonicalize(b)?)
+}
+
+/// Moves a file or directory, falling back to copy-then-delete across filesystems
+///
+/// `fs::rename` can't move entries between filesystems, so when it fails with a cross-device
+/// error the entry is copied (preserving permissions and modified times) and then removed.
+fn move_path(from: &Path, to: &Path, log: &mut Logger) -> Result<(), Error> {
+    if is_case_only_change(from, to) && fs::symlink_metadata(to).is_ok() && is_same_entry(from, to)?
+    {
+        return rename_case(from, to);
+    }
+
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(ref e) if is_cross_device(e) => {
+            log.info(&format!(
+                "{:?} is on a different filesystem, copying instead",
+                from
+            ));
+            copy_path(from, to)?;
+            if fs::symlink_metadata(from)?.is_dir() {
+                fs::remove_dir_all(from)?;
+            } else {
+                fs::remove_file(from)?;
+            }
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns true if `from` and `to` only differ in the case of their names, e.g. `foo.txt` and
+/// `FOO.TXT` in the same directory
+fn is_case_only_change(from: &Path, to: &Path) -> bool {
+    let (from_name, to_name) = match (from.file_name(), to.file_name()) {
+        (Some(from_name), Some(to_name)) => {
+            (from_name.to_string_lossy(), to_name.to_string_lossy())
+        }
+        _ => return false,
+    };
+    from.parent() == to.parent()
+        && from_name != to_name
+        && from_name.to_lowercase() == to_name.to_lowercase()
+}
+
+/// Renames an entry to a name only differing in case through a temporary name
+///
+/// Case-insensitive filesystems (e.g. NTFS or APFS) may refuse or ignore renaming `foo.txt`
+/// to `FOO.TXT`, as they see both names as the same entry. If the second step fails the
+/// entry is moved back to `from`.
+fn rename_case(from: &Path, to: &Path) -> Result<(), Error> {
+    let dir = from.parent().unwrap_or(Path::new("."));
+    let name = from.file_name().unwrap_or(OsStr::new("")).to_string_lossy();
+    let temp = (0..)
+        .map(|n| dir.join(format!(".{}.ram-utils-{}", name, n)))
+        .find(|temp| fs::symlink_metadata(temp).is_err())
+        .expect("no free temporary name");
+
+    fs::rename(from, &temp)?;
+    if let Err(e) = fs::rename(&temp, to) {
+        fs::rename(&temp, from)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Returns true if an error was caused by moving an entry to a different filesystem
+fn is_cross_device(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::CrossesDevices
+}
+
+/// Recursively copies a file, symlink or directory, preserving permissions and modified times
+fn copy_path(from: &Path, to: &Path) -> Result<(), Error> {
+    let metadata = fs::symlink_metadata(from)?;
+
+    if metadata.file_type().is_symlink() {
+        copy_symlink(from, to)?;
+        return Ok(());
+    }
+
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(from, to)?;
+    }
+
+    // The modified time is set first as the permissions may make the copy read-only
+    if metadata.is_file() {
+        fs::File::options()
+            .write(true)
+            .open(to)?
+            .set_modified(metadata.modified()?)?;
+    }
+    fs::set_permissions(to, metadata.permissions())?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(windows)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    if fs::metadata(from).map(|m| m.is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+/// Applies `transform` to a file name, normalizing it before and after if `options.normalize`
+/// is set
+///
+/// Normalizing first means names that only differ in their encoding (e.g. `café` stored as NFD
+/// by macOS and typed as NFC elsewhere) are converted to exactly the same name.
+fn transform_filename(filename: &str, transform: &Transform, options: &ConvertOptions) -> String {
+    match options.normalize {
+        Some(form) => {
+            let normalized = normalize(filename, form);
+            normalize(&apply_transform(&normalized, transform, options), form)
+        }
+        None => apply_transform(filename, transform, options),
+    }
+}
+
+/// Converts `name` to the Unicode normalization `form`
+pub fn normalize(name: &str, form: Normalization) -> String {
+    match form {
+        Normalization::Nfc => name.nfc().collect(),
+        Normalization::Nfd => name.nfd().collect(),
+    }
+}
+
+fn apply_transform(filename: &str, transform: &Transform, options: &ConvertOptions) -> String {
+    match transform {
+        Transform::Case(case) => match options.out_sep {
+            Some(ref out_sep) => {
+                let separators = options
+                    .word_sep
+                    .as_ref()
+                    .map_or(DEFAULT_WORD_SEPARATORS, |s| s);
+                let (stem, ext) = split_extension(filename);
+                let joined = format!("{}{}", split_words(stem, separators).join(out_sep), ext);
+                convert_case(&joined, case, &options.keep, options.locale)
+            }
+            None => convert_case(filename, case, &options.keep, options.locale),
+        },
+        Transform::StemExt { stem, ext } => {
+            let (stem_part, ext_part) = split_extension(filename);
+            let convert = |part: &str, case: &Option<LetterCase>, keep: &[String]| match case {
+                Some(case) => convert_case(part, case, keep, options.locale),
+                None => part.to_string(),
+            };
+            format!(
+                "{}{}",
+                convert(stem_part, stem, &options.keep),
+                convert(ext_part, ext, &[])
+            )
+        }
+        Transform::Style(style) => join_words(filename, style, options),
+        Transform::Trim { before_ext } => trim_whitespace(filename, *before_ext),
+        Transform::StripDiacritics { placeholder } => strip_diacritics(filename, placeholder),
+        Transform::Normalize(form) => normalize(filename, *form),
+        Transform::Despace { separator, lower } => {
+            let despaced = despace(filename, *separator);
+            if *lower {
+                convert_case(
+                    &despaced,
+                    &LetterCase::LowerCase,
+                    &options.keep,
+                    options.locale,
+                )
+            } else {
+                despaced
+            }
+        }
+        Transform::FixDuplicateExt => fix_duplicate_ext(filename),
+        Transform::Sanitize { replacement } => sanitize(filename, replacement),
+        Transform::Replace {
+            pattern,
+            replacement,
+        } => pattern
+            .replace_all(filename, replacement.as_str())
+            .into_owned(),
+    }
+}
+
+/// Splits a file name into its stem and extension, including the `.`
+///
+/// E.g.
+/// `photo.tar.gz` => (`photo.tar`, `.gz`)
+/// `.bashrc` => (`.bashrc`, ``)
+fn split_extension(filename: &str) -> (&str, &str) {
+    match filename.rfind('.') {
+        Some(dot) if dot > 0 => filename.split_at(dot),
+        _ => (filename, ""),
+    }
+}
+
+/// Splits a name into words at any of the `separators` characters, dropping empty words
+///
+/// E.g. with separators `_-`: `the_quick--brown` => [`the`, `quick`, `brown`]
+fn split_words<'a>(name: &'a str, separators: &str) -> Vec<&'a str> {
+    name.split(|c| separators.contains(c))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Converts a name to the specified letter case, leaving any `keep` words as given
+///
+/// A word is a run of alphanumeric characters and is matched against `keep` as a whole,
+/// ignoring case. E.g. with `keep` of `["NASA"]`:
+/// `Nasa apollo mission.txt` => `NASA APOLLO MISSION.TXT`
+/// `nasa-Apollo_NASAL.txt` => `NASA-apollo_nasal.txt` (lower case)
+///
+/// Case mappings can change the length of a name, e.g. `straße` upper cases to `STRASSE`.
+fn convert_case(name: &str, case: &LetterCase, keep: &[String], locale: CaseLocale) -> String {
+    let convert = |s: &str| match case {
+        LetterCase::UpperCase => locale.to_uppercase(s),
+        LetterCase::LowerCase => locale.to_lowercase(s),
+    };
+
+    if keep.is_empty() {
+        return convert(name);
+    }
+
+    // Split the name into alternating runs of alphanumeric and other characters
+    let mut runs: Vec<String> = Vec::new();
+    let mut in_word = false;
+    for c in name.chars() {
+        if runs.is_empty() || c.is_alphanumeric() != in_word {
+            runs.push(String::new());
+            in_word = c.is_alphanumeric();
+        }
+        if let Some(run) = runs.last_mut() {
+            run.push(c);
+        }
+    }
+
+    let mut converted = String::with_capacity(name.len());
+    for run in runs {
+        match kept_word(&run, keep) {
+            Some(word) => converted.push_str(word),
+            None => converted.push_str(&convert(&run)),
+        }
+    }
+
+    converted
+}
+
+impl CaseLocale {
+    /// Upper cases `s`, e.g. `istanbul` => `İSTANBUL` in Turkish
+    fn to_uppercase(self, s: &str) -> String {
+        match self {
+            CaseLocale::Default => s.to_uppercase(),
+            // `ı` already upper cases to `I` by default
+            CaseLocale::Turkish => s.replace('i', "İ").to_uppercase(),
+        }
+    }
+
+    /// Lower cases `s`, e.g. `ISPARTA` => `ısparta` in Turkish
+    pub fn to_lowercase(self, s: &str) -> String {
+        match self {
+            CaseLocale::Default => s.to_lowercase(),
+            // By default `İ` lower cases to `i` followed by a combining dot
+            CaseLocale::Turkish => s.replace('I', "ı").replace('İ', "i").to_lowercase(),
+        }
+    }
+}
+
+/// Returns the `keep` word matching `word`, ignoring case
+fn kept_word<'a>(word: &str, keep: &'a [String]) -> Option<&'a String> {
+    keep.iter()
+        .find(|kept| kept.to_lowercase() == word.to_lowercase())
+}
+
+/// Converts a file stem to the words of a case `style`, e.g. `snake_case`
+///
+/// Words are split at the word separator characters and at camelCase boundaries, and the
+/// extension is lower cased. Kept words are left as given. E.g.
+/// `MyFileName.TXT` => `my_file_name.txt` (snake)
+/// `HTML parser-v2.rs` => `html-parser-v2.rs` (kebab)
+/// `my_file name.TXT` => `myFileName.txt` (camel)
+/// `my_file name.TXT` => `MyFileName.txt` (pascal)
+/// `my_file name.TXT` => `My File Name.txt` (title)
+fn join_words(filename: &str, style: &CaseStyle, options: &ConvertOptions) -> String {
+    let separators = options
+        .word_sep
+        .as_ref()
+        .map_or(DEFAULT_WORD_SEPARATORS, |s| s);
+    let (stem, ext) = split_extension(filename);
+
+    let mut words = Vec::new();
+    for word in split_words(stem, separators) {
+        match kept_word(word, &options.keep) {
+            Some(kept) => words.push(kept.clone()),
+            None => words.extend(
+                split_camel_case(word)
+                    .iter()
+                    .map(|w| options.locale.to_lowercase(w)),
+            ),
+        }
+    }
+
+    let capitalize = |word: &str| match kept_word(word, &options.keep) {
+        Some(kept) => kept.clone(),
+        None => {
+            let mut chars = word.chars();
+            chars.next().map_or(String::new(), |first| {
+                options.locale.to_uppercase(&first.to_string()) + chars.as_str()
+            })
+        }
+    };
+    let capitalized = |skip: usize| -> Vec<String> {
+        words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                if i < skip {
+                    word.clone()
+                } else {
+                    capitalize(word)
+                }
+            })
+            .collect()
+    };
+
+    let stem = match style {
+        CaseStyle::Snake => words.join("_"),
+        CaseStyle::Kebab => words.join("-"),
+        CaseStyle::Camel => capitalized(1).concat(),
+        CaseStyle::Pascal => capitalized(0).concat(),
+        CaseStyle::Title => capitalized(0).join(" "),
+    };
+    format!("{}{}", stem, options.locale.to_lowercase(ext))
+}
+
+/// Splits a word at camelCase boundaries, keeping acronyms together
+///
+/// E.g.
+/// `myFileName` => [`my`, `File`, `Name`]
+/// `HTMLParser2` => [`HTML`, `Parser2`]
+fn split_camel_case(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = 0;
+
+    for i in 1..chars.len() {
+        let (index, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next_is_lower = chars.get(i + 1).is_some_and(|n| n.1.is_lowercase());
+
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower));
+
+        if boundary {
+            words.push(&word[start..index]);
+            start = index;
+        }
+    }
+
+    if start < word.len() {
+        words.push(&word[start..]);
+    }
+
+    words
+}
+
+/// Trims leading/trailing whitespace and collapses internal runs of whitespace to a single space
+///
+/// E.g.
+/// `  my  file .txt` => `my file .txt`
+/// `  my  file .txt` => `my file.txt` (with `before_ext`)
+fn trim_whitespace(filename: &str, before_ext: bool) -> String {
+    let trimmed = filename.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    if before_ext {
+        let (stem, ext) = split_extension(&trimmed);
+        return format!("{}{}", stem.trim_end(), ext);
+    }
+
+    trimmed
+}
+
+/// Replaces each run of whitespace with `separator`, dropping it at the ends and before the
+/// extension
+///
+/// E.g.
+/// `My  Cool File .txt` => `My_Cool_File.txt`
+fn despace(filename: &str, separator: char) -> String {
+    trim_whitespace(filename, true).replace(' ', &separator.to_string())
+}
+
+/// Reduces a name to ASCII by removing accents, e.g. `café résumé.txt` => `cafe resume.txt`
+///
+/// Characters are decomposed (NFD) and their combining marks dropped. A few letters without a
+/// decomposition are spelled out, e.g. `ß` => `ss`, and each run of any other non-ASCII
+/// characters is replaced with `placeholder`. If nothing of the stem would be left, the name is
+/// returned unchanged.
+fn strip_diacritics(filename: &str, placeholder: &str) -> String {
+    let (stem, ext) = split_extension(filename);
+    let stripped_stem = strip_diacritics_part(stem, placeholder);
+
+    if stripped_stem.trim().is_empty() {
+        return filename.to_string();
+    }
+
+    stripped_stem + &strip_diacritics_part(ext, placeholder)
+}
+
+fn strip_diacritics_part(part: &str, placeholder: &str) -> String {
+    let mut stripped = String::new();
+    let mut in_replaced_run = false;
+
+    for c in part.nfd() {
+        if unicode_normalization::char::is_combining_mark(c) {
+            continue;
+        }
+
+        let ascii = match c {
+            c if c.is_ascii() => None,
+            'ß' => Some("ss"),
+            'æ' => Some("ae"),
+            'Æ' => Some("AE"),
+            'œ' => Some("oe"),
+            'Œ' => Some("OE"),
+            'ø' => Some("o"),
+            'Ø' => Some("O"),
+            'đ' => Some("d"),
+            'Đ' => Some("D"),
+            'ł' => Some("l"),
+            'Ł' => Some("L"),
+            'þ' => Some("th"),
+            'Þ' => Some("Th"),
+            _ => {
+                if !in_replaced_run {
+                    stripped.push_str(placeholder);
+                    in_replaced_run = true;
+                }
+                continue;
+            }
+        };
+
+        in_replaced_run = false;
+        match ascii {
+            Some(ascii) => stripped.push_str(ascii),
+            None => stripped.push(c),
+        }
+    }
+
+    stripped
+}
+
+/// Collapses an extension repeated at the end of a name, ignoring case, into a single lower
+/// cased one
+///
+/// E.g.
+/// `report.pdf.pdf` => `report.pdf`
+/// `photo.JPG.jpg.JPG` => `photo.jpg`
+/// `archive.tar.gz` => `archive.tar.gz`
+fn fix_duplicate_ext(filename: &str) -> String {
+    let (mut stem, ext) = split_extension(filename);
+    let mut repeated = false;
+
+    loop {
+        let (inner_stem, inner_ext) = split_extension(stem);
+        if inner_ext.is_empty() || !inner_ext.eq_ignore_ascii_case(ext) {
+            break;
+        }
+        stem = inner_stem;
+        repeated = true;
+    }
+
+    if !repeated {
+        return filename.to_string();
+    }
+
+    format!("{}{}", stem, ext.to_lowercase())
+}
+
+/// Characters that can't be used in a name on Windows, besides control characters
+const INVALID_NAME_CHARS: &str = "<>:\"/\\|?*";
+
+/// Makes a name portable, e.g. `what? <draft>.txt ` => `what_ _draft_.txt`
+///
+/// Each of `<>:"/\|?*` and each control character is replaced with `replacement`, which may be
+/// empty to remove them, and trailing spaces and dots are trimmed. If nothing would be left, the
+/// name is returned unchanged.
+fn sanitize(filename: &str, replacement: &str) -> String {
+    let mut sanitized = String::with_capacity(filename.len());
+
+    for c in filename.chars() {
+        if c.is_control() || INVALID_NAME_CHARS.contains(c) {
+            sanitized.push_str(replacement);
+        } else {
+            sanitized.push(c);
+        }
+    }
+
+    let trimmed = sanitized.trim_end_matches([' ', '.']);
+    if trimmed.is_empty() {
+        return filename.to_string();
+    }
+
+    trimmed.to_string()
+}
+
+/// Writes a `--backup` manifest line, `new_path<TAB>original_path`
+pub fn write_manifest_entry<W: Write>(out: &mut W, new: &Path, original: &Path) -> io::Result<()> {
+    writeln!(out, "{}\t{}", new.display(), original.display())
+}
+
+/// Reads the `(new_path, original_path)` pairs of a `--backup` manifest, in the order written
+pub fn read_manifest<R: BufRead>(reader: R) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let mut entries = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.split_once('\t') {
+            Some((new, original)) => entries.push((PathBuf::from(new), PathBuf::from(original))),
+            None => {
+                return Err(err_msg(format!(
+                    "Invalid manifest line {}: {}",
+                    i + 1,
+                    line
+                )))
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Renames each new path of a manifest back to its original path
+///
+/// The renames are undone in reverse, so a directory gets its old name back before the entries
+/// inside it, whose recorded paths use that old name. Entries whose current name no longer
+/// matches the recorded one, e.g. because they were renamed again since, are skipped.
+pub fn restore_names(
+    entries: &[(PathBuf, PathBuf)],
+    dry_run: bool,
+    on_conflict: ConflictPolicy,
+    log: &mut Logger,
+) {
+    for (new, original) in entries.iter().rev() {
+        if !has_exact_name(new) {
+            log.summary.skipped += 1;
+            log.warn(&format!(
+                "Skipping {:?}, no entry has that name anymore",
+                new
+            ));
+            continue;
+        }
+
+        if dry_run {
+            log.info(&format!("Restoring {:?} => {:?}", new, original));
+        } else if let Err(e) = apply_rename(new, original, on_conflict, log) {
+            log.error(&format!("Error: {}", e));
+            if on_conflict.stops_on(&e) {
+                break;
+            }
+        }
+    }
+}
+
+/// Reads a plan written by `--plan-json`
+pub fn read_plan<R: Read>(reader: R) -> Result<Vec<PlanEntry>, Error> {
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Returns the renames of a plan that can still be applied, deepest `from` first
+///
+/// Entries whose `from` no longer exists with that exact name and kind, e.g. because it was
+/// renamed since the plan was made, or whose paths weren't valid UTF-8 are skipped with a
+/// warning. Deepest first means the entries inside a directory are renamed before it is.
+pub fn checked_plan(entries: Vec<PlanEntry>, log: &mut Logger) -> Vec<(PathBuf, PathBuf)> {
+    let mut plan = Vec::new();
+
+    for entry in entries {
+        let from = PathBuf::from(&entry.from);
+        let kind = fs::symlink_metadata(&from).map(|metadata| entry_kind(&metadata.file_type()));
+
+        let problem = if !entry.valid_utf8 {
+            Some("its path isn't valid UTF-8")
+        } else if kind.is_err() || !has_exact_name(&from) {
+            Some("no entry has that name anymore")
+        } else if kind.ok() != Some(entry.kind.as_str()) {
+            Some("it's no longer the same kind of entry")
+        } else {
+            None
+        };
+
+        match problem {
+            Some(problem) => {
+                log.summary.skipped += 1;
+                log.warn(&format!("Skipping {:?}, {}", from, problem));
+            }
+            None => plan.push((from, PathBuf::from(entry.to))),
+        }
+    }
+
+    // The sort is stable, so entries at the same depth keep their planned order
+    plan.sort_by_key(|(from, _)| std::cmp::Reverse(from.components().count()));
+    plan
+}
+
+/// A rename read from a `rename-from` mapping file
+#[derive(Debug, PartialEq)]
+pub struct MappingRow {
+    /// Line of the row in the file, for reporting
+    pub line: u64,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Reads the `(old path, new path)` rows of a CSV or TSV mapping file, in order
+///
+/// Columns after the first two are ignored and a first row of `from`, `to` is taken as a
+/// header, so the CSV written by `--format csv` can be read back.
+pub fn read_mapping<R: Read>(reader: R, delimiter: u8) -> Result<Vec<MappingRow>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(reader);
+    let mut rows = Vec::new();
+
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = record
+            .position()
+            .map_or(i as u64 + 1, |position| position.line());
+
+        match (record.get(0), record.get(1)) {
+            (Some("from"), Some("to")) if i == 0 => continue,
+            (Some(from), Some(to)) if !from.is_empty() && !to.is_empty() => rows.push(MappingRow {
+                line,
+                from: PathBuf::from(from),
+                to: PathBuf::from(to),
+            }),
+            _ => {
+                return Err(err_msg(format!(
+                    "Invalid mapping on line {}, expected an old and a new path",
+                    line
+                )))
+            }
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Returns a message for each row of a mapping that can't be applied in order
+///
+/// A source must exist, or be the target of an earlier row, and no two rows may have the same
+/// target. Under `ConflictPolicy::Error` and `Fail` a target also mustn't exist, unless it's
+/// the source of an earlier row or the same entry as its own source, e.g. a case-only change.
+pub fn check_mapping(rows: &[MappingRow], on_conflict: ConflictPolicy) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut sources = HashSet::new();
+    let mut targets = HashMap::new();
+
+    for row in rows {
+        if !targets.contains_key(&row.from) && fs::symlink_metadata(&row.from).is_err() {
+            problems.push(format!("Line {}: {:?} does not exist", row.line, row.from));
+        }
+
+        if let Some(line) = targets.get(&row.to) {
+            problems.push(format!(
+                "Line {}: {:?} is already the target of line {}",
+                row.line, row.to, line
+            ));
+        } else if matches!(on_conflict, ConflictPolicy::Error | ConflictPolicy::Fail)
+            && !sources.contains(&row.to)
+            && fs::symlink_metadata(&row.to).is_ok()
+            && !is_same_entry(&row.from, &row.to).unwrap_or(false)
+        {
+            problems.push(format!("Line {}: {:?} already exists", row.line, row.to));
+        }
+
+        sources.insert(&row.from);
+        targets.entry(&row.to).or_insert(row.line);
+    }
+
+    problems
+}
+
+/// Returns true if an entry exists at `path` with exactly that name
+///
+/// On case-insensitive filesystems `FOO.TXT` also finds `foo.txt`, so the names in the parent
+/// directory are compared instead.
+pub fn has_exact_name(path: &Path) -> bool {
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name),
+        _ => return false,
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+
+    match fs::read_dir(dir) {
+        Ok(mut entries) => entries.any(|entry| entry.is_ok_and(|entry| entry.file_name() == name)),
+        Err(_) => false,
+    }
+}
+
+/// Logs the renames of a dry run, followed by how many entries would be renamed or skipped
+///
+/// With a `preview` limit only the first renames are listed, then e.g. `... and 199990 more`.
+pub fn log_plan(plan: &[(PathBuf, PathBuf)], preview: Option<usize>, log: &mut Logger) {
+    let shown = preview.unwrap_or(plan.len()).min(plan.len());
+
+    for (from, to) in &plan[..shown] {
+        let message = format!("Converting {} => {}", log.path(from), log.path(to));
+        log.info(&message);
+    }
+    if shown < plan.len() {
+        log.info(&format!("... and {} more", plan.len() - shown));
+    }
+
+    log.info(&format!(
+        "Would rename {}; skipped {}",
+        entries(plan.len()),
+        log.summary.skipped
+    ));
+}
+
+/// Parses a `template` rename pattern, rejecting unknown placeholders up front
+///
+/// E.g. `{year}-{month}-{name}.{ext}` => `[Year, "-", Month, "-", Name, ".", Ext]`
+///
+/// The counter may be given a width to zero pad it to, e.g. `{n:03}` => `001`.
+pub fn parse_template(pattern: &str) -> Result<Vec<TemplatePart>, Error> {
+    if pattern.contains('/') || pattern.contains(std::path::MAIN_SEPARATOR) {
+        return Err(err_msg("Template must not contain path separators"));
+    }
+
+    let mut parts = Vec::new();
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TemplatePart::Literal(rest[..start].to_string()));
+        }
+
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(err_msg(format!("Unclosed `{{` in template: {}", pattern))),
+        };
+
+        let placeholder = match &rest[start + 1..end] {
+            "name" => Placeholder::Name,
+            "ext" => Placeholder::Ext,
+            "year" => Placeholder::Year,
+            "month" => Placeholder::Month,
+            "day" => Placeholder::Day,
+            "date" => Placeholder::Date,
+            "time" => Placeholder::Time,
+            "size" => Placeholder::Size,
+            "model" => Placeholder::Model,
+            "parent" => Placeholder::Parent,
+            "n" => Placeholder::Counter { width: 0 },
+            other if other.starts_with("n:") => match other[2..].parse() {
+                Ok(width) => Placeholder::Counter { width },
+                Err(_) => return Err(err_msg(format!("Invalid counter width `{{{}}}`", other))),
+            },
+            other => return Err(err_msg(format!("Unknown placeholder `{{{}}}`", other))),
+        };
+        parts.push(TemplatePart::Placeholder(placeholder));
+        rest = &rest[end + 1..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Literal(rest.to_string()));
+    }
+
+    Ok(parts)
+}
+
+impl TemplateValues {
+    /// Reads the values for the file at `path`, dated by its (local) modified time
+    ///
+    /// The modified time is used rather than the creation time since the latter isn't available
+    /// on every platform and is reset when files are copied.
+    pub fn from_path(path: &Path) -> Result<TemplateValues, Error> {
+        let metadata = fs::metadata(path)?;
+        let modified: DateTime<Local> = metadata.modified()?.into();
+        let filename = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| err_msg(format!("Invalid file name: {:?}", path)))?;
+        let (name, ext) = split_extension(filename);
+        let parent = fs::canonicalize(path)?
+            .parent()
+            .and_then(Path::file_name)
+            .map(|parent| parent.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(TemplateValues {
+            name: name.to_string(),
+            ext: ext.trim_start_matches('.').to_string(),
+            year: modified.year(),
+            month: modified.month(),
+            day: modified.day(),
+            hour: modified.hour(),
+            minute: modified.minute(),
+            second: modified.second(),
+            size: metadata.len(),
+            parent,
+            model: String::new(),
+        })
+    }
+
+    /// Reads the values for the photo at `path`, dated by when it was taken according to its
+    /// EXIF data, or by its modified time if it has none
+    pub fn from_photo(path: &Path) -> Result<TemplateValues, Error> {
+        let mut values = TemplateValues::from_path(path)?;
+        let info = read_photo_info(path)?;
+
+        if let Some(taken) = info.taken {
+            values.year = taken.year();
+            values.month = taken.month();
+            values.day = taken.day();
+            values.hour = taken.hour();
+            values.minute = taken.minute();
+            values.second = taken.second();
+        }
+        if let Some(model) = info.model {
+            values.model = model.replace(['/', path::MAIN_SEPARATOR], "_");
+        }
+
+        Ok(values)
+    }
+}
+
+/// Returns true if `path` has one of the `PHOTO_EXTENSIONS`, ignoring case
+pub fn is_photo(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| PHOTO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Reads the date taken and camera model from the EXIF data of a JPEG or TIFF based raw file
+///
+/// Only the first `EXIF_READ_BYTES` are read. A file without (readable) EXIF data gives an
+/// empty `PhotoInfo` rather than an error.
+pub fn read_photo_info(path: &Path) -> Result<PhotoInfo, Error> {
+    let mut data = Vec::new();
+    fs::File::open(path)?
+        .take(EXIF_READ_BYTES)
+        .read_to_end(&mut data)?;
+
+    Ok(exif_tiff(&data).map(read_exif).unwrap_or_default())
+}
+
+/// Returns the TIFF structure holding the EXIF data of a file: the payload of a JPEG's
+/// `Exif` APP1 segment, or the whole file for TIFF based formats
+fn exif_tiff(data: &[u8]) -> Option<&[u8]> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        // Olympus and Panasonic raws use their own magic numbers in an otherwise TIFF header
+        let tiff = [&b"II*\0"[..], b"MM\0*", b"IIRO", b"IIRS", b"IIU\0"];
+        return tiff
+            .iter()
+            .any(|magic| data.starts_with(magic))
+            .then_some(data);
+    }
+
+    let mut i = 2;
+    while i + 4 <= data.len() && data[i] == 0xFF {
+        let marker = data[i + 1];
+        // Start of scan, the image data follows
+        if marker == 0xDA {
+            break;
+        }
+
+        let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        let segment = &data[i + 4..(i + 2 + length).min(data.len())];
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+        i += 2 + length;
+    }
+
+    None
+}
+
+/// Reads the camera model from IFD0 and the date taken from the EXIF IFD of a TIFF structure
+fn read_exif(tiff: &[u8]) -> PhotoInfo {
+    const MODEL: u16 = 0x0110;
+    const EXIF_IFD: u16 = 0x8769;
+    const DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+    let mut info = PhotoInfo::default();
+    let little_endian = match tiff.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return info,
+    };
+    let number = |offset: usize, size: usize| -> Option<usize> {
+        let bytes = tiff.get(offset..offset + size)?;
+        let fold = |n: usize, b: &u8| n << 8 | *b as usize;
+        Some(if little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    };
+    // The entries of an IFD as `(tag, count, offset of the value field)`
+    let entries = |ifd: usize| -> Vec<(usize, usize, usize)> {
+        let count = number(ifd, 2).unwrap_or(0);
+        (0..count)
+            .map(|i| ifd + 2 + i * 12)
+            .filter_map(|entry| Some((number(entry, 2)?, number(entry + 4, 4)?, entry + 8)))
+            .collect()
+    };
+    // ASCII values of up to 4 bytes are stored in the value field itself
+    let ascii = |count: usize, field: usize| -> Option<String> {
+        let offset = if count > 4 { number(field, 4)? } else { field };
+        let bytes = tiff.get(offset..offset + count)?;
+        let text = String::from_utf8_lossy(bytes.split(|b| *b == 0).next()?);
+        Some(text.trim().to_string()).filter(|text| !text.is_empty())
+    };
+
+    let ifd0 = match number(4, 4) {
+        Some(ifd0) => ifd0,
+        None => return info,
+    };
+    for (tag, count, field) in entries(ifd0) {
+        if tag == MODEL as usize {
+            info.model = ascii(count, field);
+        } else if tag == EXIF_IFD as usize {
+            let exif_ifd = number(field, 4).unwrap_or(0);
+            for (tag, count, field) in entries(exif_ifd) {
+                if tag == DATE_TIME_ORIGINAL as usize {
+                    info.taken = ascii(count, field).and_then(|taken| {
+                        NaiveDateTime::parse_from_str(&taken, "%Y:%m:%d %H:%M:%S").ok()
+                    });
+                }
+            }
+        }
+    }
+
+    info
+}
+
+/// Expands a parsed template with the values of a single file, the `n`th one renamed
+///
+/// Months and days are zero padded. If the file has no extension, a `.` directly before
+/// `{ext}` is dropped so `{name}.{ext}` doesn't leave a trailing dot.
+fn render_template(template: &[TemplatePart], values: &TemplateValues, n: usize) -> String {
+    let mut filename = String::new();
+
+    for part in template {
+        match part {
+            TemplatePart::Literal(text) => filename.push_str(text),
+            TemplatePart::Placeholder(Placeholder::Name) => filename.push_str(&values.name),
+            TemplatePart::Placeholder(Placeholder::Ext) => {
+                if values.ext.is_empty() {
+                    if filename.ends_with('.') {
+                        filename.pop();
+                    }
+                } else {
+                    filename.push_str(&values.ext);
+                }
+            }
+            TemplatePart::Placeholder(Placeholder::Year) => {
+                filename.push_str(&format!("{:04}", values.year))
+            }
+            TemplatePart::Placeholder(Placeholder::Month) => {
+                filename.push_str(&format!("{:02}", values.month))
+            }
+            TemplatePart::Placeholder(Placeholder::Day) => {
+                filename.push_str(&format!("{:02}", values.day))
+            }
+            TemplatePart::Placeholder(Placeholder::Date) => filename.push_str(&format!(
+                "{:04}-{:02}-{:02}",
+                values.year, values.month, values.day
+            )),
+            TemplatePart::Placeholder(Placeholder::Time) => filename.push_str(&format!(
+                "{:02}{:02}{:02}",
+                values.hour, values.minute, values.second
+            )),
+            TemplatePart::Placeholder(Placeholder::Size) => {
+                filename.push_str(&values.size.to_string())
+            }
+            TemplatePart::Placeholder(Placeholder::Model) => filename.push_str(&values.model),
+            TemplatePart::Placeholder(Placeholder::Parent) => filename.push_str(&values.parent),
+            TemplatePart::Placeholder(Placeholder::Counter { width }) => {
+                filename.push_str(&format!("{:0width$}", n, width = width))
+            }
+        }
+    }
+
+    filename
+}
+
+/// Returns the files renamed by `template`: `path` itself, or the files in it (sorted)
+pub fn template_files(
+    path: &Path,
+    recursive: bool,
+    options: &WalkOptions,
+) -> Result<Vec<PathBuf>, Error> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    if recursive {
+        walk(path, options, &mut |entry_path, file_type| {
+            if file_type.is_file() {
+                files.push(entry_path.to_path_buf());
+            }
+            Ok(())
+        })?;
+    } else {
+        let filter = EntryFilter::default().for_dir(path, options);
+        for entry in read_dir_sorted(path)? {
+            let entry_path = entry.path();
+            if entry.file_type()?.is_file() && !filter.is_excluded(&entry_path, false, options) {
+                files.push(entry_path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Plans the renames of `files` to their expanded template names
+///
+/// `values` provides the placeholder values of each file, and the counter is the position of
+/// each file in `files`, so it stays the same for a file that is skipped. Files whose name is
+/// unchanged are skipped.
+///
+/// Under `ConflictPolicy::Number` (`--on-conflict number` or `suffix`), names colliding with an
+/// existing entry or an earlier file's new name are numbered here, e.g. `2023-05.jpg`,
+/// `2023-05_1.jpg`. Under the other policies colliding names are planned as they are, and the
+/// policy is applied by `apply_rename` when the file is renamed.
+pub fn template_plan<F>(
+    files: &[PathBuf],
+    template: &[TemplatePart],
+    mut values: F,
+    on_conflict: ConflictPolicy,
+    log: &mut Logger,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error>
+where
+    F: FnMut(&Path) -> Result<TemplateValues, Error>,
+{
+    let mut claimed = HashSet::new();
+    let mut plan = Vec::new();
+
+    for (i, file) in files.iter().enumerate() {
+        let filename = render_template(template, &values(file)?, i + 1);
+        if filename.is_empty() {
+            return Err(err_msg(format!(
+                "Template produced an empty name for {:?}",
+                file
+            )));
+        }
+
+        let dir = file.parent().unwrap_or(Path::new("."));
+        if dir.join(&filename) == *file {
+            claimed.insert(file.clone());
+            log.summary.skipped += 1;
+            log.skip(&format!("Skipping {:?}", file));
+            continue;
+        }
+
+        // Other policies are applied by `apply_rename`, as the plan is carried out
+        let target = if on_conflict == ConflictPolicy::Number {
+            unique_path(dir, Path::new(&filename), &claimed)
+        } else {
+            dir.join(&filename)
+        };
+        claimed.insert(target.clone());
+        plan.push((file.clone(), target));
+    }
+
+    Ok(plan)
+}
+
+/// Writes one line per extension with the file counts and share of all files aligned in
+/// columns, followed by the total
+///
+/// The output is line based, so an extension containing a newline is split across lines.
+///
+/// E.g.
+/// `jpg  320 files (98.5%)`
+/// `txt    5 files ( 1.5%)`
+/// `Total: 325 files`
+pub fn write_extensions<W: WriteColor>(
+    out: &mut W,
+    extensions: &HashMap<String, u32>,
+    sort: &ExtensionSort,
+    sizes: Option<&ExtensionSizes>,
+) -> io::Result<()> {
+    let exts = sorted_extensions(extensions, sort);
+
+    let ext_width = exts.iter().map(|e| e.0.chars().count()).max().unwrap_or(0);
+    let count_width = exts
+        .iter()
+        .map(|e| e.1.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let total: u32 = exts.iter().map(|e| e.1).sum();
+    let percent_width = exts
+        .iter()
+        .map(|e| format!("{:.1}", percentage(*e.1, total)).len())
+        .max()
+        .unwrap_or(0);
+    let size_width = exts
+        .iter()
+        .filter_map(|e| Some(sizes?.format(sizes?.size(e.0)).len()))
+        .max()
+        .unwrap_or(0);
+
+    for (ext, count) in &exts {
+        out.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+        write!(out, "{:<width$}", ext, width = ext_width)?;
+        out.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+        write!(out, " {:>width$}", count, width = count_width)?;
+        out.reset()?;
+        write!(
+            out,
+            " files ({:>width$.1}%",
+            percentage(**count, total),
+            width = percent_width
+        )?;
+        if let Some(sizes) = sizes {
+            let size = sizes.format(sizes.size(ext));
+            write!(out, ", {:>width$}", size, width = size_width)?;
+        }
+        writeln!(out, ")")?;
+    }
+
+    match sizes {
+        Some(sizes) => {
+            let total_size = exts.iter().map(|e| sizes.size(e.0)).sum();
+            writeln!(out, "Total: {} files, {}", total, sizes.format(total_size))?;
+        }
+        None => writeln!(out, "Total: {} files", total)?,
+    }
+
+    Ok(())
+}
+
+/// Writes the extensions of each directory as `write_extensions` does under a `dir:` heading,
+/// followed by the `combined` extensions of all directories under `All:`
+pub fn write_extensions_by_dir<W: WriteColor>(
+    out: &mut W,
+    groups: &[DirExtensions],
+    combined: &HashMap<String, u32>,
+    sort: &ExtensionSort,
+) -> io::Result<()> {
+    for group in groups {
+        writeln!(out, "{}:", group.dir.display())?;
+        write_extensions(out, &group.extensions, sort, None)?;
+        writeln!(out)?;
+    }
+
+    writeln!(out, "All:")?;
+    write_extensions(out, combined, sort, None)
+}
+
+/// Writes a `directory,extension,count` header followed by one row per extension of each
+/// directory
+pub fn write_extensions_by_dir_csv<W: Write>(
+    out: &mut W,
+    groups: &[DirExtensions],
+    sort: &ExtensionSort,
+) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["directory", "extension", "count"])?;
+    for group in groups {
+        let dir = group.dir.to_string_lossy();
+        for (ext, count) in sorted_extensions(&group.extensions, sort) {
+            writer.write_record([dir.as_ref(), ext.as_str(), &count.to_string()])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the files of each extension under an `ext (n files):` heading, with the extensions
+/// in the order of `sort` as counted by their number of files
+pub fn write_extension_files<W: Write>(
+    out: &mut W,
+    files: &HashMap<String, Vec<PathBuf>>,
+    sort: &ExtensionSort,
+) -> io::Result<()> {
+    let counts = extension_file_counts(files);
+    let exts = sorted_extensions(&counts, sort);
+
+    for (i, (ext, count)) in exts.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+
+        writeln!(out, "{} ({} files):", ext, count)?;
+        for path in &files[*ext] {
+            writeln!(out, "  {}", path.display())?;
+        }
+    }
+
+    let total: u32 = counts.values().sum();
+    if !exts.is_empty() {
+        writeln!(out)?;
+    }
+    writeln!(out, "Total: {} files", total)?;
+
+    Ok(())
+}
+
+/// A row of `unique_ext --show-files --format json`
+#[derive(Serialize)]
+struct ExtensionFile<'a> {
+    extension: &'a str,
+    path: String,
+}
+
+/// Writes the files of each extension as a JSON array of `{"extension", "path"}` objects, in
+/// the order of `write_extension_files`
+pub fn write_extension_files_json<W: Write>(
+    out: &mut W,
+    files: &HashMap<String, Vec<PathBuf>>,
+    sort: &ExtensionSort,
+) -> Result<(), Error> {
+    let counts = extension_file_counts(files);
+    let mut rows = Vec::new();
+    for (ext, _) in sorted_extensions(&counts, sort) {
+        for path in &files[ext] {
+            rows.push(ExtensionFile {
+                extension: ext,
+                path: path.to_string_lossy().into_owned(),
+            });
+        }
+    }
+    serde_json::to_writer_pretty(&mut *out, &rows)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Writes an `extension,path` header followed by one row per file, in the order of
+/// `write_extension_files`
+pub fn write_extension_files_csv<W: Write>(
+    out: &mut W,
+    files: &HashMap<String, Vec<PathBuf>>,
+    sort: &ExtensionSort,
+) -> Result<(), Error> {
+    let counts = extension_file_counts(files);
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["extension", "path"])?;
+    for (ext, _) in sorted_extensions(&counts, sort) {
+        for path in &files[ext] {
+            writer.write_record([ext.as_str(), &path.to_string_lossy()])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn extension_file_counts(files: &HashMap<String, Vec<PathBuf>>) -> HashMap<String, u32> {
+    files
+        .iter()
+        .map(|(ext, paths)| (ext.clone(), paths.len() as u32))
+        .collect()
+}
+
+/// A row of `unique_ext --format json`
+#[derive(Serialize)]
+struct ExtensionCount<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    directory: Option<String>,
+    extension: &'a str,
+    count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+}
+
+/// Writes the extensions as a JSON array of `{"extension", "count"}` objects, also with
+/// `"bytes"` if given the `sizes`
+pub fn write_extensions_json<W: Write>(
+    out: &mut W,
+    extensions: &HashMap<String, u32>,
+    sort: &ExtensionSort,
+    sizes: Option<&ExtensionSizes>,
+) -> Result<(), Error> {
+    let rows: Vec<ExtensionCount> = sorted_extensions(extensions, sort)
+        .into_iter()
+        .map(|(ext, count)| ExtensionCount {
+            directory: None,
+            extension: ext,
+            count: *count,
+            bytes: sizes.map(|sizes| sizes.size(ext)),
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *out, &rows)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Writes the extensions of each directory as a JSON array of
+/// `{"directory", "extension", "count"}` objects, like `write_extensions_by_dir_csv`
+pub fn write_extensions_by_dir_json<W: Write>(
+    out: &mut W,
+    groups: &[DirExtensions],
+    sort: &ExtensionSort,
+) -> Result<(), Error> {
+    let mut rows = Vec::new();
+    for group in groups {
+        for (ext, count) in sorted_extensions(&group.extensions, sort) {
+            rows.push(ExtensionCount {
+                directory: Some(group.dir.to_string_lossy().into_owned()),
+                extension: ext,
+                count: *count,
+                bytes: None,
+            });
+        }
+    }
+    serde_json::to_writer_pretty(&mut *out, &rows)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Writes an `extension,count` header followed by one row per extension, with a `bytes`
+/// column if given the `sizes`
+///
+/// Extensions containing a comma or quote are quoted as usual for CSV.
+pub fn write_extensions_csv<W: Write>(
+    out: &mut W,
+    extensions: &HashMap<String, u32>,
+    sort: &ExtensionSort,
+    sizes: Option<&ExtensionSizes>,
+) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    match sizes {
+        Some(_) => writer.write_record(["extension", "count", "bytes"])?,
+        None => writer.write_record(["extension", "count"])?,
+    }
+    for (ext, count) in sorted_extensions(extensions, sort) {
+        match sizes {
+            Some(sizes) => writer.write_record([
+                ext.as_str(),
+                &count.to_string(),
+                &sizes.size(ext).to_string(),
+            ])?,
+            None => writer.write_record([ext.as_str(), &count.to_string()])?,
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Sorts the extensions for listing, with the `(other)` rollup of `--roll-rare` always last
+fn sorted_extensions<'a>(
+    extensions: &'a HashMap<String, u32>,
+    sort: &ExtensionSort,
+) -> Vec<(&'a String, &'a u32)> {
+    let mut exts: Vec<(&String, &u32)> = extensions.iter().collect();
+    sort_extensions(&mut exts, sort);
+    exts.sort_by_key(|e| e.0 == RARE_EXTENSIONS);
+    exts
+}
+
+fn sort_extensions(exts: &mut [(&String, &u32)], sort: &ExtensionSort) {
+    match sort {
+        ExtensionSort::Name => exts.sort(),
+        ExtensionSort::Count => exts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0))),
+        ExtensionSort::Size(sizes) => {
+            let size = |ext: &String| sizes.get(ext).copied().unwrap_or(0);
+            exts.sort_by(|a, b| size(b.0).cmp(&size(a.0)).then(a.0.cmp(b.0)))
+        }
+        ExtensionSort::Reversed(sort) => {
+            sort_extensions(exts, sort);
+            exts.reverse();
+        }
+    }
+}
+
+/// Returns the extensions found in at least `min_count` files, for listing
+///
+/// With `roll_rare` the others are counted together as `(other)`, so the total still covers
+/// every file.
+pub fn drop_rare_extensions(
+    extensions: &HashMap<String, u32>,
+    min_count: u32,
+    roll_rare: bool,
+) -> HashMap<String, u32> {
+    let (mut common, rare): (HashMap<String, u32>, HashMap<String, u32>) = extensions
+        .iter()
+        .map(|(ext, count)| (ext.clone(), *count))
+        .partition(|(_, count)| *count >= min_count);
+
+    if roll_rare && !rare.is_empty() {
+        common.insert(RARE_EXTENSIONS.to_string(), rare.values().sum());
+    }
+
+    common
+}
+
+/// Folds extensions differing only in case into their lower case spelling, e.g. the counts of
+/// `JPG`, `Jpg` and `jpg` are added up under `jpg`, using `merge` to add up the values
+pub fn fold_extension_case<V, F>(extensions: HashMap<String, V>, mut merge: F) -> HashMap<String, V>
+where
+    F: FnMut(&mut V, V),
+{
+    let mut folded: HashMap<String, V> = HashMap::new();
+    for (ext, value) in extensions {
+        match folded.entry(ext.to_lowercase()) {
+            Entry::Occupied(entry) => merge(entry.into_mut(), value),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
+    folded
+}
+
+/// Returns the spellings of the extensions found in more than one case, sorted, by their lower
+/// case spelling, e.g. `jpg` => `[JPG, Jpg, jpg]`
+pub fn extension_variants<V>(extensions: &HashMap<String, V>) -> BTreeMap<String, Vec<String>> {
+    let mut variants: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for ext in extensions.keys() {
+        variants
+            .entry(ext.to_lowercase())
+            .or_default()
+            .push(ext.clone());
+    }
+    variants.retain(|_, spellings| spellings.len() > 1);
+    for spellings in variants.values_mut() {
+        spellings.sort();
+    }
+    variants
+}
+
+/// Writes the `variants` found by `extension_variants` under a `Variants:` heading, e.g.
+/// `jpg: JPG, Jpg, jpg`, or nothing if there are none
+pub fn write_extension_variants<W: Write>(
+    out: &mut W,
+    variants: &BTreeMap<String, Vec<String>>,
+) -> io::Result<()> {
+    if variants.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "\nVariants:")?;
+    for (ext, spellings) in variants {
+        writeln!(out, "  {}: {}", ext, spellings.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Returns the sizes of the `extensions` kept by `drop_rare_extensions`, with the sizes of the
+/// others added up under `(other)` if it rolled them up
+pub fn drop_rare_sizes(
+    sizes: &HashMap<String, u64>,
+    extensions: &HashMap<String, u32>,
+) -> HashMap<String, u64> {
+    let (mut kept, rare): (HashMap<String, u64>, HashMap<String, u64>) = sizes
+        .iter()
+        .map(|(ext, size)| (ext.clone(), *size))
+        .partition(|(ext, _)| extensions.contains_key(ext));
+
+    if extensions.contains_key(RARE_EXTENSIONS) {
+        kept.insert(RARE_EXTENSIONS.to_string(), rare.values().sum());
+    }
+
+    kept
+}
+
+/// Groups extension counts into categories, using `DEFAULT_CATEGORIES` updated by `overrides`
+///
+/// Extensions are matched case-insensitively and those in no category are put in
+/// `OTHER_CATEGORY`. Categories are sorted by their total, largest first, with `other` last.
+pub fn categorize(
+    extensions: &HashMap<String, u32>,
+    overrides: Option<&HashMap<String, Vec<String>>>,
+) -> Vec<Category> {
+    let mut category_of: HashMap<String, &str> = HashMap::new();
+    for (category, exts) in DEFAULT_CATEGORIES {
+        for ext in exts.iter() {
+            category_of.insert(ext.to_string(), category);
+        }
+    }
+    for (category, exts) in overrides.into_iter().flatten() {
+        for ext in exts {
+            category_of.insert(ext.trim_start_matches('.').to_lowercase(), category);
+        }
+    }
+
+    let mut by_category: HashMap<&str, Vec<(String, u32)>> = HashMap::new();
+    for (ext, count) in extensions {
+        let category = category_of
+            .get(&ext.to_lowercase())
+            .copied()
+            .unwrap_or(OTHER_CATEGORY);
+        by_category
+            .entry(category)
+            .or_default()
+            .push((ext.clone(), *count));
+    }
+
+    let mut categories: Vec<Category> = by_category
+        .into_iter()
+        .map(|(name, mut extensions)| {
+            extensions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            Category {
+                name: name.to_string(),
+                total: extensions.iter().map(|e| e.1).sum(),
+                extensions,
+            }
+        })
+        .collect();
+    categories.sort_by(|a, b| {
+        (a.name == OTHER_CATEGORY)
+            .cmp(&(b.name == OTHER_CATEGORY))
+            .then(b.total.cmp(&a.total))
+            .then(a.name.cmp(&b.name))
+    });
+
+    categories
+}
+
+/// Writes each category with its total, followed by its indented extensions and their counts
+///
+/// E.g.
+/// `images  325 files`
+/// `  jpg   320`
+/// `  png     5`
+/// `other     3 files`
+/// `  xyz     3`
+pub fn write_categories<W: WriteColor>(out: &mut W, categories: &[Category]) -> io::Result<()> {
+    let name_width = categories
+        .iter()
+        .flat_map(|c| {
+            let exts = c.extensions.iter().map(|e| e.0.chars().count() + 2);
+            exts.chain(Some(c.name.chars().count()))
+        })
+        .max()
+        .unwrap_or(0);
+    let count_width = categories
+        .iter()
+        .map(|c| c.total.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    for category in categories {
+        out.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)).set_bold(true))?;
+        write!(out, "{:<width$}", category.name, width = name_width)?;
+        out.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+        write!(out, " {:>width$}", category.total, width = count_width)?;
+        out.reset()?;
+        writeln!(out, " files")?;
+
+        for (ext, count) in &category.extensions {
+            out.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+            write!(out, "  {:<width$}", ext, width = name_width - 2)?;
+            out.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+            write!(out, " {:>width$}", count, width = count_width)?;
+            out.reset()?;
+            writeln!(out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `count` as a percentage of `total`, or 0 if `total` is 0
+fn percentage(count: u32, total: u32) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        f64::from(count) * 100.0 / f64::from(total)
+    }
+}
+
+/// Counts the files below `path` by extension, treating any of the `compound` extensions
+/// (e.g. `tar.gz`) as a single extension
+///
+/// Directories and entries that can't be read are logged as errors and skipped, so the counts
+/// of everything readable are still returned. `log.summary.errors` tells if any were skipped.
+pub fn find_unique_extensions(
+    path: &Path,
+    options: &WalkOptions,
+    compound: &[String],
+    log: &mut Logger,
+) -> Result<HashMap<String, u32>, Error> {
+    let mut res = HashMap::new();
+
+    walk_skipping_errors(
+        path,
+        options,
+        &mut |entry_path, file_type| {
+            if file_type.is_file() || file_type.is_symlink() {
+                let ext = extension_bucket(entry_path, compound);
+                let count = res.entry(ext).or_insert(0);
+                *count += 1;
+            }
+            Ok(())
+        },
+        &mut |entry_path, e| log.error(&format!("Error: Skipping {:?}: {}", entry_path, e)),
+    )?;
+
+    Ok(res)
+}
+
+/// Adds up the sizes in bytes of the files below `path` by extension, like
+/// `find_unique_extensions` counts them
+pub fn find_extension_sizes(
+    path: &Path,
+    options: &WalkOptions,
+    compound: &[String],
+    log: &mut Logger,
+) -> Result<HashMap<String, u64>, Error> {
+    let mut res = HashMap::new();
+
+    walk_skipping_errors(
+        path,
+        options,
+        &mut |entry_path, file_type| {
+            if file_type.is_file() || file_type.is_symlink() {
+                let ext = extension_bucket(entry_path, compound);
+                let size = fs::symlink_metadata(entry_path)?.len();
+                *res.entry(ext).or_insert(0) += size;
+            }
+            Ok(())
+        },
+        &mut |entry_path, e| log.error(&format!("Error: Skipping {:?}: {}", entry_path, e)),
+    )?;
+
+    Ok(res)
+}
+
+/// Lists the files below `path` by extension, like `find_unique_extensions` counts them
+///
+/// With `only`, just the files of that extension are listed. It's matched case-insensitively
+/// and may start with a `.`, e.g. `.TMP` lists `foo.tmp`. Each list of files is sorted.
+pub fn find_extension_files(
+    path: &Path,
+    options: &WalkOptions,
+    compound: &[String],
+    only: Option<&str>,
+    log: &mut Logger,
+) -> Result<HashMap<String, Vec<PathBuf>>, Error> {
+    let only = only.map(|ext| ext.trim_start_matches('.'));
+    let mut res: HashMap<String, Vec<PathBuf>> = HashMap::new();
+
+    walk_skipping_errors(
+        path,
+        options,
+        &mut |entry_path, file_type| {
+            if file_type.is_file() || file_type.is_symlink() {
+                let ext = extension_bucket(entry_path, compound);
+                if only.is_none_or(|only| ext.eq_ignore_ascii_case(only)) {
+                    res.entry(ext).or_default().push(entry_path.to_path_buf());
+                }
+            }
+            Ok(())
+        },
+        &mut |entry_path, e| log.error(&format!("Error: Skipping {:?}: {}", entry_path, e)),
+    )?;
+
+    for files in res.values_mut() {
+        files.sort();
+    }
+    Ok(res)
+}
+
+/// Counts the files below `path` by extension like `find_unique_extensions`, but separately
+/// for each top-level subdirectory
+///
+/// Files directly inside `path` are counted under `.`, which comes first. The other
+/// directories are sorted by name.
+pub fn find_unique_extensions_by_dir(
+    path: &Path,
+    options: &WalkOptions,
+    compound: &[String],
+    log: &mut Logger,
+) -> Result<Vec<DirExtensions>, Error> {
+    let mut by_dir: HashMap<PathBuf, HashMap<String, u32>> = HashMap::new();
+
+    walk_skipping_errors(
+        path,
+        options,
+        &mut |entry_path, file_type| {
+            if file_type.is_file() || file_type.is_symlink() {
+                let ext = extension_bucket(entry_path, compound);
+                let relative = entry_path.strip_prefix(path)?;
+                let dir = match relative.components().count() {
+                    1 => PathBuf::from("."),
+                    _ => relative.components().take(1).collect(),
+                };
+                let count = by_dir.entry(dir).or_default().entry(ext).or_insert(0);
+                *count += 1;
+            }
+            Ok(())
+        },
+        &mut |entry_path, e| log.error(&format!("Error: Skipping {:?}: {}", entry_path, e)),
+    )?;
+
+    let mut groups: Vec<DirExtensions> = by_dir
+        .into_iter()
+        .map(|(dir, extensions)| DirExtensions { dir, extensions })
+        .collect();
+    groups
+        .sort_by(|a, b| (a.dir != Path::new("."), &a.dir).cmp(&(b.dir != Path::new("."), &b.dir)));
+    Ok(groups)
+}
+
+/// Adds up the extension counts of every directory
+pub fn combine_extensions(groups: &[DirExtensions]) -> HashMap<String, u32> {
+    let mut combined = HashMap::new();
+    for group in groups {
+        for (ext, count) in &group.extensions {
+            *combined.entry(ext.clone()).or_insert(0) += count;
+        }
+    }
+    combined
+}
+
+/// Returns the extension of `path`, or the matching `compound` extension if it has one
+///
+/// Compound extensions are matched case-insensitively but returned as written in the name,
+/// e.g. with `tar.gz`: `foo.tar.gz` => `tar.gz`, `foo.gz` => `gz`, `.tar.gz` => `gz`.
+fn file_extension(path: &Path, compound: &[String]) -> Option<String> {
+    let filename = path.file_name()?.to_string_lossy();
+
+    for ext in compound {
+        let split = match filename.len().checked_sub(ext.len() + 1) {
+            Some(split) if split > 0 => split,
+            _ => continue,
+        };
+
+        let suffix = match filename.get(split..) {
+            Some(suffix) => suffix,
+            None => continue,
+        };
+        if suffix.starts_with('.') && suffix[1..].eq_ignore_ascii_case(ext) {
+            return Some(suffix[1..].to_string());
+        }
+    }
+
+    path.extension()
+        .map(|ext| ext.to_string_lossy().into_owned())
+}
+
+/// Returns the extension `path` is counted under by `unique_ext`, `NO_EXTENSION` or
+/// `DOTFILE_EXTENSION` if it has none, so every file is counted
+fn extension_bucket(path: &Path, compound: &[String]) -> String {
+    if let Some(ext) = file_extension(path, compound) {
+        return ext;
+    }
+
+    if is_hidden(path) {
+        DOTFILE_EXTENSION.to_string()
+    } else {
+        NO_EXTENSION.to_string()
+    }
+}
+
+/// Moves every file below `src` directly into `dst`, returning the moves performed
+///
+/// Name collisions are resolved by appending a numeric suffix to the file stem, e.g.
+/// `photo.jpg`, `photo_1.jpg`, `photo_2.jpg`. Files already in `dst` are left in place, so
+/// `dst` may be `src` itself to flatten it in place.
+pub fn flatten(
+    src: &Path,
+    dst: &Path,
+    options: &FlattenOptions,
+    log: &mut Logger,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+
+    walk(src, &options.walk, &mut |entry_path, file_type| {
+        if file_type.is_dir() {
+            dirs.push(entry_path.to_path_buf());
+        } else if entry_path.parent() != Some(dst) {
+            files.push(entry_path.to_path_buf());
+        }
+        Ok(())
+    })?;
+
+    if !options.dry_run {
+        fs::create_dir_all(dst)?;
+    }
+
+    let mut claimed = HashSet::new();
+    let mut moves = Vec::new();
+
+    for file in files {
+        let filename = match file.file_name() {
+            Some(filename) => filename,
+            None => continue,
+        };
+
+        let target = unique_path(dst, Path::new(filename), &claimed);
+        claimed.insert(target.clone());
+
+        let message = format!("Moving {:?} => {:?}", file, target);
+        if options.dry_run {
+            log.info(&message);
+        } else {
+            log.change(&message);
+            move_path(&file, &target, log)?;
+        }
+        moves.push((file, target));
+    }
+
+    if options.prune {
+        let mut removed: HashSet<PathBuf> = moves.iter().map(|m| m.0.clone()).collect();
+        dirs.retain(|dir| dir != dst && !dst.starts_with(dir));
+        remove_empty_dirs(dirs, &mut removed, options.dry_run, log)?;
+    }
+
+    Ok(moves)
+}
+
+/// Removes each of `dirs` whose entries have all been `removed`, returning the directories
+/// removed
+///
+/// `dirs` must list directories before their parents, as `walk` does, so a directory that
+/// only held empty directories is removed as well.
+fn remove_empty_dirs(
+    dirs: Vec<PathBuf>,
+    removed: &mut HashSet<PathBuf>,
+    dry_run: bool,
+    log: &mut Logger,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut removed_dirs = Vec::new();
+
+    for dir in dirs {
+        let mut is_empty = true;
+        for entry in fs::read_dir(&dir)? {
+            if !removed.contains(&entry?.path()) {
+                is_empty = false;
+                break;
+            }
+        }
+
+        if is_empty {
+            let message = format!("Removing {}", log.path(&dir));
+            if dry_run {
+                log.info(&message);
+            } else {
+                log.change(&message);
+                fs::remove_dir(&dir)?;
+            }
+            removed.insert(dir.clone());
+            removed_dirs.push(dir);
+        }
+    }
+
+    Ok(removed_dirs)
+}
+
+/// Removes the empty directories below `dir`, including those that only held empty
+/// directories, returning the directories removed
+///
+/// `dir` itself is kept. Directories skipped by `options`, e.g. hidden ones, are neither
+/// removed nor looked into, and a directory holding one isn't empty.
+pub fn prune_empty_dirs(
+    dir: &Path,
+    dry_run: bool,
+    options: &WalkOptions,
+    log: &mut Logger,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut dirs = Vec::new();
+
+    walk(dir, options, &mut |entry_path, file_type| {
+        if file_type.is_dir() && entry_path != dir {
+            dirs.push(entry_path.to_path_buf());
+        }
+        Ok(())
+    })?;
+
+    remove_empty_dirs(dirs, &mut HashSet::new(), dry_run, log)
+}
+
+/// Name of the `organize --by ext` directory for files without an extension
+const NO_EXTENSION_DIR: &str = "none";
+
+/// Moves each file directly in `dir` into a subdirectory picked by `options.by`, returning the
+/// moves performed
+///
+/// Subdirectories are created as needed. Name collisions are resolved by appending a numeric
+/// suffix to the file stem like `flatten` does.
+pub fn organize(
+    dir: &Path,
+    options: &OrganizeOptions,
+    log: &mut Logger,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let mut claimed = HashSet::new();
+    let mut moves = Vec::new();
+
+    for file in template_files(dir, false, &options.walk)? {
+        let filename = match file.file_name() {
+            Some(filename) => filename,
+            None => continue,
+        };
+
+        let subdir = match options.by {
+            OrganizeBy::Ext => file_extension(&file, &[])
+                .map(|ext| ext.to_lowercase())
+                .unwrap_or_else(|| NO_EXTENSION_DIR.to_string()),
+            OrganizeBy::Date => {
+                let modified: DateTime<Local> = fs::metadata(&file)?.modified()?.into();
+                format!("{:04}/{:02}", modified.year(), modified.month())
+            }
+        };
+        let target_dir = dir.join(subdir);
+        let target = unique_path(&target_dir, Path::new(filename), &claimed);
+        claimed.insert(target.clone());
+
+        let message = format!("Moving {} => {}", log.path(&file), log.path(&target));
+        if options.dry_run {
+            log.info(&message);
+        } else {
+            fs::create_dir_all(&target_dir)?;
+            log.change(&message);
+            move_path(&file, &target, log)?;
+        }
+        moves.push((file, target));
+    }
+
+    Ok(moves)
+}
+
+/// Returns a path for `filename` in `dir` that doesn't exist and hasn't been `claimed`
+///
+/// The extension is preserved when adding a suffix, e.g. `photo.jpg` => `photo_1.jpg`.
+fn unique_path(dir: &Path, filename: &Path, claimed: &HashSet<PathBuf>) -> PathBuf {
+    let mut target = dir.join(filename);
+    let mut n = 1;
+
+    while target.exists() || claimed.contains(&target) {
+        let mut name = filename
+            .file_stem()
+            .unwrap_or(OsStr::new(""))
+            .to_os_string();
+        name.push(format!("_{}", n));
+        if let Some(ext) = filename.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+
+        target = dir.join(name);
+        n += 1;
+    }
+
+    target
+}
+
+/// Finds groups of non-empty files below `path` with identical contents, as compared by `hash`
+///
+/// Files are first grouped by size and only files sharing a size are hashed, so most files
+/// are never read. Larger files are then grouped by a hash of their first `QUICK_HASH_BYTES`
+/// (passed as the limit to `hash`) and only read in full if that matches too. Groups and the
+/// paths within them are sorted.
+pub fn find_duplicates<F>(
+    path: &Path,
+    options: &WalkOptions,
+    mut hash: F,
+) -> Result<Vec<DuplicateGroup>, Error>
+where
+    F: FnMut(&Path, Option<u64>) -> io::Result<Vec<u8>>,
+{
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+
+    walk(path, options, &mut |entry_path, file_type| {
+        if file_type.is_file() {
+            let size = fs::metadata(entry_path)?.len();
+            if size > 0 {
+                by_size
+                    .entry(size)
+                    .or_default()
+                    .push(entry_path.to_path_buf());
+            }
+        }
+        Ok(())
+    })?;
+
+    let mut groups = Vec::new();
+
+    for (size, paths) in by_size {
+        if paths.len() < 2 {
+            continue;
+        }
+
+        let candidates = if size > QUICK_HASH_BYTES {
+            group_by_hash(paths, |path| hash(path, Some(QUICK_HASH_BYTES)))?
+        } else {
+            vec![paths]
+        };
+
+        for paths in candidates {
+            for mut paths in group_by_hash(paths, |path| hash(path, None))? {
+                paths.sort();
+                groups.push(DuplicateGroup { size, paths });
+            }
+        }
+    }
+
+    groups.sort_by(|a, b| a.paths.cmp(&b.paths));
+    Ok(groups)
+}
+
+/// Splits `paths` into groups of two or more sharing the same `hash`, dropping the rest
+fn group_by_hash<F>(paths: Vec<PathBuf>, mut hash: F) -> io::Result<Vec<Vec<PathBuf>>>
+where
+    F: FnMut(&Path) -> io::Result<Vec<u8>>,
+{
+    let mut by_hash: HashMap<Vec<u8>, Vec<PathBuf>> = HashMap::new();
+    for path in paths {
+        by_hash.entry(hash(&path)?).or_default().push(path);
+    }
+
+    Ok(by_hash
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .collect())
+}
+
+impl HashAlgo {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Blake3 => "blake3",
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Md5 => "md5",
+        }
+    }
+
+    /// Hashes the first `limit` bytes of the file at `path`, or all of it if `None`
+    pub fn hash_file(self, path: &Path, limit: Option<u64>) -> io::Result<Vec<u8>> {
+        let file = fs::File::open(path)?;
+        let mut reader = file.take(limit.unwrap_or(u64::MAX));
+
+        match self {
+            HashAlgo::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                read_chunks(&mut reader, |chunk| {
+                    hasher.update(chunk);
+                })?;
+                Ok(hasher.finalize().as_bytes().to_vec())
+            }
+            HashAlgo::Sha256 => {
+                let mut hasher = Sha256::new();
+                read_chunks(&mut reader, |chunk| hasher.update(chunk))?;
+                Ok(hasher.finalize().to_vec())
+            }
+            HashAlgo::Md5 => {
+                let mut hasher = Md5::new();
+                read_chunks(&mut reader, |chunk| hasher.update(chunk))?;
+                Ok(hasher.finalize().to_vec())
+            }
+        }
+    }
+}
+
+/// Passes everything read from `reader` to `f`, a chunk at a time
+fn read_chunks<R: Read, F: FnMut(&[u8])>(reader: &mut R, mut f: F) -> io::Result<()> {
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => f(&buffer[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl DuplicateGroup {
+    /// Bytes taken up by the copies beyond the first
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64).saturating_sub(1)
+    }
+}
+
+/// Writes each duplicate group as a header line followed by its indented paths, then the
+/// total wasted by the copies
+///
+/// E.g.
+/// `2 files, 11 bytes each, 11 bytes wasted (blake3):`
+/// `  a.txt`
+/// `  nested/b.txt`
+/// ``
+/// `Total: 1 group, 11 bytes wasted`
+pub fn write_duplicates<W: Write>(
+    out: &mut W,
+    groups: &[DuplicateGroup],
+    algo: HashAlgo,
+) -> io::Result<()> {
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+
+        writeln!(
+            out,
+            "{} files, {} bytes each, {} bytes wasted ({}):",
+            group.paths.len(),
+            group.size,
+            group.wasted(),
+            algo.name()
+        )?;
+        for path in &group.paths {
+            writeln!(out, "  {}", path.display())?;
+        }
+    }
+
+    if !groups.is_empty() {
+        let wasted: u64 = groups.iter().map(DuplicateGroup::wasted).sum();
+        writeln!(
+            out,
+            "\nTotal: {} group{}, {} bytes wasted",
+            groups.len(),
+            if groups.len() == 1 { "" } else { "s" },
+            wasted
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the paths of each duplicate group terminated by NUL bytes, as `find -print0` does
+///
+/// Groups are separated by an extra NUL, i.e. an empty entry, as no path can be empty.
+pub fn write_duplicates_null<W: Write>(out: &mut W, groups: &[DuplicateGroup]) -> io::Result<()> {
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b"\0")?;
+        }
+
+        for path in &group.paths {
+            out.write_all(path.as_os_str().as_encoded_bytes())?;
+            out.write_all(b"\0")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes every file but the first in each group, returning the paths removed
+pub fn delete_duplicates(
+    groups: &[DuplicateGroup],
+    dry_run: bool,
+    log: &mut Logger,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut removed = Vec::new();
+
+    for group in groups {
+        for path in group.paths.iter().skip(1) {
+            let message = format!("Removing {:?}", path);
+            if dry_run {
+                log.info(&message);
+            } else {
+                log.change(&message);
+                fs::remove_file(path)?;
+            }
+            removed.push(path.clone());
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Adds up the sizes of the files below each directory up to `depth` levels below `path`,
+/// sorted by path and followed by the total of `path` itself
+///
+/// Entries that can't be read are logged as errors and left out of the totals.
+pub fn find_dir_sizes(
+    path: &Path,
+    depth: usize,
+    options: &WalkOptions,
+    log: &mut Logger,
+) -> Result<Vec<DirSize>, Error> {
+    let mut sizes: BTreeMap<PathBuf, u64> = BTreeMap::new();
+    let mut total = 0;
+
+    walk_skipping_errors(
+        path,
+        options,
+        &mut |entry_path, file_type| {
+            let relative = entry_path.strip_prefix(path)?;
+            let levels = relative.components().count();
+            if file_type.is_dir() {
+                if levels <= depth {
+                    sizes.entry(entry_path.to_path_buf()).or_insert(0);
+                }
+                return Ok(());
+            }
+
+            let size = fs::symlink_metadata(entry_path)?.len();
+            total += size;
+            // Counted towards every reported directory it's in
+            let mut dir = path.to_path_buf();
+            for component in relative.components().take((levels - 1).min(depth)) {
+                dir.push(component);
+                *sizes.entry(dir.clone()).or_insert(0) += size;
+            }
+            Ok(())
+        },
+        &mut |entry_path, e| log.error(&format!("Error: Skipping {:?}: {}", entry_path, e)),
+    )?;
+
+    let mut dirs: Vec<DirSize> = sizes
+        .into_iter()
+        .map(|(path, size)| DirSize { path, size })
+        .collect();
+    dirs.push(DirSize {
+        path: path.to_path_buf(),
+        size: total,
+    });
+    Ok(dirs)
+}
+
+/// Formats a size in bytes, or with `human` in the largest unit of 1024 that fits, e.g. `512B`,
+/// `1.5K` or `12M`
+pub fn format_size(bytes: u64, human: bool) -> String {
+    if !human {
+        return bytes.to_string();
+    }
+
+    let units = ["B", "K", "M", "G", "T", "P"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else if size < 10.0 {
+        format!("{:.1}{}", size, units[unit])
+    } else {
+        format!("{:.0}{}", size, units[unit])
+    }
+}
+
+/// E.g. `48G` when `human`, or `51539607552 bytes`
+fn size_label(bytes: u64, human: bool) -> String {
+    if human {
+        format_size(bytes, true)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// Writes the size of each directory followed by a tab and its path, as `du` does
+pub fn write_dir_sizes<W: Write>(out: &mut W, dirs: &[DirSize], human: bool) -> io::Result<()> {
+    for dir in dirs {
+        writeln!(
+            out,
+            "{}\t{}",
+            format_size(dir.size, human),
+            dir.path.display()
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Finds the symlinks below `path`, sorted by path
+///
+/// Links to directories aren't followed. A link is broken if following it fails, so a link
+/// to another broken link is broken too.
+pub fn find_links(path: &Path, options: &WalkOptions) -> Result<Vec<Link>, Error> {
+    let mut links = Vec::new();
+
+    walk(path, options, &mut |entry_path, file_type| {
+        if file_type.is_symlink() {
+            links.push(Link {
+                path: entry_path.to_path_buf(),
+                target: fs::read_link(entry_path)?,
+                broken: fs::metadata(entry_path).is_err(),
+            });
+        }
+        Ok(())
+    })?;
+
+    links.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(links)
+}
+
+/// Writes each link followed by its target, marking broken links
+///
+/// E.g.
+/// `photos/latest -> 2024/IMG_001.JPG`
+/// `photos/old -> 2019/IMG_001.JPG (broken)`
+pub fn write_links<W: Write>(out: &mut W, links: &[Link]) -> io::Result<()> {
+    for link in links {
+        write!(out, "{} -> {}", link.path.display(), link.target.display())?;
+        if link.broken {
+            write!(out, " (broken)")?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the broken links of `links`, returning the paths removed
+pub fn delete_broken_links(
+    links: &[Link],
+    dry_run: bool,
+    log: &mut Logger,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut removed = Vec::new();
+
+    for link in links.iter().filter(|link| link.broken) {
+        let message = format!("Removing {:?}", link.path);
+        if dry_run {
+            log.info(&message);
+        } else {
+            log.change(&message);
+            fs::remove_file(&link.path)?;
+        }
+        removed.push(link.path.clone());
+    }
+
+    Ok(removed)
+}
+
+/// Finds the entries below `path` which share a directory and a lower cased name
+///
+/// These can't coexist on a case-insensitive filesystem, and would clash when converted to a
+/// single case. Clashes are sorted by directory.
+pub fn find_name_clashes(path: &Path, options: &WalkOptions) -> Result<Vec<NameClash>, Error> {
+    let mut by_folded_name: HashMap<(PathBuf, String), Vec<PathBuf>> = HashMap::new();
+
+    walk(path, options, &mut |entry_path, _| {
+        if let (Some(dir), Some(name)) = (entry_path.parent(), entry_path.file_name()) {
+            let folded = name.to_string_lossy().to_lowercase();
+            by_folded_name
+                .entry((dir.to_path_buf(), folded))
+                .or_default()
+                .push(PathBuf::from(name));
+        }
+        Ok(())
+    })?;
+
+    let mut clashes: Vec<NameClash> = by_folded_name
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|((dir, _), mut names)| {
+            names.sort();
+            NameClash { dir, names }
+        })
+        .collect();
+    clashes.sort_by(|a, b| (&a.dir, &a.names).cmp(&(&b.dir, &b.names)));
+
+    Ok(clashes)
+}
+
+/// Writes each clash as its directory followed by the indented clashing names
+///
+/// E.g.
+/// `photos:`
+/// `  IMG_001.JPG`
+/// `  img_001.jpg`
+pub fn write_name_clashes<W: Write>(out: &mut W, clashes: &[NameClash]) -> io::Result<()> {
+    for clash in clashes {
+        writeln!(out, "{}:", clash.dir.display())?;
+        for name in &clash.names {
+            writeln!(out, "  {}", name.display())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts watching `dir` (and its subdirectories if `recursive` is set) for new or changed
+/// entries, sending each to `sender` until the returned watcher is dropped
+pub fn start_watcher(
+    dir: &Path,
+    recursive: bool,
+    sender: Sender<WatchEvent>,
+) -> Result<RecommendedWatcher, Error> {
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        if let Ok(event) = result {
+            if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+                for path in event.paths {
+                    let _ = sender.send(WatchEvent::Changed(path));
+                }
+            }
+        }
+    })?;
+
+    let mode = if recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher.watch(dir, mode)?;
+    Ok(watcher)
+}
+
+/// Converts the entries reported by `events` until `WatchEvent::Stop` is received
+///
+/// An entry is only converted once it has gone `debounce` without any further events, so files
+/// still being written (e.g. downloads) aren't renamed part way through.
+pub fn watch(
+    root: &Path,
+    events: &Receiver<WatchEvent>,
+    transform: &Transform,
+    options: &ConvertOptions,
+    debounce: Duration,
+    log: &mut Logger,
+) {
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+
+    loop {
+        let now = Instant::now();
+        let timeout = pending
+            .values()
+            .map(|changed| (*changed + debounce).saturating_duration_since(now))
+            .min()
+            .unwrap_or(debounce);
+
+        match events.recv_timeout(timeout) {
+            Ok(WatchEvent::Changed(path)) => {
+                pending.insert(path, Instant::now());
+            }
+            Ok(WatchEvent::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        let now = Instant::now();
+        let mut settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= debounce)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        // Entries inside a directory sort after it, so reversing converts them first
+        settled.sort();
+        for path in settled.into_iter().rev() {
+            pending.remove(&path);
+            convert_new_entry(root, &path, transform, options, log);
+        }
+    }
+}
+
+/// Converts an entry reported by `watch`, along with its children if `options.recursive` is set
+///
+/// Entries which no longer exist, e.g. the old name of a renamed file, are ignored.
+fn convert_new_entry(
+    root: &Path,
+    path: &Path,
+    transform: &Transform,
+    options: &ConvertOptions,
+    log: &mut Logger,
+) {
+    let is_dir = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.is_dir(),
+        Err(_) => return,
+    };
+
+    let filter = match watched_entry_filter(root, path, is_dir, &options.walk) {
+        Ok(filter) => filter,
+        Err(reason) => {
+            log.skip(&format!("Skipping {:?}, {}", path, reason));
+            return;
+        }
+    };
+
+    if (is_dir && options.ignore_dirs)
+        || (!is_dir && (options.ignore_files || !is_selected_file(path, options)))
+    {
+        log.skip(&format!("Skipping {:?}", path));
+        return;
+    }
+
+    let mut plan = Vec::new();
+    if is_dir && options.recursive {
+        match dir_plan(path, transform, options, &filter, log) {
+            Ok(children) => plan.extend(children),
+            Err(e) => log.error(&format!("Error: {}", e)),
+        }
+    }
+    match convert_path(path, transform, options) {
+        Some(target) => plan.push((path.to_path_buf(), target)),
+        None => {
+            log.summary.skipped += 1;
+            log.skip(&format!("Skipping {:?}", path));
+        }
+    }
+
+    for (from, to) in plan {
+        if let Err(e) = apply_rename(&from, &to, options.on_conflict, log) {
+            log.error(&format!("Error: {}", e));
+        }
+    }
+}
+
+/// Returns the filter of the directory containing `path`, as walking down to it from the
+/// watched `root` would build it, or why `path` (or a directory above it) isn't walked
+///
+/// E.g. with `root/.git/HEAD` the `.git` directory is hidden, so `HEAD` is left alone too.
+fn watched_entry_filter(
+    root: &Path,
+    path: &Path,
+    is_dir: bool,
+    options: &WalkOptions,
+) -> Result<EntryFilter, &'static str> {
+    // The watcher may report absolute (or even canonical) paths for a relative `root`
+    let absolute = |path: &Path| path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let (mut root, mut path) = (absolute(root), absolute(path));
+    if !path.starts_with(&root) {
+        if let (Ok(canonical_root), Ok(canonical_path)) =
+            (fs::canonicalize(&root), fs::canonicalize(&path))
+        {
+            root = canonical_root;
+            path = canonical_path;
+        }
+    }
+    let relative = match path.strip_prefix(&root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        _ => return Err("not inside the watched directory"),
+    };
+
+    let mut filter = EntryFilter::default().for_dir(&root, options);
+    let mut current = root.clone();
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        current.push(component);
+        let is_last = components.peek().is_none();
+
+        if let Some(reason) = filter.exclusion(&current, is_dir || !is_last, options) {
+            return Err(reason);
+        }
+        if !is_last {
+            if !filter.descends(options) {
+                return Err("below the maximum depth");
+            }
+            filter = filter.for_dir(&current, options);
+        }
+    }
+
+    Ok(filter)
+}
+
+/// Gathers the metadata of `path`, and with a directory the counts, sizes and extremes of the
+/// entries below it, as reported by `stat`
+///
+/// Entries that can't be read are logged as errors and left out.
+pub fn path_stats(
+    path: &Path,
+    options: &WalkOptions,
+    log: &mut Logger,
+) -> Result<PathStats, Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut stats = PathStats {
+        path: path.to_path_buf(),
+        kind: entry_kind(&metadata.file_type()).to_string(),
+        mode: permission_bits(&metadata),
+        readonly: metadata.permissions().readonly(),
+        modified: metadata.modified().ok(),
+        accessed: metadata.accessed().ok(),
+        created: metadata.created().ok(),
+        ..PathStats::default()
+    };
+
+    if !metadata.is_dir() {
+        stats.add_entry(path, &metadata, 0);
+        return Ok(stats);
+    }
+
+    walk_skipping_errors(
+        path,
+        options,
+        &mut |entry_path, _| {
+            let metadata = fs::symlink_metadata(entry_path)?;
+            let depth = entry_path.strip_prefix(path)?.components().count();
+            stats.add_entry(entry_path, &metadata, depth);
+            Ok(())
+        },
+        &mut |entry_path, e| log.error(&format!("Error: Skipping {:?}: {}", entry_path, e)),
+    )?;
+
+    Ok(stats)
+}
+
+impl PathStats {
+    fn add_entry(&mut self, path: &Path, metadata: &fs::Metadata, depth: usize) {
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            self.dirs += 1;
+        } else {
+            if file_type.is_symlink() {
+                self.symlinks += 1;
+            } else {
+                self.files += 1;
+            }
+            self.size += metadata.len();
+        }
+
+        if let (true, Ok(modified)) = (file_type.is_file(), metadata.modified()) {
+            if self
+                .oldest
+                .as_ref()
+                .is_none_or(|(_, oldest)| modified < *oldest)
+            {
+                self.oldest = Some((path.to_path_buf(), modified));
+            }
+            if self
+                .newest
+                .as_ref()
+                .is_none_or(|(_, newest)| modified > *newest)
+            {
+                self.newest = Some((path.to_path_buf(), modified));
+            }
+        }
+
+        if depth > 0
+            && self
+                .deepest
+                .as_ref()
+                .is_none_or(|(_, deepest)| depth > *deepest)
+        {
+            self.deepest = Some((path.to_path_buf(), depth));
+        }
+
+        let name_length = |path: &Path| {
+            path.file_name()
+                .map_or(0, |name| name.to_string_lossy().chars().count())
+        };
+        if self
+            .longest_name
+            .as_ref()
+            .is_none_or(|longest| name_length(path) > name_length(longest))
+        {
+            self.longest_name = Some(path.to_path_buf());
+        }
+    }
+}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn permission_bits(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// E.g. `rwxr-xr-x (755)`, or `read-only` or `read-write` without permission bits
+fn permissions_label(mode: Option<u32>, readonly: bool) -> String {
+    let mode = match mode {
+        Some(mode) => mode,
+        None if readonly => return "read-only".to_string(),
+        None => return "read-write".to_string(),
+    };
+
+    let mut label = String::new();
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        label.push(if bits & 4 != 0 { 'r' } else { '-' });
+        label.push(if bits & 2 != 0 { 'w' } else { '-' });
+        label.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    format!("{} ({:o})", label, mode)
+}
+
+/// Returns the labelled lines of `stat`, e.g. `("Size", "2.0K")`, leaving out the ones that
+/// are unknown or don't apply
+pub fn path_stat_fields(stats: &PathStats, human: bool) -> Vec<(&'static str, String)> {
+    let time = |time: &SystemTime| {
+        let time: DateTime<Local> = (*time).into();
+        time.format("%Y-%m-%d %H:%M:%S").to_string()
+    };
+
+    let mut fields = vec![
+        ("Path", stats.path.display().to_string()),
+        ("Type", stats.kind.clone()),
+        ("Permissions", permissions_label(stats.mode, stats.readonly)),
+    ];
+    if let Some(ref modified) = stats.modified {
+        fields.push(("Modified", time(modified)));
+    }
+    if let Some(ref accessed) = stats.accessed {
+        fields.push(("Accessed", time(accessed)));
+    }
+    if let Some(ref created) = stats.created {
+        fields.push(("Created", time(created)));
+    }
+    fields.push(("Size", size_label(stats.size, human)));
+
+    if stats.kind == "dir" {
+        fields.push((
+            "Entries",
+            format!(
+                "{} files, {} directories, {} symlinks",
+                stats.files, stats.dirs, stats.symlinks
+            ),
+        ));
+        if let Some((ref path, ref modified)) = stats.oldest {
+            fields.push((
+                "Oldest file",
+                format!("{} ({})", path.display(), time(modified)),
+            ));
+        }
+        if let Some((ref path, ref modified)) = stats.newest {
+            fields.push((
+                "Newest file",
+                format!("{} ({})", path.display(), time(modified)),
+            ));
+        }
+        if let Some((ref path, depth)) = stats.deepest {
+            let levels = if depth == 1 { "level" } else { "levels" };
+            fields.push((
+                "Deepest path",
+                format!("{} ({} {})", path.display(), depth, levels),
+            ));
+        }
+        if let Some(ref path) = stats.longest_name {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let length = name.chars().count();
+            fields.push((
+                "Longest name",
+                format!("{} ({} characters)", path.display(), length),
+            ));
+        }
+    }
+
+    fields
+}
+
+/// Writes the `fields` of `path_stat_fields` one per line, with their values aligned
+pub fn write_path_stats<W: Write>(out: &mut W, fields: &[(&str, String)]) -> io::Result<()> {
+    let width = fields
+        .iter()
+        .map(|(label, _)| label.len() + 1)
+        .max()
+        .unwrap_or(0);
+    for (label, value) in fields {
+        writeln!(
+            out,
+            "{:<width$} {}",
+            format!("{}:", label),
+            value,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the `fields` of `path_stat_fields` as a JSON object keyed by their snake cased
+/// labels, e.g. `{"path": "photos", "longest_name": ...}`
+pub fn write_path_stats_json<W: Write>(
+    out: &mut W,
+    fields: &[(&str, String)],
+) -> Result<(), Error> {
+    let object: serde_json::Map<String, serde_json::Value> = fields
+        .iter()
+        .map(|(label, value)| (stat_key(label), serde_json::Value::from(value.as_str())))
+        .collect();
+    serde_json::to_writer_pretty(&mut *out, &object)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Writes a `field,value` header followed by the `fields` of `path_stat_fields`
+pub fn write_path_stats_csv<W: Write>(out: &mut W, fields: &[(&str, String)]) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["field", "value"])?;
+    for (label, value) in fields {
+        writer.write_record([stat_key(label).as_str(), value])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// E.g. `Oldest file` => `oldest_file`
+fn stat_key(label: &str) -> String {
+    label.to_lowercase().replace(' ', "_")
+}
+
+/// Writes `path` and the entries below it as a tree, e.g.
+///
+/// ```text
+/// photos
+/// ├── 2023
+/// │   └── beach.jpg
+/// └── notes.txt
+/// ```
+///
+/// Files are followed by the annotations selected in `options`, e.g. `beach.jpg [1.2M, jpg]`.
+pub fn write_tree<W: Write>(out: &mut W, path: &Path, options: &TreeOptions) -> Result<(), Error> {
+    writeln!(out, "{}", path.display())?;
+    write_tree_children(out, path, "", options, &EntryFilter::default())
+}
+
+fn write_tree_children<W: Write>(
+    out: &mut W,
+    path: &Path,
+    prefix: &str,
+    options: &TreeOptions,
+    parent_filter: &EntryFilter,
+) -> Result<(), Error> {
+    let filter = parent_filter.for_dir(path, &options.walk);
+
+    let mut entries = Vec::new();
+    for entry in read_dir_sorted(path)? {
+        let is_dir = entry.file_type()?.is_dir();
+        let entry_path = entry.path();
+
+        if filter.is_excluded(&entry_path, is_dir, &options.walk)
+            || (is_dir && options.ignore_dirs)
+            || (!is_dir && options.ignore_files)
+        {
+            continue;
+        }
+        entries.push((entry_path, is_dir));
+    }
+
+    let count = entries.len();
+    for (i, (entry_path, is_dir)) in entries.into_iter().enumerate() {
+        let is_last = i + 1 == count;
+        let connector = if is_last { "└── " } else { "├── " };
+
+        let mut label = entry_path
+            .file_name()
+            .unwrap_or(OsStr::new(""))
+            .to_string_lossy()
+            .into_owned();
+        if let Some(ref transform) = options.preview {
+            if let Some(target) = convert_path(&entry_path, transform, &ConvertOptions::default()) {
+                let target_name = target
+                    .file_name()
+                    .unwrap_or(OsStr::new(""))
+                    .to_string_lossy();
+                label = format!("{} -> {}", label, target_name);
+            }
+        }
+
+        let mut annotations = Vec::new();
+        if !is_dir && options.sizes {
+            let size = fs::symlink_metadata(&entry_path)?.len();
+            annotations.push(size_label(size, options.human));
+        }
+        if !is_dir && options.extensions {
+            annotations.push(extension_bucket(&entry_path, &[]));
+        }
+        if !annotations.is_empty() {
+            label = format!("{} [{}]", label, annotations.join(", "));
+        }
+        writeln!(out, "{}{}{}", prefix, connector, label)?;
+
+        if is_dir && filter.descends(&options.walk) {
+            let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+            write_tree_children(out, &entry_path, &child_prefix, options, &filter)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+    use termcolor::NoColor;
+
+    fn quiet_logger() -> Logger {
+        Logger::new(Verbosity::Quiet)
+    }
+
+    #[test]
+    fn test_convert_file() {
+        let lower_path = env::temp_dir().join("test.file");
+        let upper_path = env::temp_dir().join("TEST.FILE");
+
+        if lower_path.exists() {
+            fs::remove_file(&lower_path).unwrap();
+        }
+
+        if upper_path.exists() {
+            fs::remove_file(&upper_path).unwrap();
+        }
+
+        // -- Test to upper case
+        let _f = File::create(&lower_path).unwrap();
+        convert_file_or_dir(
+            &lower_path,
+            &Transform::Case(LetterCase::UpperCase),
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(upper_path.exists());
+
+        fs::remove_file(&upper_path).unwrap();
+
+        // -- Test to lower case
+        let _f = File::create(&upper_path).unwrap();
+        convert_file_or_dir(
+            &upper_path,
+            &Transform::Case(LetterCase::LowerCase),
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(lower_path.exists());
+
+        fs::remove_file(&lower_path).unwrap();
+    }
+
+    #[test]
+    fn test_turkish_case() {
+        let upper = Transform::Case(LetterCase::UpperCase);
+        let lower = Transform::Case(LetterCase::LowerCase);
+        let turkish = ConvertOptions {
+            locale: CaseLocale::Turkish,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            transform_filename("istanbul ılık.txt", &upper, &turkish),
+            "İSTANBUL ILIK.TXT"
+        );
+        assert_eq!(
+            transform_filename("İSTANBUL ILIK.TXT", &lower, &turkish),
+            "istanbul ılık.txt"
+        );
+
+        // -- Dotted and dotless i round-trip
+        for name in &["iı", "İI"] {
+            let lowered = transform_filename(name, &lower, &turkish);
+            let uppered = transform_filename(&lowered, &upper, &turkish);
+            assert_eq!(lowered, "iı");
+            assert_eq!(uppered, "İI");
+        }
+
+        // -- Other letters use the default mappings
+        assert_eq!(
+            transform_filename("Straße ÇĞÖŞÜ", &lower, &turkish),
+            "straße çğöşü"
+        );
+        assert_eq!(
+            transform_filename(
+                "İlk Işık.JPG",
+                &Transform::Style(CaseStyle::Snake),
+                &turkish
+            ),
+            "ilk_ışık.jpg"
+        );
+
+        // -- The default stays locale-independent
+        let default = ConvertOptions::default();
+        assert_eq!(transform_filename("istanbul", &upper, &default), "ISTANBUL");
+        assert_eq!(transform_filename("ILIK", &lower, &default), "ilik");
+    }
+
+    #[test]
+    fn test_convert_case_keep() {
+        let keep = vec![String::from("NASA"), String::from("iPhone")];
+
+        assert_eq!(
+            convert_case(
+                "nasa apollo mission.txt",
+                &LetterCase::LowerCase,
+                &keep,
+                CaseLocale::Default
+            ),
+            "NASA apollo mission.txt"
+        );
+        assert_eq!(
+            convert_case(
+                "Nasa Apollo iphone.JPG",
+                &LetterCase::UpperCase,
+                &keep,
+                CaseLocale::Default
+            ),
+            "NASA APOLLO iPhone.JPG"
+        );
+
+        // Only whole words are kept
+        assert_eq!(
+            convert_case(
+                "nasal_NASA-nasa2.TXT",
+                &LetterCase::LowerCase,
+                &keep,
+                CaseLocale::Default
+            ),
+            "nasal_NASA-nasa2.txt"
+        );
+        assert_eq!(
+            convert_case(
+                "photo.jpg",
+                &LetterCase::UpperCase,
+                &[],
+                CaseLocale::Default
+            ),
+            "PHOTO.JPG"
+        );
+    }
+
+    #[test]
+    fn test_convert_file_skips_unchanged() {
+        let root = env::temp_dir().join("ram-utils-convert-test-unchanged");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(&root).unwrap();
+        File::create(root.join("UPPER.TXT")).unwrap();
+        File::create(root.join("Mixed.txt")).unwrap();
+
+        let out = SharedBuffer::default();
+        let mut log = Logger::with_writers(
+            Verbosity::Verbose,
+            Box::new(out.clone()),
+            Box::new(io::sink()),
+        );
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions::default();
+
+        convert_file_or_dir(&root.join("UPPER.TXT"), &transform, &options, &mut log).unwrap();
+        assert_eq!(log.summary.files, 0);
+        assert_eq!(log.summary.skipped, 1);
+        assert!(!out.contents().contains("Converting"));
+
+        // A name differing only by case is a real conversion
+        assert_eq!(
+            convert_path(&root.join("Mixed.txt"), &transform, &options),
+            Some(root.join("MIXED.TXT"))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_children() {
+        let root = env::temp_dir().join("ram-utils-convert-test-convert-children");
+
+        let mut lower_paths: Vec<PathBuf> = Vec::new();
+        let mut upper_paths: Vec<PathBuf> = Vec::new();
+
+        for name in ["one", "two", "three"].iter() {
+            let lower_dir = root.join(name);
+            let upper_dir = root.join(name.to_uppercase());
+
+            let lower_file = lower_dir.with_extension("file");
+            let upper_file = upper_dir.with_extension("FILE");
+
+            lower_paths.push(lower_file);
+            upper_paths.push(upper_file);
+
+            lower_paths.push(lower_dir);
+            upper_paths.push(upper_dir);
+        }
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        // -- Test to upper case
+        fs::create_dir(&root).unwrap();
+
+        for path in &lower_paths {
+            if path.is_dir() {
+                fs::create_dir(path).unwrap();
+            } else {
+                File::create(path).unwrap();
+            }
+        }
+
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        for path in &upper_paths {
+            assert!(path.exists());
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+
+        // -- Test to lower case
+        fs::create_dir(&root).unwrap();
+
+        for path in &upper_paths {
+            if path.is_dir() {
+                fs::create_dir(path).unwrap();
+            } else {
+                File::create(path).unwrap();
+            }
+        }
+
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::LowerCase),
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        for path in &lower_paths {
+            assert!(path.exists());
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_dir_plan() {
+        let root = env::temp_dir().join("ram-utils-convert-test-plan");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(root.join("a").join("b")).unwrap();
+        File::create(root.join("a").join("b").join("c.txt")).unwrap();
+
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions::default();
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+
+        assert_eq!(
+            plan,
+            [
+                (root.join("a/b/c.txt"), root.join("a/b/C.TXT")),
+                (root.join("a/b"), root.join("a/B")),
+                (root.join("a"), root.join("A")),
+            ]
+        );
+
+        // Nothing is renamed until the plan is applied
+        assert!(root.join("a/b/c.txt").exists());
+
+        convert_children(&root, &transform, &options, &mut quiet_logger()).unwrap();
+        assert!(root.join("A/B/C.TXT").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_dir_plan_order() {
+        let root = env::temp_dir().join("ram-utils-convert-test-plan-order");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("c")).unwrap();
+        File::create(root.join("a/b/one.txt")).unwrap();
+        File::create(root.join("a/two.txt")).unwrap();
+        File::create(root.join("c/three.txt")).unwrap();
+        File::create(root.join("four.txt")).unwrap();
+
+        let plan_order = |order| {
+            let options = ConvertOptions {
+                order,
+                ..Default::default()
+            };
+            let transform = Transform::Case(LetterCase::UpperCase);
+            convert_dir_plan(&root, &transform, &options, &mut quiet_logger())
+                .unwrap()
+                .into_iter()
+                .map(|(from, _)| from.strip_prefix(&root).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+
+        let depth_first = plan_order(TraversalOrder::DepthFirst);
+        let expected: Vec<PathBuf> = [
+            "a/b/one.txt",
+            "a/b",
+            "a/two.txt",
+            "a",
+            "c/three.txt",
+            "c",
+            "four.txt",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(depth_first, expected);
+
+        let breadth_first = plan_order(TraversalOrder::BreadthFirst);
+        let expected: Vec<PathBuf> = [
+            "a/b/one.txt",
+            "a/b",
+            "a/two.txt",
+            "c/three.txt",
+            "a",
+            "c",
+            "four.txt",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(breadth_first, expected);
+
+        // -- Either way, every directory comes after everything inside it
+        for plan in &[depth_first, breadth_first] {
+            for (i, dir) in plan.iter().enumerate() {
+                assert!(plan[i + 1..]
+                    .iter()
+                    .all(|later| !later.starts_with(dir) || later == dir));
+            }
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_plan_parents() {
+        let root = env::temp_dir().join("ram-utils-convert-test-parents");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::create_dir_all(root.join("a/d")).unwrap();
+        File::create(root.join("a/b/c/file.txt")).unwrap();
+
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions::default();
+        let paths = vec![root.join("a/b/c/file.txt"), root.join("a/d")];
+
+        let mut log = quiet_logger();
+        let mut plan = Vec::new();
+        for path in &paths {
+            plan.extend(plan_entry(path, &transform, &options, &mut log));
+        }
+        plan.extend(plan_parents(&paths, Some(&root), &transform, &options, &mut log).unwrap());
+
+        assert_eq!(
+            plan,
+            vec![
+                (root.join("a/b/c/file.txt"), root.join("a/b/c/FILE.TXT")),
+                (root.join("a/d"), root.join("a/D")),
+                (root.join("a/b/c"), root.join("a/b/C")),
+                (root.join("a/b"), root.join("a/B")),
+                (root.join("a"), root.join("A")),
+            ]
+        );
+
+        for (from, to) in &plan {
+            apply_rename(from, to, ConflictPolicy::Error, &mut log).unwrap();
+        }
+        assert!(root.join("A/B/C/FILE.TXT").is_file());
+        assert!(root.join("A/D").is_dir());
+
+        // -- Nothing at or above the root is converted
+        let plan = plan_parents(
+            &[root.join("A/B")],
+            Some(&root.join("A")),
+            &transform,
+            &options,
+            &mut log,
+        );
+        assert_eq!(plan.unwrap(), vec![]);
+        assert!(plan_parents(
+            &[root.join("A/B")],
+            Some(Path::new("/elsewhere")),
+            &transform,
+            &options,
+            &mut log
+        )
+        .is_err());
+
+        // -- Relative paths stop at their first component
+        let relative = plan_parents(
+            &[PathBuf::from("../x/y/z")],
+            None,
+            &transform,
+            &options,
+            &mut log,
+        );
+        assert_eq!(relative.unwrap(), vec![]);
+        let relative = plan_parents(
+            &[PathBuf::from("./x/y")],
+            None,
+            &transform,
+            &options,
+            &mut log,
+        );
+        assert_eq!(
+            relative.unwrap(),
+            vec![(PathBuf::from("./x"), PathBuf::from("./X"))]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_copy_converted() {
+        let root = env::temp_dir().join("ram-utils-convert-test-into");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src/a dir")).unwrap();
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::write(root.join("out/C.TXT"), "stale").unwrap();
+        fs::write(root.join("src/a dir/b.txt"), "b").unwrap();
+        fs::write(root.join("src/c.txt"), "c").unwrap();
+        fs::write(root.join("src/D.TXT"), "d").unwrap();
+        fs::write(root.join("single.txt"), "single").unwrap();
+
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions {
+            recursive: true,
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+
+        let paths = vec![root.join("src"), root.join("single.txt")];
+        copy_converted(
+            &paths,
+            &root.join("out"),
+            &transform,
+            &options,
+            &mut quiet_logger(),
+        );
+
+        // -- Originals are untouched
+        assert_eq!(
+            fs::read_to_string(root.join("src/a dir/b.txt")).unwrap(),
+            "b"
+        );
+        assert_eq!(fs::read_to_string(root.join("src/c.txt")).unwrap(), "c");
+        assert!(root.join("single.txt").exists());
+
+        // -- Copies have converted names, overwriting existing files
+        assert_eq!(
+            fs::read_to_string(root.join("out/A DIR/B.TXT")).unwrap(),
+            "b"
+        );
+        assert_eq!(fs::read_to_string(root.join("out/C.TXT")).unwrap(), "c");
+        assert_eq!(fs::read_to_string(root.join("out/D.TXT")).unwrap(), "d");
+        assert_eq!(
+            fs::read_to_string(root.join("out/SINGLE.TXT")).unwrap(),
+            "single"
+        );
+
+        // -- Without -r only the files directly inside a directory are copied
+        let options = ConvertOptions::default();
+        copy_converted(
+            &[root.join("src")],
+            &root.join("flat"),
+            &transform,
+            &options,
+            &mut quiet_logger(),
+        );
+        assert!(root.join("flat/C.TXT").exists());
+        assert!(!root.join("flat/A DIR").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rename_refuses_existing_target() {
+        let root = env::temp_dir().join("ram-utils-convert-test-clash");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("foo.txt"), "lower").unwrap();
+        fs::write(root.join("bar.txt"), "bar").unwrap();
+
+        let mut log = quiet_logger();
+        let (foo, bar) = (root.join("foo.txt"), root.join("bar.txt"));
+        assert!(apply_rename(&foo, &bar, ConflictPolicy::Error, &mut log).is_err());
+        assert_eq!(fs::read_to_string(root.join("bar.txt")).unwrap(), "bar");
+        assert_eq!(fs::read_to_string(root.join("foo.txt")).unwrap(), "lower");
+
+        // -- Renaming an entry to a name it already has is allowed
+        let upper = root.join("FOO.TXT");
+        apply_rename(&foo, &upper, ConflictPolicy::Error, &mut log).unwrap();
+        assert_eq!(fs::read_to_string(root.join("FOO.TXT")).unwrap(), "lower");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rename_conflict_policies() {
+        let root = env::temp_dir().join("ram-utils-conflict-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        // -- `draft.txt` is renamed to `final.txt`, which already exists, as does `final_1.txt`
+        let reset = || {
+            for (name, contents) in &[
+                ("draft.txt", "a"),
+                ("final.txt", "existing"),
+                ("final_1.txt", "one"),
+            ] {
+                fs::write(root.join(name), contents).unwrap();
+            }
+        };
+        let rename = |on_conflict| {
+            let mut log = quiet_logger();
+            let result = apply_rename(
+                &root.join("draft.txt"),
+                &root.join("final.txt"),
+                on_conflict,
+                &mut log,
+            );
+            (result, log.summary)
+        };
+        let read = |name: &str| fs::read_to_string(root.join(name)).ok();
+
+        // -- error
+        reset();
+        let (result, summary) = rename(ConflictPolicy::Error);
+        assert!(result.is_err());
+        assert_eq!(summary.files, 0);
+        assert_eq!(read("draft.txt").as_deref(), Some("a"));
+        assert_eq!(read("final.txt").as_deref(), Some("existing"));
+
+        // -- skip
+        let (result, summary) = rename(ConflictPolicy::Skip);
+        assert!(result.is_ok());
+        assert_eq!((summary.files, summary.skipped), (0, 1));
+        assert_eq!(read("draft.txt").as_deref(), Some("a"));
+        assert_eq!(read("final.txt").as_deref(), Some("existing"));
+
+        // -- number
+        let (result, summary) = rename(ConflictPolicy::Number);
+        assert!(result.is_ok());
+        assert_eq!(summary.files, 1);
+        assert_eq!(read("draft.txt"), None);
+        assert_eq!(read("final.txt").as_deref(), Some("existing"));
+        assert_eq!(read("final_1.txt").as_deref(), Some("one"));
+        assert_eq!(read("final_2.txt").as_deref(), Some("a"));
+
+        // -- overwrite
+        reset();
+        let (result, summary) = rename(ConflictPolicy::Overwrite);
+        assert!(result.is_ok());
+        assert_eq!(summary.files, 1);
+        assert_eq!(read("draft.txt"), None);
+        assert_eq!(read("final.txt").as_deref(), Some("a"));
+
+        // -- fail
+        reset();
+        let (result, summary) = rename(ConflictPolicy::Fail);
+        let e = result.unwrap_err();
+        assert!(ConflictPolicy::Fail.stops_on(&e));
+        assert!(!ConflictPolicy::Error.stops_on(&e));
+        assert!(!ConflictPolicy::Fail.stops_on(&err_msg("Permission denied")));
+        assert_eq!(summary.files, 0);
+        assert_eq!(read("draft.txt").as_deref(), Some("a"));
+        assert_eq!(read("final.txt").as_deref(), Some("existing"));
+
+        // -- Copies with --into follow the same policy
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::write(root.join("out/B.TXT"), "existing").unwrap();
+        let copy = |on_conflict| {
+            let options = ConvertOptions {
+                on_conflict,
+                ..Default::default()
+            };
+            let transform = Transform::Case(LetterCase::UpperCase);
+            copy_converted(
+                &[root.join("b.txt")],
+                &root.join("out"),
+                &transform,
+                &options,
+                &mut quiet_logger(),
+            );
+        };
+
+        copy(ConflictPolicy::Error);
+        copy(ConflictPolicy::Skip);
+        assert_eq!(read("out/B.TXT").as_deref(), Some("existing"));
+        assert_eq!(read("out/B_1.TXT"), None);
+        copy(ConflictPolicy::Number);
+        assert_eq!(read("out/B_1.TXT").as_deref(), Some("b"));
+        copy(ConflictPolicy::Overwrite);
+        assert_eq!(read("out/B.TXT").as_deref(), Some("b"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_same_entry() {
+        let root = env::temp_dir().join("ram-utils-same-entry-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("foo"), "foo").unwrap();
+        fs::write(root.join("FOO"), "FOO").unwrap();
+        fs::hard_link(root.join("foo"), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(root.join("foo"), root.join("symlink")).unwrap();
+
+        assert!(is_same_entry(&root.join("foo"), &root.join("foo")).unwrap());
+        assert!(is_same_entry(&root.join("foo"), &root.join("./link")).unwrap());
+        assert!(!is_same_entry(&root.join("foo"), &root.join("FOO")).unwrap());
+        assert!(!is_same_entry(&root.join("foo"), &root.join("symlink")).unwrap());
+        assert!(is_same_entry(&root.join("foo"), &root.join("missing")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_plan_entries_json() {
+        let root = env::temp_dir().join("ram-utils-convert-test-plan-json");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        File::create(root.join("dir/file.txt")).unwrap();
+
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let plan_json = || {
+            let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+            let json = serde_json::to_string(&plan_entries(&plan).unwrap()).unwrap();
+            serde_json::from_str::<Vec<serde_json::Value>>(&json).unwrap()
+        };
+
+        let entries = plan_json();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0]["from"],
+            root.join("dir/file.txt").to_str().unwrap()
+        );
+        assert_eq!(
+            entries[0]["to"],
+            root.join("dir/FILE.TXT").to_str().unwrap()
+        );
+        assert_eq!(entries[0]["kind"], "file");
+        assert_eq!(entries[0]["valid_utf8"], true);
+        assert_eq!(entries[1]["from"], root.join("dir").to_str().unwrap());
+        assert_eq!(entries[1]["to"], root.join("DIR").to_str().unwrap());
+        assert_eq!(entries[1]["kind"], "dir");
+
+        // Nothing is renamed
+        assert!(root.join("dir/file.txt").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            // Names that aren't UTF-8 aren't converted, but may be part of a converted path
+            let invalid = root.join(OsStr::from_bytes(b"caf\xe9"));
+            fs::create_dir(&invalid).unwrap();
+            File::create(invalid.join("file.txt")).unwrap();
+            std::os::unix::fs::symlink("dir", root.join("link")).unwrap();
+
+            let entries = plan_json();
+            assert_eq!(entries.len(), 4);
+            assert_eq!(
+                entries[0]["from"],
+                root.join("caf\u{fffd}/file.txt").to_str().unwrap()
+            );
+            assert_eq!(entries[0]["valid_utf8"], false);
+            assert_eq!(entries[3]["from"], root.join("link").to_str().unwrap());
+            assert_eq!(entries[3]["kind"], "symlink");
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_renameable_path() {
+        let root = env::temp_dir().join("ram-utils-renameable-path-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+
+        let dir = root.join("dir");
+        let trailing = PathBuf::from(format!("{}/", dir.display()));
+        assert_eq!(renameable_path(&trailing).unwrap(), dir);
+        assert_eq!(renameable_path(&dir.join(".")).unwrap(), dir);
+        assert_eq!(
+            renameable_path(&dir.join("sub/..")).unwrap(),
+            fs::canonicalize(&dir).unwrap()
+        );
+        assert_eq!(
+            renameable_path(Path::new(".")).unwrap(),
+            env::current_dir().unwrap().canonicalize().unwrap()
+        );
+
+        let error = renameable_path(Path::new("/")).unwrap_err();
+        assert_eq!(error.to_string(), "/ has no name to convert");
+
+        // -- A directory passed with a trailing slash is planned like any other
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let mut log = quiet_logger();
+        let plan = plan_entry(&trailing, &transform, &ConvertOptions::default(), &mut log);
+        assert_eq!(plan, vec![(dir.clone(), root.join("DIR"))]);
+
+        // -- The filesystem root is reported rather than skipped silently
+        let plan = plan_entry(
+            Path::new("/"),
+            &transform,
+            &ConvertOptions::default(),
+            &mut log,
+        );
+        assert!(plan.is_empty());
+        assert_eq!(log.summary.errors, 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_dir_plan_snapshot() {
+        let root = env::temp_dir().join("ram-utils-convert-test-snapshot");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(&root).unwrap();
+        for name in ["c", "a", "b", "ab", "AC"].iter() {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions::default();
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+
+        // Every entry needing a rename is planned exactly once, in name order
+        let from: Vec<PathBuf> = plan.iter().map(|p| p.0.clone()).collect();
+        assert_eq!(
+            from,
+            [
+                root.join("a"),
+                root.join("ab"),
+                root.join("b"),
+                root.join("c")
+            ]
+        );
+
+        convert_children(&root, &transform, &options, &mut quiet_logger()).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["A", "AB", "AC", "B", "C"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_children_ignores() {
+        let root = env::temp_dir().join("ram-utils-convert-test-ignores");
+
+        let lower_dir = root.join("test");
+        let upper_dir = root.join("TEST");
+
+        let lower_file = &lower_dir.with_extension("file");
+        let upper_file = &upper_dir.with_extension("FILE");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        // -- Test ignore file
+        fs::create_dir_all(&lower_dir).unwrap();
+        fs::File::create(lower_file).unwrap();
+
+        let options = ConvertOptions {
+            ignore_files: true,
+            ..Default::default()
+        };
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &options,
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(upper_dir.exists());
+        assert!(lower_file.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+
+        // -- Test ignore directory
+        fs::create_dir_all(&lower_dir).unwrap();
+        fs::File::create(lower_file).unwrap();
+
+        let options = ConvertOptions {
+            ignore_dirs: true,
+            ..Default::default()
+        };
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &options,
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(lower_dir.exists());
+        assert!(upper_file.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_dir_recursive() {
+        let root = env::temp_dir().join("ram-utils-convert-test-recursive");
+        let lower_file = root.join("test").join("bar").join("baz.file");
+        let upper_file = root.join("TEST").join("BAR").join("BAZ.FILE");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(lower_file.parent().unwrap()).unwrap();
+        fs::File::create(lower_file).unwrap();
+
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(upper_file.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_children_only_ext() {
+        let root = env::temp_dir().join("ram-utils-convert-test-only-ext");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(root.join("album")).unwrap();
+        File::create(root.join("album").join("song.mp3")).unwrap();
+        File::create(root.join("album").join("other.MP3")).unwrap();
+        File::create(root.join("album").join("cover.jpg")).unwrap();
+        File::create(root.join("notes")).unwrap();
+
+        let options = ConvertOptions {
+            only_ext: vec![String::from("mp3")],
+            ..Default::default()
+        };
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &options,
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        // Directories are still traversed and converted
+        assert!(root.join("ALBUM").join("SONG.MP3").exists());
+        assert!(root.join("ALBUM").join("OTHER.MP3").exists());
+        assert!(root.join("ALBUM").join("cover.jpg").exists());
+        assert!(root.join("notes").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_children_ignore_ext() {
+        let root = env::temp_dir().join("ram-utils-convert-test-ignore-ext");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(root.join("links")).unwrap();
+        File::create(root.join("links").join("site.lnk")).unwrap();
+        File::create(root.join("links").join("other.LNK")).unwrap();
+        File::create(root.join("links").join("page.url")).unwrap();
+        File::create(root.join("links").join("notes.txt")).unwrap();
+
+        let options = ConvertOptions {
+            ignore_ext: vec![String::from("lnk")],
+            ..Default::default()
+        };
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &options,
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(root.join("LINKS").join("site.lnk").exists());
+        assert!(root.join("LINKS").join("other.LNK").exists());
+        assert!(root.join("LINKS").join("PAGE.URL").exists());
+        assert!(root.join("LINKS").join("NOTES.TXT").exists());
+
+        // -- Test ignore wins over only
+        let options = ConvertOptions {
+            only_ext: vec![String::from("lnk"), String::from("url")],
+            ignore_ext: vec![String::from("lnk")],
+            ..Default::default()
+        };
+        assert!(!is_selected_file(Path::new("site.lnk"), &options));
+        assert!(is_selected_file(Path::new("page.URL"), &options));
+        assert!(!is_selected_file(Path::new("notes.txt"), &options));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_children_keep_going() {
+        let root = env::temp_dir().join("ram-utils-convert-keep-going-test");
+        let reset = || {
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            for name in &["a.txt", "b.txt", "B.TXT", "c.txt"] {
+                File::create(root.join(name)).unwrap();
+            }
+        };
+        let upper = Transform::Case(LetterCase::UpperCase);
+
+        // -- The conflict of b.txt ends the run
+        reset();
+        let result = convert_children(
+            &root,
+            &upper,
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        );
+        assert!(result.is_err());
+
+        // -- Unless keeping going, which renames the others and reports the failure
+        reset();
+        let options = ConvertOptions {
+            keep_going: true,
+            ..ConvertOptions::default()
+        };
+        let err = SharedBuffer::default();
+        let mut log = Logger::with_writers(
+            Verbosity::Quiet,
+            Box::new(io::sink()),
+            Box::new(err.clone()),
+        );
+        convert_children(&root, &upper, &options, &mut log).unwrap();
+        assert!(has_exact_name(&root.join("A.TXT")));
+        assert!(has_exact_name(&root.join("b.txt")));
+        assert!(has_exact_name(&root.join("C.TXT")));
+        assert_eq!(log.summary.errors, 1);
+        assert_eq!(log.failures.len(), 1);
+        assert_eq!(log.failures[0].0, root.join("b.txt"));
+
+        log.report_failures();
+        assert!(err.contents().contains("Failed to convert 1 entry:"));
+        assert!(err.contents().contains("b.txt"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_children_hidden() {
+        let root = env::temp_dir().join("ram-utils-convert-test-hidden");
+        let lower_git = root.join(".git");
+        let upper_git = root.join(".GIT");
+        let lower_head = lower_git.join("head");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        // -- Test hidden entries are skipped by default
+        fs::create_dir_all(&lower_git).unwrap();
+        fs::File::create(&lower_head).unwrap();
+
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(lower_head.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+
+        // -- Test hidden entries are converted when included
+        fs::create_dir_all(&lower_git).unwrap();
+        fs::File::create(&lower_head).unwrap();
+
+        let options = ConvertOptions {
+            walk: WalkOptions {
+                include_hidden: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        convert_children(
+            &root,
+            &Transform::Case(LetterCase::UpperCase),
+            &options,
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(upper_git.join("HEAD").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_respect_gitignore() {
+        let root = env::temp_dir().join("ram-utils-gitignore-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\ntarget/\n").unwrap();
+        fs::write(root.join("nested/.gitignore"), "!keep.log\n").unwrap();
+        File::create(root.join("notes.txt")).unwrap();
+        File::create(root.join("debug.log")).unwrap();
+        File::create(root.join("nested/error.log")).unwrap();
+        File::create(root.join("nested/keep.log")).unwrap();
+        File::create(root.join("target/app.bin")).unwrap();
+
+        let walk = WalkOptions {
+            respect_gitignore: true,
+            ..Default::default()
+        };
+
+        // -- Ignored files aren't counted, except where a nested `.gitignore` re-includes them
+        let extensions = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(extensions.get("txt"), Some(&1));
+        assert_eq!(extensions.get("log"), Some(&1));
+        assert_eq!(extensions.get("bin"), None);
+
+        // -- ... or renamed
+        let options = ConvertOptions {
+            walk,
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+        let renamed: Vec<&Path> = plan
+            .iter()
+            .map(|(from, _)| from.strip_prefix(&root).unwrap())
+            .collect();
+        assert_eq!(
+            renamed,
+            vec![
+                Path::new("nested/keep.log"),
+                Path::new("nested"),
+                Path::new("notes.txt"),
+            ]
+        );
+
+        // -- Off by default
+        let extensions =
+            find_unique_extensions(&root, &WalkOptions::default(), &[], &mut quiet_logger())
+                .unwrap();
+        assert_eq!(extensions.get("log"), Some(&3));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_is_hidden() {
+        assert!(is_hidden(Path::new(".git")));
+        assert!(is_hidden(Path::new("/home/ralph/.config")));
+        assert!(!is_hidden(Path::new("/home/ralph/.config/foo")));
+        assert!(!is_hidden(Path::new("foo.txt")));
+        assert!(!is_hidden(Path::new(".")));
+    }
+
+    #[test]
+    fn test_find_extensions() {
+        let root = env::temp_dir().join("ram-utils-test-find-extensions");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        let extensions = ["foo", "bar", "baz123"];
+        for ext in extensions {
+            let mut filepath = root.join("testfile");
+            filepath.set_extension(ext);
+            fs::create_dir_all(filepath.parent().unwrap()).unwrap();
+            fs::File::create(&filepath).unwrap();
+        }
+
+        let exts = find_unique_extensions(&root, &WalkOptions::default(), &[], &mut quiet_logger())
+            .unwrap();
+        for (ext, count) in exts.iter() {
+            assert!(extensions.contains(&ext.as_str()));
+            assert_eq!(*count, 1);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_flatten() {
+        let root = env::temp_dir().join("ram-utils-test-flatten");
+        let src = root.join("src");
+        let dst = root.join("dst");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(src.join("a").join("b")).unwrap();
+        fs::create_dir_all(src.join("c")).unwrap();
+        for dir in ["", "a", "a/b", "c"].iter() {
+            File::create(src.join(dir).join("photo.jpg")).unwrap();
+        }
+        File::create(src.join("a").join("notes")).unwrap();
+
+        let moves = flatten(&src, &dst, &FlattenOptions::default(), &mut quiet_logger()).unwrap();
+        assert_eq!(moves.len(), 5);
+
+        let mut names: Vec<String> = fs::read_dir(&dst)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "notes",
+                "photo.jpg",
+                "photo_1.jpg",
+                "photo_2.jpg",
+                "photo_3.jpg"
+            ]
+        );
+
+        // Without pruning the emptied directories are left behind
+        assert!(src.join("a").join("b").is_dir());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_organize() {
+        let root = env::temp_dir().join("ram-utils-organize-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("pdf")).unwrap();
+        fs::create_dir_all(root.join("nested")).unwrap();
+        for name in &[
+            "a.pdf",
+            "B.PDF",
+            "pdf/a.pdf",
+            "photo.jpg",
+            "README",
+            "nested/c.txt",
+        ] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        // -- A dry run leaves everything in place
+        let options = OrganizeOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let moves = organize(&root, &options, &mut quiet_logger()).unwrap();
+        assert_eq!(moves.len(), 4);
+        assert!(!root.join("jpg").exists());
+
+        let moves = organize(&root, &OrganizeOptions::default(), &mut quiet_logger()).unwrap();
+        let targets: Vec<_> = moves
+            .iter()
+            .map(|(_, to)| to.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                PathBuf::from("pdf/B.PDF"),
+                PathBuf::from("none/README"),
+                PathBuf::from("pdf/a_1.pdf"),
+                PathBuf::from("jpg/photo.jpg"),
+            ]
+        );
+        assert!(root.join("pdf/a.pdf").exists());
+
+        // -- Files in subdirectories stay where they are
+        assert!(root.join("nested/c.txt").exists());
+
+        // -- By date, into year and month directories of the modified time
+        let set_modified = |name: &str, year, month| {
+            let modified = Local.with_ymd_and_hms(year, month, 15, 12, 0, 0).unwrap();
+            File::create(root.join("nested").join(name))
+                .unwrap()
+                .set_modified(modified.into())
+                .unwrap();
+        };
+        set_modified("c.txt", 2023, 5);
+        set_modified("d.txt", 2023, 11);
+        set_modified("e.txt", 2024, 1);
+
+        let options = OrganizeOptions {
+            by: OrganizeBy::Date,
+            ..Default::default()
+        };
+        organize(&root.join("nested"), &options, &mut quiet_logger()).unwrap();
+        assert!(root.join("nested/2023/05/c.txt").exists());
+        assert!(root.join("nested/2023/11/d.txt").exists());
+        assert!(root.join("nested/2024/01/e.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_flatten_dry_run_and_prune() {
+        let root = env::temp_dir().join("ram-utils-test-flatten-prune");
+        let src = root.join("src");
+        let dst = root.join("dst");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(src.join("a").join("b")).unwrap();
+        fs::create_dir_all(src.join("c")).unwrap();
+        File::create(src.join("a").join("b").join("song.mp3")).unwrap();
+        File::create(src.join("c").join("song.mp3")).unwrap();
+        File::create(src.join("c").join(".keep")).unwrap();
+
+        // -- Test dry run leaves everything in place
+        let options = FlattenOptions {
+            dry_run: true,
+            prune: true,
+            ..Default::default()
+        };
+        let moves = flatten(&src, &dst, &options, &mut quiet_logger()).unwrap();
+
+        assert_eq!(moves.len(), 2);
+        assert!(!dst.exists());
+        assert!(src.join("a").join("b").join("song.mp3").exists());
+
+        // -- Test prune removes emptied directories but keeps non-empty ones
+        let options = FlattenOptions {
+            prune: true,
+            ..Default::default()
+        };
+        flatten(&src, &dst, &options, &mut quiet_logger()).unwrap();
+
+        assert!(dst.join("song.mp3").exists());
+        assert!(dst.join("song_1.mp3").exists());
+        assert!(!src.join("a").exists());
+        assert!(src.join("c").join(".keep").exists());
+
+        // -- Test flattening into the source directory itself
+        fs::create_dir_all(dst.join("d").join("e")).unwrap();
+        File::create(dst.join("d").join("e").join("song.mp3")).unwrap();
+        let moves = flatten(&dst, &dst, &options, &mut quiet_logger()).unwrap();
+
+        assert_eq!(
+            moves,
+            vec![(dst.join("d/e/song.mp3"), dst.join("song_2.mp3"))]
+        );
+        assert!(dst.join("song.mp3").exists());
+        assert!(!dst.join("d").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut progress = Progress::new(200, Box::new(io::sink()));
+        assert_eq!(
+            progress.render(Duration::from_secs(0)),
+            format!("[>{}] 0/200 (0%) ETA ?", " ".repeat(29))
+        );
+
+        progress.done = 50;
+        assert_eq!(
+            progress.render(Duration::from_secs(10)),
+            format!(
+                "[{}>{}] 50/200 (25%) ETA 30s",
+                "=".repeat(7),
+                " ".repeat(22)
+            )
+        );
+
+        progress.done = 200;
+        assert_eq!(
+            progress.render(Duration::from_secs(40)),
+            format!("[{}] 200/200 (100%) ETA 0s", "=".repeat(30))
+        );
+
+        // -- Quick runs never draw the bar
+        let out = SharedBuffer::default();
+        let mut progress = Progress::new(3, Box::new(out.clone()));
+        for _ in 0..3 {
+            progress.inc();
+        }
+        progress.finish();
+        assert_eq!(out.contents(), "");
+
+        assert_eq!(format_duration(5), "5s");
+        assert_eq!(format_duration(185), "3m05s");
+        assert_eq!(format_duration(3720), "1h02m");
+    }
+
+    #[test]
+    fn test_skip_reasons() {
+        let root = env::temp_dir().join("ram-utils-skip-reasons-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+        for name in &[".hidden", "notes.tmp", "song.mp3", "photo.jpg", "Done.TXT"] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let out = SharedBuffer::default();
+        let mut log = Logger::with_writers(
+            Verbosity::VeryVerbose,
+            Box::new(out.clone()),
+            Box::new(io::sink()),
+        );
+        let options = ConvertOptions {
+            recursive: true,
+            ignore_ext: vec!["mp3".to_string()],
+            walk: WalkOptions {
+                exclude: glob_set(vec!["*.tmp"]).unwrap(),
+                exclude_dirs: glob_set(vec!["node_modules"]).unwrap(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::UpperCase);
+        convert_dir_plan(&root, &transform, &options, &mut log).unwrap();
+
+        let contents = out.contents();
+        for (name, reason) in &[
+            (".hidden", "hidden"),
+            ("node_modules", "excluded directory"),
+            ("notes.tmp", "excluded"),
+            ("song.mp3", "filtered by extension"),
+        ] {
+            let line = format!("Skipping {:?}, {}\n", root.join(name), reason);
+            assert!(contents.contains(&line), "{}", contents);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_prune_empty_dirs() {
+        let root = env::temp_dir().join("ram-utils-prune-empty-test");
+        let _ = fs::remove_dir_all(&root);
+        for dir in &["a/b/c", "a/d", "keep/empty", "hidden/.git", ".cache"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        File::create(root.join("keep/file.txt")).unwrap();
+
+        // -- A dry run removes nothing
+        let removed =
+            prune_empty_dirs(&root, true, &WalkOptions::default(), &mut quiet_logger()).unwrap();
+        assert_eq!(removed.len(), 5);
+        assert!(root.join("a/b/c").exists());
+
+        let removed =
+            prune_empty_dirs(&root, false, &WalkOptions::default(), &mut quiet_logger()).unwrap();
+        let mut removed: Vec<_> = removed
+            .iter()
+            .map(|dir| dir.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        removed.sort();
+        assert_eq!(
+            removed,
+            ["a", "a/b", "a/b/c", "a/d", "keep/empty"]
+                .iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        );
+
+        // -- Hidden directories aren't looked into, so they and their parents stay
+        assert!(root.join("hidden/.git").exists());
+        assert!(root.join(".cache").exists());
+        assert!(root.join("keep/file.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_remove_emptied_dirs() {
+        let root = env::temp_dir().join("ram-utils-remove-emptied-test");
+        let _ = fs::remove_dir_all(&root);
+        let (one, two) = (root.join("one"), root.join("two"));
+        for dir in &[one.join("a/b"), two.join("c"), two.join("d")] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        File::create(two.join("d/keep.txt")).unwrap();
+
+        // -- Each file's emptied parents are removed up to the root it was moved within, with
+        // several roots given
+        let plan = vec![
+            (one.join("a/b/x.txt"), one.join("x.txt")),
+            (two.join("c/y.txt"), two.join("y.txt")),
+            (two.join("d/z.txt"), two.join("z.txt")),
+        ];
+        remove_emptied_dirs(&plan, &[one.clone(), two.clone()], &mut quiet_logger());
+        assert!(!one.join("a").exists());
+        assert!(!two.join("c").exists());
+        assert!(two.join("d/keep.txt").exists());
+
+        // -- The roots themselves stay, even when emptied
+        assert!(one.exists());
+        assert!(two.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_transform_filename_separators() {
+        let upper = Transform::Case(LetterCase::UpperCase);
+        let lower = Transform::Case(LetterCase::LowerCase);
+
+        // -- Test default behavior keeps separators
+        let options = ConvertOptions::default();
+        assert_eq!(
+            transform_filename("the_quick_brown.fox", &upper, &options),
+            "THE_QUICK_BROWN.FOX"
+        );
+
+        // -- Test custom separators
+        let options = ConvertOptions {
+            word_sep: Some(String::from("_")),
+            out_sep: Some(String::from("-")),
+            ..Default::default()
+        };
+        assert_eq!(
+            transform_filename("the_quick_brown.fox", &upper, &options),
+            "THE-QUICK-BROWN.FOX"
+        );
+        assert_eq!(
+            transform_filename("the quick_brown.fox", &lower, &options),
+            "the quick-brown.fox"
+        );
+
+        // -- Test multi-character boundary sets and default boundaries
+        let options = ConvertOptions {
+            word_sep: Some(String::from("_ .")),
+            out_sep: Some(String::from("-")),
+            ..Default::default()
+        };
+        assert_eq!(
+            transform_filename("My_Photo  2019.08.jpg", &lower, &options),
+            "my-photo-2019-08.jpg"
+        );
+
+        let options = ConvertOptions {
+            out_sep: Some(String::from("_")),
+            ..Default::default()
+        };
+        assert_eq!(
+            transform_filename("My Photo - 2019.jpg", &lower, &options),
+            "my_photo_2019.jpg"
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let replace = |pattern: &str, replacement: &str| Transform::Replace {
+            pattern: Regex::new(pattern).unwrap(),
+            replacement: String::from(replacement),
+        };
+        let options = ConvertOptions::default();
+
+        assert_eq!(
+            transform_filename("IMG_0042.JPG", &replace(r"IMG_(\d+)", "photo-$1"), &options),
+            "photo-0042.JPG"
+        );
+        assert_eq!(
+            transform_filename(
+                "2024-05-01 notes.txt",
+                &replace(r"(?P<y>\d{4})-(?P<m>\d\d)-(?P<d>\d\d)", "${d}.${m}.${y}"),
+                &options
+            ),
+            "01.05.2024 notes.txt"
+        );
+        assert_eq!(
+            transform_filename("a b c.txt", &replace(" ", "_"), &options),
+            "a_b_c.txt"
+        );
+
+        // -- Names that would be emptied or become paths are left alone
+        let root = Path::new("photos");
+        let to_path = replace("IMG_", "a/");
+        assert_eq!(
+            convert_path(&root.join("IMG_1.JPG"), &to_path, &options),
+            None
+        );
+        let to_empty = replace(".*", "");
+        assert_eq!(
+            convert_path(&root.join("IMG_1.JPG"), &to_empty, &options),
+            None
+        );
+
+        // -- The recursive and ignore options apply as with the other transforms
+        let root = env::temp_dir().join("ram-utils-replace-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("IMG_dir")).unwrap();
+        File::create(root.join("IMG_dir/IMG_0001.JPG")).unwrap();
+        File::create(root.join("IMG_0002.PNG")).unwrap();
+
+        let options = ConvertOptions {
+            recursive: true,
+            only_ext: vec![String::from("jpg")],
+            ..Default::default()
+        };
+        let transform = replace(r"IMG_(\d+)", "photo-$1");
+        let plan = plan_entry(&root, &transform, &options, &mut quiet_logger());
+        assert_eq!(
+            plan,
+            vec![(
+                root.join("IMG_dir/IMG_0001.JPG"),
+                root.join("IMG_dir/photo-0001.JPG")
+            )]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_split_camel_case() {
+        assert_eq!(split_camel_case("myFileName"), ["my", "File", "Name"]);
+        assert_eq!(split_camel_case("HTMLParser2"), ["HTML", "Parser2"]);
+        assert_eq!(split_camel_case("v2Final"), ["v2", "Final"]);
+        assert_eq!(split_camel_case("lower"), ["lower"]);
+        assert_eq!(split_camel_case("ÉtéÀParis"), ["Été", "À", "Paris"]);
+    }
+
+    #[test]
+    fn test_transform_filename_styles() {
+        let snake = Transform::Style(CaseStyle::Snake);
+        let kebab = Transform::Style(CaseStyle::Kebab);
+        let options = ConvertOptions::default();
+
+        assert_eq!(
+            transform_filename("MyFileName.TXT", &snake, &options),
+            "my_file_name.txt"
+        );
+        assert_eq!(
+            transform_filename("MyFileName.TXT", &kebab, &options),
+            "my-file-name.txt"
+        );
+        assert_eq!(
+            transform_filename("already_snake.txt", &snake, &options),
+            "already_snake.txt"
+        );
+        assert_eq!(
+            transform_filename("already-kebab.txt", &kebab, &options),
+            "already-kebab.txt"
+        );
+        assert_eq!(
+            transform_filename("HTML parser-v2.rs", &snake, &options),
+            "html_parser_v2.rs"
+        );
+        assert_eq!(transform_filename(".bashrc", &kebab, &options), ".bashrc");
+
+        let camel = Transform::Style(CaseStyle::Camel);
+        let pascal = Transform::Style(CaseStyle::Pascal);
+        let title = Transform::Style(CaseStyle::Title);
+        assert_eq!(
+            transform_filename("my_file name.TXT", &camel, &options),
+            "myFileName.txt"
+        );
+        assert_eq!(
+            transform_filename("my_file name.TXT", &pascal, &options),
+            "MyFileName.txt"
+        );
+        assert_eq!(
+            transform_filename("my_file name.TXT", &title, &options),
+            "My File Name.txt"
+        );
+        assert_eq!(
+            transform_filename("HTMLParser-v2.rs", &camel, &options),
+            "htmlParserV2.rs"
+        );
+        assert_eq!(
+            transform_filename("already-Title Case", &title, &options),
+            "Already Title Case"
+        );
+        assert_eq!(
+            transform_filename("ÉTÉ à paris.md", &pascal, &options),
+            "ÉtéÀParis.md"
+        );
+        assert_eq!(
+            transform_filename("myFileName", &camel, &options),
+            "myFileName"
+        );
+
+        let options = ConvertOptions {
+            keep: vec![String::from("iPhone")],
+            ..Default::default()
+        };
+        assert_eq!(
+            transform_filename("My iPhone Photos.JPG", &snake, &options),
+            "my_iPhone_photos.jpg"
+        );
+        assert_eq!(
+            transform_filename("iphone_photos.JPG", &camel, &options),
+            "iPhonePhotos.jpg"
+        );
+        assert_eq!(
+            transform_filename("my IPHONE photos.JPG", &title, &options),
+            "My iPhone Photos.jpg"
+        );
+    }
+
+    #[test]
+    fn test_is_cross_device() {
+        assert!(is_cross_device(&io::Error::from(
+            io::ErrorKind::CrossesDevices
+        )));
+        assert!(!is_cross_device(&io::Error::from(io::ErrorKind::NotFound)));
+
+        #[cfg(target_os = "linux")]
+        assert!(is_cross_device(&io::Error::from_raw_os_error(18))); // EXDEV
+    }
+
+    #[test]
+    fn test_copy_path() {
+        let root = env::temp_dir().join("ram-utils-test-copy-path");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        let src = root.join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("nested").join("file.txt"), "contents").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = fs::Permissions::from_mode(0o640);
+            fs::set_permissions(src.join("nested/file.txt"), permissions).unwrap();
+        }
+
+        let modified = fs::metadata(src.join("nested/file.txt"))
+            .unwrap()
+            .modified()
+            .unwrap();
+
+        copy_path(&src, &root.join("dst")).unwrap();
+
+        let copied = root.join("dst").join("nested").join("file.txt");
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "contents");
+        assert_eq!(fs::metadata(&copied).unwrap().modified().unwrap(), modified);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&copied).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_trim_whitespace() {
+        assert_eq!(trim_whitespace("  leading.txt", false), "leading.txt");
+        assert_eq!(trim_whitespace("trailing.txt  ", false), "trailing.txt");
+        assert_eq!(trim_whitespace("my  \tfile.txt", false), "my file.txt");
+        assert_eq!(trim_whitespace("  my  file .txt", false), "my file .txt");
+        assert_eq!(trim_whitespace("  my  file .txt", true), "my file.txt");
+        assert_eq!(trim_whitespace(" .hidden", true), ".hidden");
+    }
+
+    #[test]
+    fn test_convert_trim() {
+        let root = env::temp_dir().join("ram-utils-convert-test-trim");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(root.join(" my  dir ")).unwrap();
+        File::create(root.join(" my  dir ").join("doubled  space.txt")).unwrap();
+        fs::create_dir_all(root.join("clean")).unwrap();
+        File::create(root.join("clean").join("clean.txt")).unwrap();
+
+        // Renaming an entry updates its parent directory's modified time
+        let clean_modified = fs::metadata(root.join("clean"))
+            .unwrap()
+            .modified()
+            .unwrap();
+
+        let transform = Transform::Trim { before_ext: false };
+        convert_children(
+            &root,
+            &transform,
+            &ConvertOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        assert!(root.join("my dir").join("doubled space.txt").exists());
+
+        // Already clean names are not renamed
+        let modified = fs::metadata(root.join("clean"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(clean_modified, modified);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_read_paths() {
+        let paths = read_paths("one.txt\r\ntwo dir/three.txt\n\n".as_bytes(), false).unwrap();
+        assert_eq!(
+            paths,
+            [PathBuf::from("one.txt"), PathBuf::from("two dir/three.txt")]
+        );
+
+        let paths = read_paths("one\ntwo.txt\0three.txt\0".as_bytes(), true).unwrap();
+        assert_eq!(
+            paths,
+            [PathBuf::from("one\ntwo.txt"), PathBuf::from("three.txt")]
+        );
+    }
+
+    #[test]
+    fn test_convert_entries_from_reader() {
+        let root = env::temp_dir().join("ram-utils-convert-test-stdin");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(root.join("dir")).unwrap();
+        File::create(root.join("one.txt")).unwrap();
+        File::create(root.join("two.txt")).unwrap();
+        File::create(root.join("dir").join("three.txt")).unwrap();
+
+        let input = format!(
+            "{}\n{}\n",
+            root.join("one.txt").display(),
+            root.join("dir").display()
+        );
+        let paths = read_paths(input.as_bytes(), false).unwrap();
+
+        // -- Test directories aren't recursed into without -r
+        for path in &paths {
+            convert_entry(
+                path,
+                &Transform::Case(LetterCase::UpperCase),
+                &ConvertOptions::default(),
+                &mut quiet_logger(),
+            );
+        }
+
+        assert!(root.join("ONE.TXT").exists());
+        assert!(root.join("two.txt").exists());
+        assert!(root.join("DIR").join("three.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_stem_ext_case() {
+        let transform = |stem, ext, filename| {
+            let options = ConvertOptions {
+                keep: vec![String::from("NASA")],
+                ..Default::default()
+            };
+            transform_filename(filename, &Transform::StemExt { stem, ext }, &options)
+        };
+        let (upper, lower) = (Some(LetterCase::UpperCase), Some(LetterCase::LowerCase));
+
+        assert_eq!(transform(upper, lower, "Report.PDF"), "REPORT.pdf");
+        assert_eq!(transform(lower, upper, "Report.pdf"), "report.PDF");
+        assert_eq!(transform(upper, upper, "Report.pdf"), "REPORT.PDF");
+        assert_eq!(transform(lower, lower, "Report.PDF"), "report.pdf");
+
+        // -- `keep` leaves either part as is
+        assert_eq!(transform(None, lower, "Report.PDF"), "Report.pdf");
+        assert_eq!(transform(upper, None, "Report.Pdf"), "REPORT.Pdf");
+        assert_eq!(transform(None, None, "Report.Pdf"), "Report.Pdf");
+
+        // -- Kept words only apply to the stem
+        assert_eq!(
+            transform(lower, lower, "Nasa Launch.TXT"),
+            "NASA launch.txt"
+        );
+        assert_eq!(transform(lower, upper, ".bashrc"), ".bashrc");
+    }
+
+    #[test]
+    fn test_mapping() {
+        let root = env::temp_dir().join("ram-utils-mapping-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+
+        let path = |name: &str| root.join(name).display().to_string();
+        let csv = format!(
+            "from,to,kind\n{},\"{}\"\n{},{}\n",
+            path("a.txt"),
+            path("c, with comma.txt"),
+            path("b.txt"),
+            path("a.txt")
+        );
+        let rows = read_mapping(csv.as_bytes(), b',').unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].to, root.join("c, with comma.txt"));
+        assert_eq!(rows[1].from, root.join("b.txt"));
+
+        // -- b.txt can take the name of a.txt, which is renamed away first
+        assert!(check_mapping(&rows, ConflictPolicy::Error).is_empty());
+
+        let tsv = format!("{}\t{}\n", path("a.txt"), path("b.txt"));
+        let rows = read_mapping(tsv.as_bytes(), b'\t').unwrap();
+        assert_eq!(check_mapping(&rows, ConflictPolicy::Error).len(), 1);
+        assert!(check_mapping(&rows, ConflictPolicy::Overwrite).is_empty());
+
+        let tsv = format!(
+            "{}\t{}\n{}\t{}\n",
+            path("missing.txt"),
+            path("d.txt"),
+            path("b.txt"),
+            path("d.txt")
+        );
+        let rows = read_mapping(tsv.as_bytes(), b'\t').unwrap();
+        assert_eq!(
+            check_mapping(&rows, ConflictPolicy::Error),
+            vec![
+                format!("Line 1: {:?} does not exist", root.join("missing.txt")),
+                format!(
+                    "Line 2: {:?} is already the target of line 1",
+                    root.join("d.txt")
+                ),
+            ]
+        );
+
+        assert!(read_mapping("only-one-column\n".as_bytes(), b',').is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_despace() {
+        assert_eq!(despace("My Cool File.txt", '_'), "My_Cool_File.txt");
+        assert_eq!(despace("My Cool File.txt", '-'), "My-Cool-File.txt");
+        assert_eq!(despace("  a \t b\n c .md", '_'), "a_b_c.md");
+        assert_eq!(despace("no_spaces.txt", '_'), "no_spaces.txt");
+
+        let transform = |lower| Transform::Despace {
+            separator: '_',
+            lower,
+        };
+        let options = ConvertOptions::default();
+        assert_eq!(
+            transform_filename("My Cool File.TXT", &transform(false), &options),
+            "My_Cool_File.TXT"
+        );
+        assert_eq!(
+            transform_filename("My Cool File.TXT", &transform(true), &options),
+            "my_cool_file.txt"
+        );
+    }
+
+    #[test]
+    fn test_fix_duplicate_ext() {
+        assert_eq!(fix_duplicate_ext("report.pdf.pdf"), "report.pdf");
+        assert_eq!(fix_duplicate_ext("photo.JPG.jpg"), "photo.jpg");
+        assert_eq!(fix_duplicate_ext("photo.jpg.JPG.jpg"), "photo.jpg");
+        assert_eq!(fix_duplicate_ext("my.notes.txt.txt"), "my.notes.txt");
+
+        // -- Names without a repeated extension are left as is, case included
+        assert_eq!(fix_duplicate_ext("archive.tar.gz"), "archive.tar.gz");
+        assert_eq!(fix_duplicate_ext("PHOTO.JPG"), "PHOTO.JPG");
+        assert_eq!(fix_duplicate_ext(".jpg.jpg"), ".jpg.jpg");
+        assert_eq!(fix_duplicate_ext("README"), "README");
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("what? <draft>.txt ", "_"), "what_ _draft_.txt");
+        assert_eq!(sanitize("a:b|c*d.txt", ""), "abcd.txt");
+        assert_eq!(sanitize("tab\there\u{7}.md", "-"), "tab-here-.md");
+        assert_eq!(sanitize("notes. . .", "_"), "notes");
+        assert_eq!(sanitize("back\\slash", "_"), "back_slash");
+        assert_eq!(sanitize("plain.txt", "_"), "plain.txt");
+        assert_eq!(sanitize(".hidden", "_"), ".hidden");
+
+        // -- Nothing would be left
+        assert_eq!(sanitize("...", "_"), "...");
+        assert_eq!(sanitize("??", ""), "??");
+
+        // -- Through the transform, whole names including the extension are sanitized
+        let transform = Transform::Sanitize {
+            replacement: "_".to_string(),
+        };
+        assert_eq!(
+            transform_filename(
+                "report: final?.pdf.",
+                &transform,
+                &ConvertOptions::default()
+            ),
+            "report_ final_.pdf"
+        );
+    }
+
+    #[test]
+    fn test_strip_diacritics() {
+        assert_eq!(strip_diacritics("café résumé.txt", ""), "cafe resume.txt");
+        assert_eq!(strip_diacritics("Ångström Zoë.PDF", ""), "Angstrom Zoe.PDF");
+        assert_eq!(strip_diacritics("Straße Øre.jpg", ""), "Strasse Ore.jpg");
+        assert_eq!(strip_diacritics("plain.txt", ""), "plain.txt");
+
+        // -- Decomposed input is handled the same
+        assert_eq!(strip_diacritics("cafe\u{301}.txt", ""), "cafe.txt");
+
+        // -- Accents in the extension are stripped too
+        assert_eq!(strip_diacritics("notes.tèxt", ""), "notes.text");
+
+        // -- Other scripts are dropped or replaced, one placeholder per run
+        assert_eq!(
+            strip_diacritics("report 報告 2023.txt", ""),
+            "report  2023.txt"
+        );
+        assert_eq!(
+            strip_diacritics("report 報告 2023.txt", "_"),
+            "report _ 2023.txt"
+        );
+        assert_eq!(strip_diacritics("Привет мир.txt", "_"), "_ _.txt");
+        assert_eq!(strip_diacritics("🎉.png", "x"), "x.png");
+
+        // -- Names that would be left without a stem are kept
+        assert_eq!(strip_diacritics("Привет.txt", ""), "Привет.txt");
+        assert_eq!(strip_diacritics("日本", ""), "日本");
+    }
+
+    #[test]
+    fn test_transform_normalized() {
+        let nfc = "caf\u{e9}.txt";
+        let nfd = "cafe\u{301}.txt";
+        let upper = Transform::Case(LetterCase::UpperCase);
+        let options = |normalize| ConvertOptions {
+            normalize,
+            ..Default::default()
+        };
+
+        // -- Both forms convert to the same name once normalized
+        for name in &[nfc, nfd] {
+            let converted = transform_filename(name, &upper, &options(Some(Normalization::Nfc)));
+            assert_eq!(converted, "CAF\u{c9}.TXT");
+            let converted = transform_filename(name, &upper, &options(Some(Normalization::Nfd)));
+            assert_eq!(converted, "CAFE\u{301}.TXT");
+        }
+
+        // -- Names keep their form by default
+        assert_eq!(
+            transform_filename(nfd, &upper, &options(None)),
+            "CAFE\u{301}.TXT"
+        );
+
+        // -- Normalizing alone only changes names in the other form
+        let to_nfc = Transform::Normalize(Normalization::Nfc);
+        assert_eq!(transform_filename(nfd, &to_nfc, &options(None)), nfc);
+        assert_eq!(transform_filename(nfc, &to_nfc, &options(None)), nfc);
+        assert_eq!(
+            transform_filename(
+                nfc,
+                &Transform::Normalize(Normalization::Nfd),
+                &options(None)
+            ),
+            nfd
+        );
+
+        // -- `ß` upper cases to `SS`, changing the length of the name
+        let root = env::temp_dir().join("ram-utils-normalize-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        File::create(root.join(nfd)).unwrap();
+        File::create(root.join("stra\u{df}e.txt")).unwrap();
+
+        let options = ConvertOptions {
+            recursive: true,
+            normalize: Some(Normalization::Nfc),
+            ..Default::default()
+        };
+        convert_children(&root, &upper, &options, &mut quiet_logger()).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["CAF\u{c9}.TXT", "STRASSE.TXT"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("1.5M").unwrap(), 1572864);
+        assert_eq!(parse_size("2gb").unwrap(), 2 << 30);
+        assert_eq!(parse_size("1KiB").unwrap(), 1024);
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("1.2.3K").is_err());
+        assert!(parse_size("-1K").is_err());
+        assert!(parse_size("10 apples").is_err());
+    }
+
+    #[test]
+    fn test_size_filters() {
+        let root = env::temp_dir().join("ram-utils-size-filter-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("tiny.txt"), vec![0; 10]).unwrap();
+        fs::write(root.join("dir/medium.jpg"), vec![0; 2048]).unwrap();
+        fs::write(root.join("large.mp4"), vec![0; 8192]).unwrap();
+
+        let walk = WalkOptions {
+            min_size: Some(1024),
+            max_size: Some(4096),
+            ..Default::default()
+        };
+
+        let extensions = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions["jpg"], 1);
+
+        // -- Directories aren't filtered by size, only the files in them
+        let options = ConvertOptions {
+            walk,
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                (root.join("dir/medium.jpg"), root.join("dir/MEDIUM.JPG")),
+                (root.join("dir"), root.join("DIR")),
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        assert_eq!(parse_time("7d", now).unwrap(), now - day * 7);
+        assert_eq!(parse_time("24h", now).unwrap(), now - day);
+        assert_eq!(parse_time("2w", now).unwrap(), now - day * 14);
+
+        let date = Local.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            parse_time("2023-01-01", now).unwrap(),
+            SystemTime::from(date)
+        );
+
+        assert!(parse_time("", now).is_err());
+        assert!(parse_time("7", now).is_err());
+        assert!(parse_time("d", now).is_err());
+        assert!(parse_time("7 days", now).is_err());
+        assert!(parse_time("2023-13-01", now).is_err());
+    }
+
+    #[test]
+    fn test_modified_time_filters() {
+        let root = env::temp_dir().join("ram-utils-mtime-filter-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+        for (name, age) in &[("new.txt", 1), ("month.jpg", 30), ("old.png", 400)] {
+            let file = File::create(root.join(name)).unwrap();
+            file.set_modified(now - day * *age).unwrap();
+        }
+
+        let extensions = |newer_than, older_than| {
+            let walk = WalkOptions {
+                newer_than,
+                older_than,
+                ..Default::default()
+            };
+            let mut exts: Vec<String> =
+                find_unique_extensions(&root, &walk, &[], &mut quiet_logger())
+                    .unwrap()
+                    .into_keys()
+                    .collect();
+            exts.sort();
+            exts
+        };
+
+        assert_eq!(extensions(Some(now - day * 7), None), vec!["txt"]);
+        assert_eq!(extensions(None, Some(now - day * 7)), vec!["jpg", "png"]);
+        assert_eq!(
+            extensions(Some(now - day * 365), Some(now - day * 7)),
+            vec!["jpg"]
+        );
+
+        let options = ConvertOptions {
+            walk: WalkOptions {
+                older_than: Some(now - day * 365),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+        assert_eq!(plan, vec![(root.join("old.png"), root.join("OLD.PNG"))]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_convert_summary() {
+        let root = env::temp_dir().join("ram-utils-convert-test-summary");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::create_dir_all(root.join("DONE")).unwrap();
+        File::create(root.join("dir").join("one.txt")).unwrap();
+        File::create(root.join("two.txt")).unwrap();
+        File::create(root.join("THREE.TXT")).unwrap();
+
+        let mut log = quiet_logger();
+        let options = ConvertOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::UpperCase);
+        convert_entry(&root.join("missing"), &transform, &options, &mut log);
+        convert_children(&root, &transform, &options, &mut log).unwrap();
+
+        assert_eq!(
+            log.summary,
+            Summary {
+                files: 2,
+                dirs: 1,
+                skipped: 2,
+                errors: 1,
+            }
+        );
+        assert_eq!(
+            log.summary.to_string(),
+            "Renamed 2 files, 1 dir; skipped 2; 1 error"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_write_extensions() {
+        let mut extensions = HashMap::new();
+        extensions.insert(String::from("jpg"), 320);
+        extensions.insert(String::from("txt"), 5);
+        extensions.insert(String::from("docx"), 12);
+
+        // -- Test alphabetical order and alignment
+        let mut buffer = termcolor::Buffer::no_color();
+        write_extensions(&mut buffer, &extensions, &ExtensionSort::Name, None).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+        assert!(!output.contains('\x1b'));
+        assert_eq!(
+            output,
+            "docx  12 files ( 3.6%)\n\
+             jpg  320 files (95.0%)\n\
+             txt    5 files ( 1.5%)\n\
+             Total: 337 files\n"
+        );
+
+        // -- Test count order
+        let mut buffer = termcolor::Buffer::no_color();
+        write_extensions(&mut buffer, &extensions, &ExtensionSort::Count, None).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+        assert!(output.starts_with("jpg  320 files (95.0%)\ndocx  12 files ( 3.6%)\n"));
+
+        // -- Test sizes, in bytes or human readable
+        let mut sizes = ExtensionSizes {
+            sizes: HashMap::new(),
+            human: false,
+        };
+        sizes.sizes.insert(String::from("jpg"), 3 * 1024 * 1024);
+        sizes.sizes.insert(String::from("txt"), 100);
+        sizes.sizes.insert(String::from("docx"), 20 * 1024);
+        let mut buffer = termcolor::Buffer::no_color();
+        write_extensions(&mut buffer, &extensions, &ExtensionSort::Name, Some(&sizes)).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        assert_eq!(
+            output,
+            "docx  12 files ( 3.6%,   20480 bytes)\n\
+             jpg  320 files (95.0%, 3145728 bytes)\n\
+             txt    5 files ( 1.5%,     100 bytes)\n\
+             Total: 337 files, 3166308 bytes\n"
+        );
+
+        sizes.human = true;
+        let mut buffer = termcolor::Buffer::no_color();
+        write_extensions(&mut buffer, &extensions, &ExtensionSort::Name, Some(&sizes)).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        assert_eq!(
+            output,
+            "docx  12 files ( 3.6%,  20K)\n\
+             jpg  320 files (95.0%, 3.0M)\n\
+             txt    5 files ( 1.5%, 100B)\n\
+             Total: 337 files, 3.0M\n"
+        );
+
+        // -- Test colored output
+        let mut buffer = termcolor::Buffer::ansi();
+        write_extensions(&mut buffer, &extensions, &ExtensionSort::Name, None).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+        assert!(output.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_fold_extension_case() {
+        let mut extensions = HashMap::new();
+        extensions.insert(String::from("JPG"), 3);
+        extensions.insert(String::from("Jpg"), 1);
+        extensions.insert(String::from("jpg"), 10);
+        extensions.insert(String::from("txt"), 5);
+
+        // -- The spellings are found before folding them into one
+        let variants = extension_variants(&extensions);
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants["jpg"], vec!["JPG", "Jpg", "jpg"]);
+
+        let folded = fold_extension_case(extensions, |a, b| *a += b);
+        assert_eq!(folded.len(), 2);
+        assert_eq!(folded["jpg"], 14);
+        assert_eq!(folded["txt"], 5);
+        assert!(extension_variants(&folded).is_empty());
+
+        let mut output = Vec::new();
+        write_extension_variants(&mut output, &variants).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\nVariants:\n  jpg: JPG, Jpg, jpg\n"
+        );
+    }
+
+    #[test]
+    fn test_drop_rare_extensions() {
+        let mut extensions = HashMap::new();
+        extensions.insert(String::from("jpg"), 320);
+        extensions.insert(String::from("txt"), 5);
+        extensions.insert(String::from("bak"), 1);
+        extensions.insert(String::from("tmp"), 2);
+
+        let common = drop_rare_extensions(&extensions, 5, false);
+        let mut exts: Vec<&String> = common.keys().collect();
+        exts.sort();
+        assert_eq!(exts, vec!["jpg", "txt"]);
+
+        // -- Only the listing changes, the counts are left alone
+        assert_eq!(extensions.len(), 4);
+        assert_eq!(drop_rare_extensions(&extensions, 0, false), extensions);
+
+        // -- Rolled up rare extensions are listed last and keep the total
+        let rolled = drop_rare_extensions(&extensions, 5, true);
+        assert_eq!(rolled[RARE_EXTENSIONS], 3);
+
+        // -- ... along with their sizes
+        let mut sizes = HashMap::new();
+        sizes.insert(String::from("jpg"), 1000);
+        sizes.insert(String::from("txt"), 50);
+        sizes.insert(String::from("bak"), 7);
+        sizes.insert(String::from("tmp"), 3);
+        let rolled_sizes = drop_rare_sizes(&sizes, &rolled);
+        assert_eq!(rolled_sizes.len(), 3);
+        assert_eq!(rolled_sizes[RARE_EXTENSIONS], 10);
+        assert_eq!(drop_rare_sizes(&sizes, &common).len(), 2);
+
+        for sort in &[ExtensionSort::Name, ExtensionSort::Count] {
+            let mut buffer = termcolor::Buffer::no_color();
+            write_extensions(&mut buffer, &rolled, sort, None).unwrap();
+            let output = String::from_utf8(buffer.into_inner()).unwrap();
+            assert_eq!(
+                output,
+                "jpg     320 files (97.6%)\n\
+                 txt       5 files ( 1.5%)\n\
+                 (other)   3 files ( 0.9%)\n\
+                 Total: 328 files\n"
+            );
+        }
+
+        // -- Nothing to roll up
+        assert_eq!(drop_rare_extensions(&extensions, 1, true), extensions);
+    }
+
+    #[test]
+    fn test_find_unique_extensions_by_dir() {
+        let root = env::temp_dir().join("ram-utils-unique-ext-by-dir-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs/old")).unwrap();
+        fs::create_dir_all(root.join("photos")).unwrap();
+        for name in &[
+            "README.md",
+            "docs/a.md",
+            "docs/b.md",
+            "docs/old/c.pdf",
+            "photos/1.jpg",
+            "photos/2.jpg",
+            "photos/notes.md",
+        ] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let groups =
+            find_unique_extensions_by_dir(&root, &WalkOptions::default(), &[], &mut quiet_logger())
+                .unwrap();
+        let dirs: Vec<&Path> = groups.iter().map(|group| group.dir.as_path()).collect();
+        assert_eq!(
+            dirs,
+            vec![Path::new("."), Path::new("docs"), Path::new("photos")]
+        );
+
+        let combined = combine_extensions(&groups);
+        let mut buffer = termcolor::Buffer::no_color();
+        write_extensions_by_dir(&mut buffer, &groups, &combined, &ExtensionSort::Count).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            ".:\n\
+             md 1 files (100.0%)\n\
+             Total: 1 files\n\
+             \n\
+             docs:\n\
+             md  2 files (66.7%)\n\
+             pdf 1 files (33.3%)\n\
+             Total: 3 files\n\
+             \n\
+             photos:\n\
+             jpg 2 files (66.7%)\n\
+             md  1 files (33.3%)\n\
+             Total: 3 files\n\
+             \n\
+             All:\n\
+             md  4 files (57.1%)\n\
+             jpg 2 files (28.6%)\n\
+             pdf 1 files (14.3%)\n\
+             Total: 7 files\n"
+        );
+
+        let mut output = Vec::new();
+        write_extensions_by_dir_csv(&mut output, &groups, &ExtensionSort::Name).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "directory,extension,count\n\
+             .,md,1\n\
+             docs,md,2\n\
+             docs,pdf,1\n\
+             photos,jpg,2\n\
+             photos,md,1\n"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_write_extensions_csv() {
+        let mut extensions = HashMap::new();
+        extensions.insert(String::from("jpg"), 320);
+        extensions.insert(String::from("a,b"), 2);
+        extensions.insert(String::from("x\"y"), 1);
+
+        let mut output = Vec::new();
+        write_extensions_csv(&mut output, &extensions, &ExtensionSort::Count, None).unwrap();
+        assert!(output.starts_with(b"extension,count\njpg,320\n\"a,b\",2\n"));
+
+        let mut reader = csv::Reader::from_reader(output.as_slice());
+        let rows: Vec<(String, u32)> = reader
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (record[0].to_string(), record[1].parse().unwrap())
+            })
+            .collect();
+
+        assert_eq!(rows.len(), extensions.len());
+        for (ext, count) in rows {
+            assert_eq!(extensions[&ext], count);
+        }
+    }
+
+    #[test]
+    fn test_find_extension_files() {
+        let root = env::temp_dir().join("ram-utils-extension-files-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for name in &["b.tmp", "sub/a.tmp", "c.txt", "d.TMP"] {
+            File::create(root.join(name)).unwrap();
+        }
+        let walk = WalkOptions::default();
+
+        // -- Every extension, each with its sorted files
+        let files = find_extension_files(&root, &walk, &[], None, &mut quiet_logger()).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(
+            files["tmp"],
+            vec![root.join("b.tmp"), root.join("sub/a.tmp")]
+        );
+
+        // -- Or one, in any case
+        let files =
+            find_extension_files(&root, &walk, &[], Some(".tmp"), &mut quiet_logger()).unwrap();
+        let mut exts: Vec<&String> = files.keys().collect();
+        exts.sort();
+        assert_eq!(exts, vec!["TMP", "tmp"]);
+
+        let mut output = Vec::new();
+        write_extension_files(&mut output, &files, &ExtensionSort::Count).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "tmp (2 files):\n  {}\n  {}\n\nTMP (1 files):\n  {}\n\nTotal: 3 files\n",
+                root.join("b.tmp").display(),
+                root.join("sub/a.tmp").display(),
+                root.join("d.TMP").display()
+            )
+        );
+
+        let mut output = Vec::new();
+        write_extension_files_csv(&mut output, &files, &ExtensionSort::Name).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("extension,path\nTMP,"));
+        assert_eq!(output.lines().count(), 4);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_unique_extensions_without_extension() {
+        let root = env::temp_dir().join("ram-utils-no-extension-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for name in &["README", "Makefile", ".bashrc", ".eslintrc.json", "a.txt"] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        // -- Files without an extension are counted too
+        let walk = WalkOptions::default();
+        let exts = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(exts.len(), 2);
+        assert_eq!(exts[NO_EXTENSION], 2);
+        assert_eq!(exts["txt"], 1);
+
+        // -- ... with hidden ones apart
+        let walk = WalkOptions {
+            include_hidden: true,
+            ..WalkOptions::default()
+        };
+        let exts = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(exts[NO_EXTENSION], 2);
+        assert_eq!(exts[DOTFILE_EXTENSION], 1);
+        assert_eq!(exts["json"], 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_extension_sizes() {
+        let root = env::temp_dir().join("ram-utils-extension-sizes-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.jpg"), vec![0; 100]).unwrap();
+        fs::write(root.join("sub/b.jpg"), vec![0; 50]).unwrap();
+        fs::write(root.join("c.txt"), vec![0; 10]).unwrap();
+        fs::write(root.join("d.txt"), vec![0; 10]).unwrap();
+        fs::write(root.join("e.txt"), vec![0; 10]).unwrap();
+        fs::write(root.join("f.md"), vec![0; 500]).unwrap();
+
+        let walk = WalkOptions::default();
+        let sizes = find_extension_sizes(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(sizes["jpg"], 150);
+        assert_eq!(sizes["txt"], 30);
+        let extensions = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+
+        let order = |sort: &ExtensionSort| -> Vec<String> {
+            sorted_extensions(&extensions, sort)
+                .into_iter()
+                .map(|(ext, _)| ext.clone())
+                .collect()
+        };
+        let size = ExtensionSort::Size(sizes.clone());
+        assert_eq!(order(&size), vec!["md", "jpg", "txt"]);
+        assert_eq!(order(&ExtensionSort::Count), vec!["txt", "jpg", "md"]);
+
+        // -- Reversed orders still list the rare extensions last
+        let reversed = ExtensionSort::Reversed(Box::new(ExtensionSort::Count));
+        assert_eq!(order(&reversed), vec!["md", "jpg", "txt"]);
+        let rolled = drop_rare_extensions(&extensions, 2, true);
+        let rolled: Vec<&String> = sorted_extensions(&rolled, &reversed)
+            .into_iter()
+            .map(|(ext, _)| ext)
+            .collect();
+        assert_eq!(rolled, vec!["jpg", "txt", RARE_EXTENSIONS]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_extensions_max_depth() {
+        let root = env::temp_dir().join("ram-utils-extensions-max-depth-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        File::create(root.join("top.txt")).unwrap();
+        File::create(root.join("a/middle.jpg")).unwrap();
+        File::create(root.join("a/b/bottom.png")).unwrap();
+
+        let count = |max_depth| {
+            let walk = WalkOptions {
+                max_depth,
+                ..Default::default()
+            };
+            let extensions =
+                find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+            let mut extensions: Vec<String> = extensions.into_keys().collect();
+            extensions.sort();
+            extensions
+        };
+
+        assert_eq!(count(Some(1)), vec!["txt"]);
+        assert_eq!(count(Some(2)), vec!["jpg", "txt"]);
+        assert_eq!(count(None), vec!["jpg", "png", "txt"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_apply_renames_parallel() {
+        let root = env::temp_dir().join("ram-utils-parallel-renames-test");
+        let _ = fs::remove_dir_all(&root);
+        for dir in &["A/B", "C/D", "E"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for name in &["A/B/F.txt", "A/B/G.txt", "C/D/H.txt", "E/I.txt", "E/i.txt"] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let options = ConvertOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::LowerCase);
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+
+        // -- Directories are renamed after their contents, whichever thread renames them
+        let mut log = quiet_logger();
+        let options = ConvertOptions {
+            on_conflict: ConflictPolicy::Number,
+            ..options
+        };
+        let results = apply_renames_parallel(&plan, &options, 4, &mut log);
+        assert_eq!(results.len(), plan.len());
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Some(Ok(Some(_))))));
+        assert_eq!((log.summary.files, log.summary.dirs), (4, 5));
+        for name in &[
+            "a/b/f.txt",
+            "a/b/g.txt",
+            "c/d/h.txt",
+            "e/i.txt",
+            "e/i_1.txt",
+        ] {
+            assert!(has_exact_name(&root.join(name)), "{}", name);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rename_case() {
+        let root = env::temp_dir().join("ram-utils-rename-case-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("foo.txt"), "foo").unwrap();
+
+        assert!(is_case_only_change(
+            &root.join("foo.txt"),
+            &root.join("FOO.TXT")
+        ));
+        assert!(!is_case_only_change(
+            &root.join("foo.txt"),
+            &root.join("foo.txt")
+        ));
+        assert!(!is_case_only_change(
+            &root.join("foo.txt"),
+            &root.join("bar.txt")
+        ));
+        assert!(!is_case_only_change(
+            &root.join("foo.txt"),
+            &root.join("dir/FOO.TXT")
+        ));
+
+        // -- The entry ends up under the new name, without a temporary entry left behind
+        rename_case(&root.join("foo.txt"), &root.join("FOO.TXT")).unwrap();
+        assert!(has_exact_name(&root.join("FOO.TXT")));
+        assert_eq!(fs::read_to_string(root.join("FOO.TXT")).unwrap(), "foo");
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 2);
+
+        // -- A failed rename leaves the entry where it was
+        fs::create_dir_all(root.join("DIR/full")).unwrap();
+        assert!(rename_case(&root.join("dir"), &root.join("DIR")).is_err());
+        assert!(has_exact_name(&root.join("dir")));
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 3);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_write_extensions_json() {
+        let mut extensions = HashMap::new();
+        extensions.insert(String::from("jpg"), 320);
+        extensions.insert(String::from("txt"), 5);
+
+        let mut output = Vec::new();
+        write_extensions_json(&mut output, &extensions, &ExtensionSort::Count, None).unwrap();
+        let rows: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            rows,
+            serde_json::json!([
+                {"extension": "jpg", "count": 320},
+                {"extension": "txt", "count": 5},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_run_records() {
+        let root = env::temp_dir().join("ram-utils-run-records-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        File::create(root.join("A.txt")).unwrap();
+        File::create(root.join("B.txt")).unwrap();
+        File::create(root.join("b.txt")).unwrap();
+
+        let mut log = quiet_logger();
+        log.record = true;
+        for name in &["A.txt", "B.txt"] {
+            let from = root.join(name);
+            let to = root.join(name.to_lowercase());
+            if let Err(e) = apply_rename(&from, &to, ConflictPolicy::Error, &mut log) {
+                log.error(&format!("Error: {}", e));
+            }
+        }
+
+        // -- Renames and errors are recorded as they happen
+        assert_eq!(
+            log.renamed,
+            vec![PlanEntry {
+                from: root.join("A.txt").to_string_lossy().into_owned(),
+                to: root.join("a.txt").to_string_lossy().into_owned(),
+                kind: String::from("file"),
+                valid_utf8: true,
+            }]
+        );
+        assert_eq!(log.error_messages.len(), 1);
+        assert!(log.error_messages[0].starts_with("Can't rename"));
+
+        let mut output = Vec::new();
+        write_run_json(&mut output, &log.renamed, &log.summary, &log.error_messages).unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(report["renamed"][0]["kind"], "file");
+        assert_eq!(report["summary"]["files"], 1);
+        assert_eq!(report["summary"]["errors"], 1);
+        assert_eq!(report["errors"].as_array().unwrap().len(), 1);
+
+        let mut output = Vec::new();
+        write_renames_csv(&mut output, &log.renamed).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("from,to,kind,valid_utf8\n"));
+        assert_eq!(output.lines().count(), 2);
+
+        let mut output = Vec::new();
+        write_renames_csv(&mut output, &[]).unwrap();
+        assert_eq!(output, b"from,to,kind,valid_utf8\n");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_compound_extensions() {
+        let root = env::temp_dir().join("ram-utils-compound-ext-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for name in &[
+            "foo.tar.gz",
+            "bar.gz",
+            "baz.TAR.GZ",
+            "qux.tar.xz",
+            ".tar.gz",
+        ] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let walk = WalkOptions {
+            include_hidden: true,
+            ..Default::default()
+        };
+        let compound: Vec<String> = DEFAULT_COMPOUND_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect();
+
+        let exts = find_unique_extensions(&root, &walk, &compound, &mut quiet_logger()).unwrap();
+        assert_eq!(exts.len(), 4);
+        assert_eq!(exts["tar.gz"], 1);
+        assert_eq!(exts["TAR.GZ"], 1);
+        assert_eq!(exts["tar.xz"], 1);
+        assert_eq!(exts["gz"], 2);
+
+        // -- Without compound extensions only the last one counts
+        let exts = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(exts["gz"], 3);
+        assert_eq!(exts["xz"], 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_percentage() {
+        let counts = [320, 12, 5, 1];
+        let total = counts.iter().sum();
+        let sum: f64 = counts.iter().map(|&count| percentage(count, total)).sum();
+
+        assert_eq!(total, 338);
+        assert!((sum - 100.0).abs() < 1e-9);
+        assert_eq!(percentage(1, 4), 25.0);
+        assert_eq!(percentage(0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let root = env::temp_dir().join("ram-utils-test-find-dupes");
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("a.txt"), "hello world").unwrap();
+        fs::write(root.join("nested").join("b.txt"), "hello world").unwrap();
+        fs::write(root.join("c.txt"), "hello there").unwrap(); // Same size, different bytes
+        fs::write(root.join("d.txt"), "goodbye").unwrap();
+        fs::write(root.join("e.txt"), "goodbye").unwrap();
+        File::create(root.join("empty1")).unwrap();
+        File::create(root.join("empty2")).unwrap();
+
+        let hash = |path: &Path, limit| HashAlgo::Blake3.hash_file(path, limit);
+        let groups = find_duplicates(&root, &WalkOptions::default(), hash).unwrap();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].paths,
+            [root.join("a.txt"), root.join("nested/b.txt")]
+        );
+        assert_eq!(groups[0].size, 11);
+        assert_eq!(groups[1].paths, [root.join("d.txt"), root.join("e.txt")]);
+
+        // -- Test the wasted bytes are reported per group and in total
+        let mut out = Vec::new();
+        write_duplicates(&mut out, &groups[..1], HashAlgo::Blake3).unwrap();
+        let expected = format!(
+            "2 files, 11 bytes each, 11 bytes wasted (blake3):\n  {}\n  {}\n\nTotal: 1 group, 11 bytes wasted\n",
+            root.join("a.txt").display(),
+            root.join("nested/b.txt").display(),
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        let mut out = Vec::new();
+        write_duplicates(&mut out, &groups, HashAlgo::Blake3).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Total: 2 groups, 18 bytes wasted\n"));
+
+        // -- Test NUL separated output has no newlines, even for a name containing one
+        let mut groups_with_newline = groups.clone();
+        groups_with_newline[1].paths[1] = root.join("e\n.txt");
+        let mut out = Vec::new();
+        write_duplicates_null(&mut out, &groups_with_newline).unwrap();
+        let expected = format!(
+            "{}\0{}\0\0{}\0{}\0",
+            root.join("a.txt").display(),
+            root.join("nested/b.txt").display(),
+            root.join("d.txt").display(),
+            root.join("e\n.txt").display(),
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        let mut out = Vec::new();
+        write_duplicates_null(&mut out, &groups).unwrap();
+        assert!(!out.contains(&b'\n'));
+        assert_eq!(out.last(), Some(&b'\0'));
+
+        // -- Test dry run deletes nothing
+        let removed = delete_duplicates(&groups, true, &mut quiet_logger()).unwrap();
+        assert_eq!(removed, [root.join("nested/b.txt"), root.join("e.txt")]);
+        assert!(root.join("e.txt").exists());
+
+        // -- Test the first file of each group is kept
+        delete_duplicates(&groups, false, &mut quiet_logger()).unwrap();
+        assert!(root.join("a.txt").exists());
+        assert!(!root.join("nested/b.txt").exists());
+        assert!(root.join("d.txt").exists());
+        assert!(!root.join("e.txt").exists());
+        assert!(root.join("c.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_duplicates_hashing() {
+        let root = env::temp_dir().join("ram-utils-find-dupes-hash-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let block = vec![b'x'; QUICK_HASH_BYTES as usize];
+        let large = |tail: &[u8]| [&block[..], tail].concat();
+        fs::write(root.join("a.txt"), "hello world").unwrap();
+        fs::write(root.join("b.txt"), "hello world").unwrap();
+        fs::write(root.join("unique.txt"), "a size of its own").unwrap();
+        fs::write(root.join("large1.bin"), large(b"end")).unwrap();
+        fs::write(root.join("large2.bin"), large(b"end")).unwrap();
+        fs::write(root.join("large3.bin"), large(b"END")).unwrap();
+        fs::write(root.join("other.bin"), [b"y", &large(b"end")[1..]].concat()).unwrap();
+
+        // -- Identical files are grouped whichever algorithm is used
+        for algo in &[HashAlgo::Blake3, HashAlgo::Sha256, HashAlgo::Md5] {
+            let hash = |path: &Path, limit| algo.hash_file(path, limit);
+            let groups = find_duplicates(&root, &WalkOptions::default(), hash).unwrap();
+            let paths: Vec<_> = groups.into_iter().map(|group| group.paths).collect();
+            assert_eq!(
+                paths,
+                vec![
+                    vec![root.join("a.txt"), root.join("b.txt")],
+                    vec![root.join("large1.bin"), root.join("large2.bin")],
+                ]
+            );
+        }
+
+        // -- Files of a unique size are never read, and larger files are only read in full if
+        // their first block matches
+        let mut hashed = Vec::new();
+        find_duplicates(&root, &WalkOptions::default(), |path, limit| {
+            hashed.push((path.file_name().unwrap().to_owned(), limit));
+            HashAlgo::Blake3.hash_file(path, limit)
+        })
+        .unwrap();
+        hashed.sort();
+
+        let quick = Some(QUICK_HASH_BYTES);
+        let expected: Vec<_> = vec![
+            ("a.txt", None),
+            ("b.txt", None),
+            ("large1.bin", None),
+            ("large1.bin", quick),
+            ("large2.bin", None),
+            ("large2.bin", quick),
+            ("large3.bin", None),
+            ("large3.bin", quick),
+            ("other.bin", quick),
+        ]
+        .into_iter()
+        .map(|(name, limit)| (OsStr::new(name).to_owned(), limit))
+        .collect();
+        assert_eq!(hashed, expected);
+
+        // -- Hashes of different algorithms differ
+        let path = root.join("a.txt");
+        let blake3 = HashAlgo::Blake3.hash_file(&path, None).unwrap();
+        let sha256 = HashAlgo::Sha256.hash_file(&path, None).unwrap();
+        let md5 = HashAlgo::Md5.hash_file(&path, None).unwrap();
+        assert_eq!((blake3.len(), sha256.len(), md5.len()), (32, 32, 16));
+        assert_ne!(blake3, sha256);
+
+        // -- The limit only hashes the start of a file
+        let quick_hash = HashAlgo::Md5.hash_file(&path, Some(5)).unwrap();
+        fs::write(root.join("hello.txt"), "hello").unwrap();
+        let hello = HashAlgo::Md5
+            .hash_file(&root.join("hello.txt"), None)
+            .unwrap();
+        assert_eq!(quick_hash, hello);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_watch() {
+        let root = env::temp_dir().join("ram-utils-watch-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let (sender, events) = mpsc::channel();
+        let watcher = start_watcher(&root, false, sender.clone()).unwrap();
+        let watched = root.clone();
+        let watching = std::thread::spawn(move || {
+            let options = ConvertOptions::default();
+            let transform = Transform::Case(LetterCase::UpperCase);
+            let debounce = Duration::from_millis(100);
+            let mut log = quiet_logger();
+            watch(&watched, &events, &transform, &options, debounce, &mut log);
+            log.summary
+        });
+
+        // -- New files are converted once they stop changing
+        fs::write(root.join("new file.txt"), "downloaded").unwrap();
+        let converted = root.join("NEW FILE.TXT");
+        let started = Instant::now();
+        while !converted.exists() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        sender.send(WatchEvent::Stop).unwrap();
+        drop(watcher);
+        let summary = watching.join().unwrap();
+
+        assert_eq!(fs::read_to_string(&converted).unwrap(), "downloaded");
+        assert!(!root.join("new file.txt").exists());
+        assert_eq!(summary.files, 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_watch_respect_gitignore() {
+        let root = env::temp_dir().join("ram-utils-watch-gitignore-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\ntarget/\n").unwrap();
+        fs::write(root.join("logs/.gitignore"), "!keep.log\n").unwrap();
+        for name in &[
+            "new.txt",
+            "new.log",
+            "target/debug/out.txt",
+            "logs/keep.log",
+        ] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let options = ConvertOptions {
+            walk: WalkOptions {
+                respect_gitignore: true,
+                ..WalkOptions::default()
+            },
+            ..ConvertOptions::default()
+        };
+        let upper = Transform::Case(LetterCase::UpperCase);
+        for name in &[
+            "new.txt",
+            "new.log",
+            "target/debug/out.txt",
+            "logs/keep.log",
+        ] {
+            convert_new_entry(
+                &root,
+                &root.join(name),
+                &upper,
+                &options,
+                &mut quiet_logger(),
+            );
+        }
+
+        // -- New entries are ignored like the walked ones, including inside ignored directories
+        assert!(has_exact_name(&root.join("NEW.TXT")));
+        assert!(has_exact_name(&root.join("new.log")));
+        assert!(has_exact_name(&root.join("target/debug/out.txt")));
+        assert!(has_exact_name(&root.join("logs/KEEP.LOG")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_watch_hidden_and_excluded_dirs() {
+        let root = env::temp_dir().join("ram-utils-watch-hidden-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("repo/.git")).unwrap();
+        fs::create_dir_all(root.join("repo/build")).unwrap();
+        let names = ["repo/.git/HEAD", "repo/build/OUT.TXT", "repo/NOTES.TXT"];
+        for name in &names {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let options = ConvertOptions {
+            recursive: true,
+            walk: WalkOptions {
+                exclude_dirs: glob_set(["build"]).unwrap(),
+                ..WalkOptions::default()
+            },
+            ..ConvertOptions::default()
+        };
+        let (sender, events) = mpsc::channel();
+        for name in &names {
+            sender.send(WatchEvent::Changed(root.join(name))).unwrap();
+        }
+        drop(sender);
+        let lower = Transform::Case(LetterCase::LowerCase);
+        let debounce = Duration::from_millis(0);
+        watch(
+            &root,
+            &events,
+            &lower,
+            &options,
+            debounce,
+            &mut quiet_logger(),
+        );
+
+        // -- Entries inside hidden or excluded directories keep their names
+        assert!(has_exact_name(&root.join("repo/.git/HEAD")));
+        assert!(has_exact_name(&root.join("repo/build/OUT.TXT")));
+        assert!(has_exact_name(&root.join("repo/notes.txt")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_watch_debounce() {
+        let root = env::temp_dir().join("ram-utils-watch-debounce-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("partial.txt"), "").unwrap();
+
+        let options = ConvertOptions::default();
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let (sender, events) = mpsc::channel();
+
+        // -- Entries still changing when watching stops are left alone
+        sender
+            .send(WatchEvent::Changed(root.join("partial.txt")))
+            .unwrap();
+        sender.send(WatchEvent::Stop).unwrap();
+        let debounce = Duration::from_secs(60);
+        watch(
+            &root,
+            &events,
+            &transform,
+            &options,
+            debounce,
+            &mut quiet_logger(),
+        );
+        assert!(root.join("partial.txt").exists());
+
+        // -- ... and are converted once they settle, ignoring entries that no longer exist
+        sender
+            .send(WatchEvent::Changed(root.join("partial.txt")))
+            .unwrap();
+        sender
+            .send(WatchEvent::Changed(root.join("gone.txt")))
+            .unwrap();
+        drop(sender);
+        let mut log = quiet_logger();
+        watch(
+            &root,
+            &events,
+            &transform,
+            &options,
+            Duration::from_millis(0),
+            &mut log,
+        );
+        assert!(root.join("PARTIAL.TXT").exists());
+        assert_eq!((log.summary.files, log.summary.errors), (1, 0));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_unique_extensions_unreadable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = env::temp_dir().join("ram-utils-unreadable-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("locked")).unwrap();
+        fs::create_dir_all(root.join("open")).unwrap();
+        File::create(root.join("a.txt")).unwrap();
+        File::create(root.join("locked/secret.key")).unwrap();
+        File::create(root.join("open/b.txt")).unwrap();
+        File::create(root.join("open/c.md")).unwrap();
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Permissions don't apply to root, in which case there is nothing to test
+        if fs::read_dir(root.join("locked")).is_err() {
+            let err = SharedBuffer::default();
+            let mut log = Logger::with_writers(
+                Verbosity::Normal,
+                Box::new(io::sink()),
+                Box::new(err.clone()),
+            );
+
+            // -- The readable siblings are still counted, and the skipped directory reported
+            let exts =
+                find_unique_extensions(&root, &WalkOptions::default(), &[], &mut log).unwrap();
+            assert_eq!(exts.get("txt"), Some(&2));
+            assert_eq!(exts.get("md"), Some(&1));
+            assert_eq!(exts.get("key"), None);
+            assert_eq!(log.summary.errors, 1);
+            assert!(err.contents().contains("locked"));
+        }
+
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_log_plan_preview() {
+        let plan: Vec<(PathBuf, PathBuf)> = (0..10)
+            .map(|i| {
+                (
+                    PathBuf::from(format!("file{}.txt", i)),
+                    PathBuf::from(format!("FILE{}.TXT", i)),
+                )
+            })
+            .collect();
+        let render = |preview| {
+            let out = SharedBuffer::default();
+            let mut log = Logger::with_writers(
+                Verbosity::Normal,
+                Box::new(out.clone()),
+                Box::new(io::sink()),
+            );
+            log.summary.skipped = 2;
+            log_plan(&plan, preview, &mut log);
+            out.contents()
+        };
+
+        // -- Exactly n renames are listed, then the number not shown and the totals
+        let out = render(Some(3));
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "Converting file0.txt => FILE0.TXT");
+        assert_eq!(lines[2], "Converting file2.txt => FILE2.TXT");
+        assert_eq!(lines[3], "... and 7 more");
+        assert_eq!(lines[4], "Would rename 10 entries; skipped 2");
+
+        // -- Without a limit, or a limit covering the plan, everything is listed
+        assert_eq!(render(None).lines().count(), 11);
+        assert_eq!(render(Some(10)), render(None));
+        assert!(!render(Some(50)).contains("more"));
+
+        // -- A preview of 0 only prints the totals
+        assert_eq!(
+            render(Some(0)),
+            "... and 10 more\nWould rename 10 entries; skipped 2\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = env::temp_dir().join("ram-utils-follow-symlinks-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("real")).unwrap();
+        fs::create_dir_all(root.join("outside")).unwrap();
+        File::create(root.join("real/A.TXT")).unwrap();
+        File::create(root.join("outside/B.JPG")).unwrap();
+        symlink(root.join("outside"), root.join("real/link")).unwrap();
+        // Links back up the tree are only followed once
+        symlink(root.join("real"), root.join("real/loop")).unwrap();
+
+        let count = |follow_symlinks| {
+            let walk = WalkOptions {
+                follow_symlinks,
+                ..Default::default()
+            };
+            find_unique_extensions(&root.join("real"), &walk, &[], &mut quiet_logger()).unwrap()
+        };
+        assert_eq!(count(false).get("JPG"), None);
+        assert_eq!(count(true).get("JPG"), Some(&1));
+        assert_eq!(count(true).get("TXT"), Some(&1));
+
+        // -- Conversions rename the entries inside linked directories through the link
+        let options = ConvertOptions {
+            recursive: true,
+            walk: WalkOptions {
+                follow_symlinks: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::LowerCase);
+        let plan = convert_dir_plan(
+            &root.join("real"),
+            &transform,
+            &options,
+            &mut quiet_logger(),
+        )
+        .unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                (root.join("real/A.TXT"), root.join("real/a.txt")),
+                (root.join("real/link/B.JPG"), root.join("real/link/b.jpg")),
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_dir_sizes() {
+        let root = env::temp_dir().join("ram-utils-dir-sizes-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/deep")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::write(root.join("top.txt"), vec![0; 1]).unwrap();
+        fs::write(root.join("a/one.txt"), vec![0; 10]).unwrap();
+        fs::write(root.join("a/deep/two.txt"), vec![0; 100]).unwrap();
+
+        let sizes = |depth| {
+            let dirs =
+                find_dir_sizes(&root, depth, &WalkOptions::default(), &mut quiet_logger()).unwrap();
+            dirs.into_iter()
+                .map(|dir| (dir.path, dir.size))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sizes(1),
+            vec![
+                (root.join("a"), 110),
+                (root.join("empty"), 0),
+                (root.clone(), 111)
+            ]
+        );
+        assert_eq!(
+            sizes(2),
+            vec![
+                (root.join("a"), 110),
+                (root.join("a/deep"), 100),
+                (root.join("empty"), 0),
+                (root.clone(), 111),
+            ]
+        );
+        assert_eq!(sizes(0), vec![(root.clone(), 111)]);
+
+        assert_eq!(format_size(512, true), "512B");
+        assert_eq!(format_size(1536, true), "1.5K");
+        assert_eq!(format_size(12 * 1024 * 1024, true), "12M");
+        assert_eq!(format_size(1536, false), "1536");
+
+        let mut out = Vec::new();
+        let dirs = vec![DirSize {
+            path: PathBuf::from("a"),
+            size: 2048,
+        }];
+        write_dir_sizes(&mut out, &dirs, true).unwrap();
+        assert_eq!(out, b"2.0K\ta\n");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_links() {
+        use std::os::unix::fs::symlink;
+
+        let root = env::temp_dir().join("ram-utils-links-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        File::create(root.join("file.txt")).unwrap();
+        symlink("../file.txt", root.join("dir/valid")).unwrap();
+        symlink("dir", root.join("to-dir")).unwrap();
+        symlink("missing.txt", root.join("dangling")).unwrap();
+        symlink("dangling", root.join("chained")).unwrap();
+
+        let links = find_links(&root, &WalkOptions::default()).unwrap();
+        let link = |name: &str, target: &str, broken| Link {
+            path: root.join(name),
+            target: PathBuf::from(target),
+            broken,
+        };
+        assert_eq!(
+            links,
+            vec![
+                link("chained", "dangling", true),
+                link("dangling", "missing.txt", true),
+                link("dir/valid", "../file.txt", false),
+                link("to-dir", "dir", false),
+            ]
+        );
+
+        let mut output = Vec::new();
+        write_links(&mut output, &links[1..3]).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{} -> missing.txt (broken)\n{} -> ../file.txt\n",
+                root.join("dangling").display(),
+                root.join("dir/valid").display()
+            )
+        );
+
+        // -- Only broken links are deleted, and only when not a dry run
+        let removed = delete_broken_links(&links, true, &mut quiet_logger()).unwrap();
+        assert_eq!(removed, vec![root.join("chained"), root.join("dangling")]);
+        assert!(fs::symlink_metadata(root.join("dangling")).is_ok());
+
+        delete_broken_links(&links, false, &mut quiet_logger()).unwrap();
+        assert!(fs::symlink_metadata(root.join("chained")).is_err());
+        assert!(fs::symlink_metadata(root.join("dangling")).is_err());
+        assert!(fs::symlink_metadata(root.join("dir/valid")).is_ok());
+        assert!(fs::symlink_metadata(root.join("to-dir")).is_ok());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_relative_log_paths() {
+        let root = env::temp_dir().join("ram-utils-relative-to-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("photos/2023")).unwrap();
+        File::create(root.join("photos/2023/beach.jpg")).unwrap();
+        File::create(root.join("photos/cover.png")).unwrap();
+
+        let out = SharedBuffer::default();
+        let mut log = Logger::with_writers(
+            Verbosity::Verbose,
+            Box::new(out.clone()),
+            Box::new(io::sink()),
+        );
+        log.relative_to = display_base(&root.join("photos"));
+
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        convert_entry(&root.join("photos"), &transform, &options, &mut log);
+
+        // -- Paths inside the base are relative and unquoted, the rest are shown in full
+        assert_eq!(
+            out.contents(),
+            format!(
+                "Converting 2023/beach.jpg => 2023/BEACH.JPG\n\
+                 Converting cover.png => COVER.PNG\n\
+                 Converting {} => {}\n",
+                root.join("photos").display(),
+                root.join("PHOTOS").display()
+            )
+        );
+        assert!(root.join("PHOTOS/2023/BEACH.JPG").exists());
+
+        // -- A file's base is its parent directory
+        assert_eq!(
+            display_base(&root.join("PHOTOS/COVER.PNG")),
+            Some(root.join("PHOTOS"))
+        );
+        assert_eq!(display_base(Path::new("cover.png")), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_categorize() {
+        let extensions: HashMap<String, u32> = [("jpg", 3), ("PNG", 1), ("mp3", 2), ("xyz", 5)]
+            .iter()
+            .map(|(ext, count)| (ext.to_string(), *count))
+            .collect();
+
+        // -- Known extensions are grouped regardless of case, unknown ones are in `other`
+        let categories = categorize(&extensions, None);
+        let expected = vec![
+            Category {
+                name: String::from("images"),
+                total: 4,
+                extensions: vec![(String::from("jpg"), 3), (String::from("PNG"), 1)],
+            },
+            Category {
+                name: String::from("audio"),
+                total: 2,
+                extensions: vec![(String::from("mp3"), 2)],
+            },
+            Category {
+                name: String::from("other"),
+                total: 5,
+                extensions: vec![(String::from("xyz"), 5)],
+            },
+        ];
+        assert_eq!(categories, expected);
+
+        let mut out = NoColor::new(Vec::new());
+        write_categories(&mut out, &categories).unwrap();
+        let expected = [
+            "images 4 files",
+            "  jpg  3",
+            "  PNG  1",
+            "audio  2 files",
+            "  mp3  2",
+            "other  5 files",
+            "  xyz  5",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(String::from_utf8(out.into_inner()).unwrap(), expected);
+
+        // -- The config can add categories and move extensions between them
+        let overrides: HashMap<String, Vec<String>> = vec![
+            (String::from("data"), vec![String::from(".XYZ")]),
+            (String::from("images"), vec![String::from("mp3")]),
+        ]
+        .into_iter()
+        .collect();
+        let categories = categorize(&extensions, Some(&overrides));
+        let names: Vec<(&str, u32)> = categories
+            .iter()
+            .map(|c| (c.name.as_str(), c.total))
+            .collect();
+        assert_eq!(names, vec![("images", 6), ("data", 5)]);
+    }
+
+    fn template_values(name: &str, ext: &str) -> TemplateValues {
+        TemplateValues {
+            name: name.to_string(),
+            ext: ext.to_string(),
+            year: 2023,
+            month: 5,
+            day: 1,
+            hour: 14,
+            minute: 22,
+            second: 33,
+            size: 2048,
+            parent: "scans".to_string(),
+            model: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_template() {
+        assert_eq!(
+            parse_template("{year}-{name}.{ext}").unwrap(),
+            vec![
+                TemplatePart::Placeholder(Placeholder::Year),
+                TemplatePart::Literal("-".to_string()),
+                TemplatePart::Placeholder(Placeholder::Name),
+                TemplatePart::Literal(".".to_string()),
+                TemplatePart::Placeholder(Placeholder::Ext),
+            ]
+        );
+        assert_eq!(
+            parse_template("{date}_{n:03}_{n}").unwrap(),
+            vec![
+                TemplatePart::Placeholder(Placeholder::Date),
+                TemplatePart::Literal("_".to_string()),
+                TemplatePart::Placeholder(Placeholder::Counter { width: 3 }),
+                TemplatePart::Literal("_".to_string()),
+                TemplatePart::Placeholder(Placeholder::Counter { width: 0 }),
+            ]
+        );
+        assert!(parse_template("{n:abc}").is_err());
+        assert!(parse_template("{name}_{camera}").is_err());
+        assert!(parse_template("{name").is_err());
+        assert!(parse_template("{year}/{name}").is_err());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let values = template_values("photo", "jpg");
+        let render = |pattern: &str| render_template(&parse_template(pattern).unwrap(), &values, 7);
+
+        assert_eq!(render("{year}-{month}-{name}.{ext}"), "2023-05-photo.jpg");
+        assert_eq!(
+            render("{year}{month}{day}_{size}.{ext}"),
+            "20230501_2048.jpg"
+        );
+        assert_eq!(render("{name}"), "photo");
+        assert_eq!(render("{date}_{n:03}.{ext}"), "2023-05-01_007.jpg");
+        assert_eq!(render("{parent}-{n}"), "scans-7");
+        assert_eq!(render("{date}_{time}.{ext}"), "2023-05-01_142233.jpg");
+
+        let values = template_values("README", "");
+        assert_eq!(
+            render_template(&parse_template("{name}.{ext}").unwrap(), &values, 1),
+            "README"
+        );
+    }
+
+    #[test]
+    fn test_template_values_from_path() {
+        use chrono::TimeZone;
+
+        let root = env::temp_dir().join("ram-utils-template-values-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let path = root.join("photo.jpg");
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"12345").unwrap();
+        let modified = Local.with_ymd_and_hms(2021, 3, 4, 12, 0, 0).unwrap();
+        file.set_modified(modified.into()).unwrap();
+
+        let values = TemplateValues::from_path(&path).unwrap();
+        assert_eq!(values.name, "photo");
+        assert_eq!(values.ext, "jpg");
+        assert_eq!((values.year, values.month, values.day), (2021, 3, 4));
+        assert_eq!(values.size, 5);
+        assert_eq!(values.parent, "ram-utils-template-values-test");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Builds a big endian TIFF structure with a camera model and date taken
+    fn exif_tiff_data(model: &str, taken: &str) -> Vec<u8> {
+        let ascii = |text: &str| [text.as_bytes(), b"\0"].concat();
+        let (model, taken) = (ascii(model), ascii(taken));
+        let entry = |tag: u16, kind: u16, count: usize, value: usize| {
+            [
+                &tag.to_be_bytes()[..],
+                &kind.to_be_bytes(),
+                &(count as u32).to_be_bytes(),
+                &(value as u32).to_be_bytes(),
+            ]
+            .concat()
+        };
+
+        // Header, IFD0 with 2 entries, the EXIF IFD with 1, then the strings
+        let exif_ifd = 8 + 2 + 2 * 12 + 4;
+        let model_offset = exif_ifd + 2 + 12 + 4;
+        let taken_offset = model_offset + model.len();
+        [
+            &b"MM\0*"[..],
+            &8u32.to_be_bytes(),
+            &2u16.to_be_bytes(),
+            &entry(0x0110, 2, model.len(), model_offset),
+            &entry(0x8769, 4, 1, exif_ifd),
+            &0u32.to_be_bytes(),
+            &1u16.to_be_bytes(),
+            &entry(0x9003, 2, taken.len(), taken_offset),
+            &0u32.to_be_bytes(),
+            &model,
+            &taken,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_read_photo_info() {
+        let root = env::temp_dir().join("ram-utils-photo-info-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let tiff = exif_tiff_data("Canon EOS R5", "2023:05:01 14:22:33");
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+        fs::write(root.join("IMG_0001.JPG"), &jpeg).unwrap();
+        fs::write(root.join("IMG_0002.CR2"), &tiff).unwrap();
+        fs::write(root.join("IMG_0003.jpg"), [0xFF, 0xD8, 0xFF, 0xD9]).unwrap();
+
+        let expected = PhotoInfo {
+            taken: NaiveDate::from_ymd_opt(2023, 5, 1).and_then(|d| d.and_hms_opt(14, 22, 33)),
+            model: Some("Canon EOS R5".to_string()),
+        };
+        assert_eq!(
+            read_photo_info(&root.join("IMG_0001.JPG")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            read_photo_info(&root.join("IMG_0002.CR2")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            read_photo_info(&root.join("IMG_0003.jpg")).unwrap(),
+            PhotoInfo::default()
+        );
+
+        let template = parse_template("{date}_{time}_{model}.{ext}").unwrap();
+        let values = TemplateValues::from_photo(&root.join("IMG_0001.JPG")).unwrap();
+        assert_eq!(
+            render_template(&template, &values, 1),
+            "2023-05-01_142233_Canon EOS R5.JPG"
+        );
+
+        // -- Without EXIF data the modified time is used
+        let path = root.join("IMG_0003.jpg");
+        let modified = Local.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified.into())
+            .unwrap();
+        let values = TemplateValues::from_photo(&path).unwrap();
+        assert_eq!(
+            render_template(&template, &values, 1),
+            "2021-03-04_050607_.jpg"
+        );
+
+        assert!(is_photo(Path::new("a/IMG_0001.JPG")));
+        assert!(!is_photo(Path::new("notes.txt")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_template_plan_counter() {
+        let root = env::temp_dir().join("ram-utils-template-counter-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::create_dir_all(root.join("a")).unwrap();
+
+        for name in &["b/2.jpg", "a/2.jpg", "a/1.jpg", "0.jpg"] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        // -- The counter follows the sorted traversal, whatever order the entries were made in
+        let files = template_files(&root, true, &WalkOptions::default()).unwrap();
+        let template = parse_template("{parent}_{n:02}.{ext}").unwrap();
+        let plan = template_plan(
+            &files,
+            &template,
+            TemplateValues::from_path,
+            ConflictPolicy::Error,
+            &mut quiet_logger(),
+        )
+        .unwrap();
+
+        let names: Vec<_> = plan
+            .iter()
+            .map(|(from, to)| {
+                let from = from.strip_prefix(&root).unwrap().to_path_buf();
+                (from, to.file_name().unwrap().to_str().unwrap().to_string())
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (
+                    PathBuf::from("0.jpg"),
+                    "ram-utils-template-counter-test_01.jpg".to_string()
+                ),
+                (PathBuf::from("a/1.jpg"), "a_02.jpg".to_string()),
+                (PathBuf::from("a/2.jpg"), "a_03.jpg".to_string()),
+                (PathBuf::from("b/2.jpg"), "b_04.jpg".to_string()),
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_template_plan_numbers_collisions() {
+        let root = env::temp_dir().join("ram-utils-template-plan-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        for name in &["2023-05.jpg", "a.jpg", "b.jpg", "c.png"] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let files = template_files(&root, false, &WalkOptions::default()).unwrap();
+        let template = parse_template("{year}-{month}.{ext}").unwrap();
+        let mut log = quiet_logger();
+        let plan = template_plan(
+            &files,
+            &template,
+            |path| {
+                let filename = path.file_name().unwrap().to_str().unwrap();
+                let (name, ext) = split_extension(filename);
+                Ok(template_values(name, ext.trim_start_matches('.')))
+            },
+            ConflictPolicy::Number,
+            &mut log,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan,
+            vec![
+                (root.join("a.jpg"), root.join("2023-05_1.jpg")),
+                (root.join("b.jpg"), root.join("2023-05_2.jpg")),
+                (root.join("c.png"), root.join("2023-05.png")),
+            ]
+        );
+        assert_eq!(log.summary.skipped, 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_write_tree() {
+        let root = env::temp_dir().join("ram-utils-tree-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("photos/2023")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        File::create(root.join("photos/2023/beach day.jpg")).unwrap();
+        File::create(root.join("photos/cover.png")).unwrap();
+        File::create(root.join("notes.txt")).unwrap();
+
+        let render = |options: &TreeOptions| {
+            let mut out = Vec::new();
+            write_tree(&mut out, &root, options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let expected = [
+            &root.display().to_string(),
+            "├── notes.txt",
+            "└── photos",
+            "    ├── 2023",
+            "    │   └── beach day.jpg",
+            "    └── cover.png",
+            "",
+        ];
+        assert_eq!(render(&TreeOptions::default()), expected.join("\n"));
+
+        let options = TreeOptions {
+            ignore_files: true,
+            walk: WalkOptions {
+                max_depth: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&options),
+            format!("{}\n└── photos\n", root.display())
+        );
+
+        let options = TreeOptions {
+            preview: Some(Transform::Style(CaseStyle::Snake)),
+            ..Default::default()
+        };
+        assert!(render(&options).contains("│   └── beach day.jpg -> beach_day.jpg\n"));
+        assert!(render(&options).contains("├── notes.txt\n"));
+
+        // -- Files are annotated, after their new name
+        fs::write(root.join("notes.txt"), vec![0; 2048]).unwrap();
+        let options = TreeOptions {
+            preview: Some(Transform::Case(LetterCase::UpperCase)),
+            sizes: true,
+            extensions: true,
+            ..Default::default()
+        };
+        let output = render(&options);
+        assert!(output.contains("├── notes.txt -> NOTES.TXT [2048 bytes, txt]\n"));
+        assert!(output.contains("└── photos -> PHOTOS\n"));
+
+        let options = TreeOptions {
+            sizes: true,
+            human: true,
+            ..Default::default()
+        };
+        assert!(render(&options).contains("├── notes.txt [2.0K]\n"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_path_stats() {
+        let root = env::temp_dir().join("ram-utils-stat-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("old.txt"), vec![0; 100]).unwrap();
+        fs::write(root.join("a/b/deep.txt"), vec![0; 20]).unwrap();
+        fs::write(root.join("a/a much longer name.md"), vec![0; 3]).unwrap();
+        let hour_ago = SystemTime::now() - Duration::from_secs(60 * 60);
+        File::options()
+            .write(true)
+            .open(root.join("old.txt"))
+            .unwrap()
+            .set_modified(hour_ago)
+            .unwrap();
+
+        let stats = path_stats(&root, &WalkOptions::default(), &mut quiet_logger()).unwrap();
+        assert_eq!(stats.kind, "dir");
+        assert_eq!((stats.files, stats.dirs, stats.symlinks), (3, 2, 0));
+        assert_eq!(stats.size, 123);
+        assert_eq!(stats.oldest.as_ref().unwrap().0, root.join("old.txt"));
+        assert_eq!(stats.deepest, Some((root.join("a/b/deep.txt"), 3)));
+        assert_eq!(
+            stats.longest_name,
+            Some(root.join("a/a much longer name.md"))
+        );
+
+        let fields = path_stat_fields(&stats, true);
+        let field = |label| fields.iter().find(|f| f.0 == label).map(|f| f.1.as_str());
+        assert_eq!(field("Size"), Some("123B"));
+        assert_eq!(field("Entries"), Some("3 files, 2 directories, 0 symlinks"));
+        assert!(field("Deepest path")
+            .unwrap()
+            .ends_with("deep.txt (3 levels)"));
+
+        // -- A file is only counted itself
+        let stats = path_stats(
+            &root.join("old.txt"),
+            &WalkOptions::default(),
+            &mut quiet_logger(),
+        )
+        .unwrap();
+        assert_eq!(
+            (stats.kind.as_str(), stats.files, stats.size),
+            ("file", 1, 100)
+        );
+        let fields = path_stat_fields(&stats, false);
+        assert!(fields.iter().all(|f| f.0 != "Entries"));
+
+        let mut output = Vec::new();
+        write_path_stats_csv(&mut output, &fields).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("\nsize,100 bytes\n"));
+
+        assert_eq!(permissions_label(Some(0o750), false), "rwxr-x--- (750)");
+        assert_eq!(permissions_label(None, true), "read-only");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_summary_exit_code() {
+        let summary = |files, errors| Summary {
+            files,
+            errors,
+            ..Summary::default()
+        };
+        assert_eq!(summary(2, 0).exit_code(), 0);
+        assert_eq!(summary(0, 0).exit_code(), EXIT_NOTHING_TO_DO);
+        assert_eq!(summary(0, 1).exit_code(), EXIT_ERROR);
+        assert_eq!(summary(2, 1).exit_code(), EXIT_PARTIAL);
+        assert_eq!(summary(0, 0).report_exit_code(), 0);
+        assert_eq!(summary(2, 1).report_exit_code(), EXIT_ERROR);
+    }
+}
@@ -1,29 +1,152 @@
 extern crate clap;
+extern crate ctrlc;
 extern crate failure;
+extern crate globset;
+extern crate ram_utils;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate termcolor;
+extern crate toml;
 
-use std::collections::HashMap;
-use std::ffi::OsStr;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::mem;
+use std::path::{self, Path, PathBuf};
+use std::process;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
 
-use clap::{App, Arg, ArgMatches, SubCommand};
-use failure::Error;
+use clap::{App, Arg, ArgMatches, Shell, SubCommand};
+use failure::{err_msg, Error};
+use globset::Glob;
+use regex::Regex;
+use serde::Deserialize;
+use termcolor::{ColorChoice, NoColor, StandardStream, WriteColor};
 
-enum LetterCase {
-    UpperCase,
-    LowerCase,
+use ram_utils::*;
+
+/// Defaults for command line options, read from a TOML config file
+///
+/// Options passed explicitly on the command line take precedence over the config file.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Config {
+    /// Convert directories recursively, like `-r`
+    recursive: Option<bool>,
+    /// Include hidden entries, like `--include-hidden`
+    include_hidden: Option<bool>,
+    /// Color report output when writing to a terminal, disabled by `--no-color`
+    color: Option<bool>,
+    /// Words whose casing is kept, like `--keep`
+    keep: Option<Vec<String>>,
+    /// Extensions counted as one by `--compound-ext`, e.g. `tar.gz`
+    compound_ext: Option<Vec<String>>,
+    /// Extensions of each `categorize` category, added to (or moved from) the built-in ones
+    categories: Option<HashMap<String, Vec<String>>>,
+    /// Where the renames of the last conversion are recorded for `undo`, `default_journal_path`
+    /// if not set in the config file
+    journal: Option<PathBuf>,
+    /// Patterns of entries to skip, like `--exclude`
+    exclude: Option<Vec<String>>,
+    /// What to do when a new name is already taken, like `--on-conflict`
+    on_conflict: Option<String>,
+    /// Named sets of options selected with `--profile`, overriding the ones above
+    profiles: Option<HashMap<String, Config>>,
 }
 
-fn main() {
+impl Config {
+    /// Returns this config with the options set in `profile` taking precedence
+    fn with_profile(self, profile: Config) -> Config {
+        Config {
+            recursive: profile.recursive.or(self.recursive),
+            include_hidden: profile.include_hidden.or(self.include_hidden),
+            color: profile.color.or(self.color),
+            keep: profile.keep.or(self.keep),
+            compound_ext: profile.compound_ext.or(self.compound_ext),
+            categories: profile.categories.or(self.categories),
+            journal: profile.journal.or(self.journal),
+            exclude: profile.exclude.or(self.exclude),
+            on_conflict: profile.on_conflict.or(self.on_conflict),
+            profiles: None,
+        }
+    }
+}
+
+/// Output format selected by `--format`
+#[derive(PartialEq)]
+enum ReportFormat {
+    /// Aligned, optionally colored columns for reading in a terminal
+    Text,
+    /// Records for scripts, e.g. the renames made along with the counts and errors
+    Json,
+    /// A header row followed by one row per entry, for spreadsheets
+    Csv,
+}
+
+/// Answer to the per-rename prompt of `--interactive`
+#[derive(Debug, PartialEq)]
+enum RenameAnswer {
+    Yes,
+    No,
+    /// Make this and every remaining rename without asking
+    All,
+    /// Stop without making this or any remaining rename
+    Quit,
+}
+
+/// Exit codes of the conversion subcommands, as shown in their help
+const CONVERT_EXIT_CODES: &str = "EXIT CODES:
+    0    At least one entry was renamed
+    1    An error occurred and nothing was renamed
+    2    Nothing needed renaming
+    3    Some entries were renamed, but others failed
+    64   Invalid arguments";
+
+/// Exit codes of every subcommand, as shown in the main help
+const EXIT_CODES: &str = "EXIT CODES:
+    0    Success
+    1    An error occurred
+    2    Nothing needed renaming or moving
+    3    Some entries were renamed, but others failed
+    64   Invalid arguments";
+
+/// Builds the command line interface of every subcommand
+fn build_cli() -> App<'static, 'static> {
     let path_arg = Arg::with_name("path")
         .help("File or directory path")
         .required(true)
         .index(1);
 
+    let convert_path_arg = path_arg.clone().required_unless("stdin");
+
+    let stdin_arg = Arg::with_name("stdin")
+        .long("stdin")
+        .help("Read the paths to convert from stdin, one per line");
+
+    let null_arg = Arg::with_name("null")
+        .short("0")
+        .long("null")
+        .requires("stdin")
+        .help("Paths read from stdin are separated by NUL instead of newlines");
+
     let recursive_arg = Arg::with_name("recursive")
         .short("r")
         .help("Convert directories recursively");
 
+    let convert_max_depth_arg = Arg::with_name("max-depth")
+        .long("max-depth")
+        .value_name("n")
+        .takes_value(true)
+        .validator(|v| match v.parse::<usize>() {
+            Ok(depth) if depth > 0 => Ok(()),
+            _ => Err(format!("Invalid depth: {}", v)),
+        })
+        .help("Convert directories recursively, but only this many levels below them, 1 being their own entries");
+
     let ignore_files_arg = Arg::with_name("ignore-files")
         .long("ignore-files")
         .conflicts_with("ignore-dirs")
@@ -34,365 +157,4281 @@ fn main() {
         .conflicts_with("ignore-files")
         .help("Ignore directories during conversion");
 
-    let args = App::new("RAM Utils")
+    let only_ext_arg = Arg::with_name("only-ext")
+        .long("only-ext")
+        .value_name("ext")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Only convert files with this extension (may be repeated)");
+
+    let keep_arg = Arg::with_name("keep")
+        .long("keep")
+        .value_name("word")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Keep the casing of this word, e.g. `NASA` (may be repeated)");
+
+    let keep_file_arg = Arg::with_name("keep-file")
+        .long("keep-file")
+        .value_name("path")
+        .takes_value(true)
+        .help("Keep the casing of each word listed in this file, one per line");
+
+    let word_sep_arg = Arg::with_name("word-sep")
+        .long("word-sep")
+        .value_name("chars")
+        .takes_value(true)
+        .help("Characters separating words in the original name [default: \" _-\"]");
+
+    let case_word_sep_arg = word_sep_arg.clone().requires("out-sep");
+
+    let out_sep_arg = Arg::with_name("out-sep")
+        .long("out-sep")
+        .value_name("sep")
+        .takes_value(true)
+        .help("Rejoin the words of the file stem with this separator");
+
+    let ignore_ext_arg = Arg::with_name("ignore-ext")
+        .long("ignore-ext")
+        .value_name("ext")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Don't convert files with this extension (may be repeated)");
+
+    let min_size_arg = Arg::with_name("min-size")
+        .long("min-size")
+        .value_name("size")
+        .takes_value(true)
+        .validator(|v| parse_size(&v).map(|_| ()).map_err(|e| e.to_string()))
+        .help("Skip files smaller than this size, e.g. `512K` or `1.5M`");
+
+    let max_size_arg = Arg::with_name("max-size")
+        .long("max-size")
+        .value_name("size")
+        .takes_value(true)
+        .validator(|v| parse_size(&v).map(|_| ()).map_err(|e| e.to_string()))
+        .help("Skip files larger than this size, e.g. `2G`");
+
+    let newer_than_arg = Arg::with_name("newer-than")
+        .long("newer-than")
+        .value_name("time")
+        .takes_value(true)
+        .validator(|v| {
+            parse_time(&v, SystemTime::now())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .help("Skip files modified before this long ago (e.g. `7d`, `24h`) or date (`2023-01-01`)");
+
+    let older_than_arg = Arg::with_name("older-than")
+        .long("older-than")
+        .value_name("time")
+        .takes_value(true)
+        .validator(|v| {
+            parse_time(&v, SystemTime::now())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .help("Skip files modified after this long ago (e.g. `7d`, `24h`) or date (`2023-01-01`)");
+
+    let respect_gitignore_arg = Arg::with_name("respect-gitignore")
+        .long("respect-gitignore")
+        .help("Skip files and directories matched by `.gitignore` files");
+
+    let exclude_dir_arg = Arg::with_name("exclude-dir")
+        .long("exclude-dir")
+        .value_name("name")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .validator(|v| Glob::new(&v).map(|_| ()).map_err(|e| e.to_string()))
+        .help("Skip directories with this name (or glob, e.g. `build*`) and everything in them");
+
+    let include_arg = Arg::with_name("include")
+        .long("include")
+        .value_name("glob")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .validator(|v| Glob::new(&v).map(|_| ()).map_err(|e| e.to_string()))
+        .help("Only include files whose path below the given directory matches this glob, e.g. `*.jpg`");
+
+    let exclude_arg = Arg::with_name("exclude")
+        .long("exclude")
+        .value_name("glob")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .validator(|v| Glob::new(&v).map(|_| ()).map_err(|e| e.to_string()))
+        .help("Skip files and directories whose path below the given directory matches this glob, e.g. `node_modules/**`");
+
+    let parents_arg = Arg::with_name("parents")
+        .long("parents")
+        .help("Also convert the names of the directories leading to the path");
+
+    let parents_root_arg = Arg::with_name("parents-root")
+        .long("parents-root")
+        .value_name("dir")
+        .takes_value(true)
+        .requires("parents")
+        .help("Don't convert --parents at or above this directory [default: current directory]");
+
+    let backup_arg = Arg::with_name("backup")
+        .long("backup")
+        .value_name("manifest")
+        .takes_value(true)
+        .conflicts_with_all(&["into", "plan-json"])
+        .help("Record each rename in this file so `restore-case` can undo it");
+
+    let interactive_arg = Arg::with_name("interactive")
+        .short("i")
+        .long("interactive")
+        .conflicts_with_all(&["yes", "dry-run", "plan-json", "into"])
+        .help("Ask before each rename: y(es), n(o), a(ll) remaining or q(uit)");
+
+    let only_stem_arg = Arg::with_name("only-stem")
+        .long("only-stem")
+        .conflicts_with("only-extension")
+        .help("Only convert the file stem, e.g. `Photo.JPG` => `photo.JPG` with lower");
+
+    let only_extension_arg = Arg::with_name("only-extension")
+        .long("only-extension")
+        .help("Only convert the extension, e.g. `Photo.JPG` => `Photo.jpg` with lower");
+
+    let follow_symlinks_arg = Arg::with_name("follow-symlinks")
+        .long("follow-symlinks")
+        .help("Descend into symlinked directories, except ones linking back up the tree");
+
+    let threads_arg = Arg::with_name("threads")
+        .long("threads")
+        .value_name("n")
+        .takes_value(true)
+        .conflicts_with_all(&["interactive", "apply-to"])
+        .validator(|v| match v.parse::<usize>() {
+            Ok(threads) if threads > 0 => Ok(()),
+            _ => Err(format!("Invalid number of threads: {}", v)),
+        })
+        .help("Rename on up to n threads, a directory level at a time [default: 1]");
+
+    let no_journal_arg = Arg::with_name("no-journal")
+        .long("no-journal")
+        .help("Don't record the renames in the journal read by `undo`");
+
+    let relative_to_arg = Arg::with_name("relative-to")
+        .long("relative-to")
+        .value_name("dir")
+        .takes_value(true)
+        .help("Show the renamed paths relative to this directory [default: the given directory]");
+
+    let plan_json_arg = Arg::with_name("plan-json")
+        .long("plan-json")
+        .conflicts_with_all(&["into", "yes"])
+        .help("Print the planned renames as a JSON array without renaming anything");
+
+    let apply_to_arg = Arg::with_name("apply-to")
+        .long("apply-to")
+        .value_name("scope")
+        .takes_value(true)
+        .possible_values(&["names", "paths"])
+        .conflicts_with_all(&["into", "parents", "backup"])
+        .help("Convert only entry names (the default), or every component of the files' paths below the given directory, merging directories that end up with the same name");
+
+    let into_arg = Arg::with_name("into")
+        .long("into")
+        .value_name("dir")
+        .takes_value(true)
+        .conflicts_with_all(&["parents", "dry-run"])
+        .help("Copy the files into this directory under their converted names instead of renaming");
+
+    let quiet_skips_arg = Arg::with_name("quiet-skips")
+        .long("quiet-skips")
+        .help("Don't list the entries left unchanged, even with -vv");
+
+    let on_conflict_arg = Arg::with_name("on-conflict")
+        .long("on-conflict")
+        .value_name("policy")
+        .takes_value(true)
+        .possible_values(&["error", "skip", "number", "suffix", "overwrite", "fail"])
+        .default_value("error")
+        .help(
+            "What to do when the new name is already taken: fail that rename (error), leave the \
+             entry (skip), append _1, _2, ... (number or suffix), replace the existing entry \
+             (overwrite) or stop the whole run at the first conflict (fail)",
+        );
+
+    let keep_going_arg = Arg::with_name("keep-going").long("keep-going").help(
+        "Carry on past failed renames instead of stopping at the first, and list each of them \
+         again at the end. --on-conflict fail still stops at the first conflict",
+    );
+
+    let depth_first_arg = Arg::with_name("depth-first")
+        .long("depth-first")
+        .conflicts_with("breadth-first")
+        .help("Rename one directory tree at a time, contents before directories (default)");
+
+    let breadth_first_arg = Arg::with_name("breadth-first")
+        .long("breadth-first")
+        .conflicts_with("depth-first")
+        .help("Rename level by level, starting with the deepest entries");
+
+    let normalize_arg = Arg::with_name("normalize")
+        .long("normalize")
+        .value_name("form")
+        .takes_value(true)
+        .possible_values(&["nfc", "nfd", "none"])
+        .default_value("none")
+        .help("Normalize names to this Unicode form before and after converting them");
+
+    let locale_arg = Arg::with_name("locale")
+        .long("locale")
+        .value_name("lang")
+        .takes_value(true)
+        .possible_values(&["tr"])
+        .help("Use this language's casing rules, which only changes how `i` and `I` are cased");
+
+    let include_hidden_arg = Arg::with_name("include-hidden")
+        .long("include-hidden")
+        .help("Include hidden files and directories (names starting with `.`)");
+
+    let skip_hidden_arg = Arg::with_name("skip-hidden")
+        .long("skip-hidden")
+        .conflicts_with("include-hidden")
+        .help("Skip hidden files and directories, overriding the config file");
+
+    let output_arg = Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .value_name("path")
+        .takes_value(true)
+        .help("Write the report to this file instead of stdout");
+
+    let dry_run_arg = Arg::with_name("dry-run")
+        .long("dry-run")
+        .help("Print the planned changes without modifying anything");
+
+    let preview_arg = Arg::with_name("preview")
+        .long("preview")
+        .value_name("n")
+        .takes_value(true)
+        .requires("dry-run")
+        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+        .help("Only print the first n planned changes of a dry run, followed by the totals");
+
+    let yes_arg = Arg::with_name("yes")
+        .short("y")
+        .long("yes")
+        .help("Don't ask for confirmation before making changes");
+
+    // Arguments shared by every conversion subcommand, each adding its own path and naming options
+    let conversion_args = |cmd: App<'static, 'static>| {
+        cmd.arg(&dry_run_arg)
+            .arg(&preview_arg)
+            .arg(&yes_arg)
+            .arg(&stdin_arg)
+            .arg(&null_arg)
+            .arg(&recursive_arg)
+            .arg(&convert_max_depth_arg)
+            .arg(&depth_first_arg)
+            .arg(&breadth_first_arg)
+            .arg(&parents_arg)
+            .arg(&parents_root_arg)
+            .arg(&into_arg)
+            .arg(&on_conflict_arg)
+            .arg(&keep_going_arg)
+            .arg(&quiet_skips_arg)
+            .arg(&backup_arg)
+            .arg(&no_journal_arg)
+            .arg(&interactive_arg)
+            .arg(&threads_arg)
+            .arg(&follow_symlinks_arg)
+            .arg(&apply_to_arg)
+            .arg(&relative_to_arg)
+            .arg(&plan_json_arg)
+            .arg(&ignore_files_arg)
+            .arg(&ignore_dirs_arg)
+            .arg(&only_ext_arg)
+            .arg(&ignore_ext_arg)
+            .arg(&min_size_arg)
+            .arg(&max_size_arg)
+            .arg(&newer_than_arg)
+            .arg(&older_than_arg)
+            .arg(&include_hidden_arg)
+            .arg(&respect_gitignore_arg)
+            .arg(&exclude_dir_arg)
+            .arg(&include_arg)
+            .arg(&exclude_arg)
+            .arg(&skip_hidden_arg)
+    };
+
+    App::new("RAM Utils")
         .version("0.1")
         .author("Ralph Minderhoud <mail@ralphminderhoud.com>")
         .about("Simple utilities")
-        .subcommand(
+        .after_help(EXIT_CODES)
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("path")
+                .takes_value(true)
+                .global(true)
+                .help("Read default options from this config file"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("name")
+                .takes_value(true)
+                .global(true)
+                .help("Use the options of this [profiles.<name>] table of the config file"),
+        )
+        .arg(
+            Arg::with_name("no-config")
+                .long("no-config")
+                .conflicts_with("config")
+                .global(true)
+                .help("Ignore any config file"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .conflicts_with("verbose")
+                .global(true)
+                .help("Only print errors"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .conflicts_with("quiet")
+                .global(true)
+                .help(
+                    "Print every change, and with -vv every skipped entry (and why) and the \
+                     timings too",
+                ),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .value_name("when")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .global(true)
+                .help("When to color report output, auto meaning only in a terminal"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "csv"])
+                .global(true)
+                .help(
+                    "Print text, or the renames (of the conversions) or counts (of unique_ext) \
+                     as JSON or CSV records",
+                ),
+        )
+        .arg(
+            Arg::with_name("timings")
+                .long("timings")
+                .global(true)
+                .help("Print how long the command took and how many entries it read per second"),
+        )
+        .subcommand(conversion_args(
             SubCommand::with_name("upper")
                 .about("Convert files and/or directories to upper case")
-                .arg(&path_arg)
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&only_stem_arg)
+                .arg(&only_extension_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg)
+                .arg(&case_word_sep_arg)
+                .arg(&out_sep_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("lower")
+                .about("Convert files and/or directories to lower case")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&only_stem_arg)
+                .arg(&only_extension_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg)
+                .arg(&case_word_sep_arg)
+                .arg(&out_sep_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("case")
+                .about("Convert the stems and extensions of names to separate cases, e.g. `REPORT.pdf`")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("stem-case")
+                        .long("stem-case")
+                        .value_name("case")
+                        .takes_value(true)
+                        .possible_values(&["upper", "lower", "keep"])
+                        .default_value("keep")
+                        .help("Case of the file stem"),
+                )
+                .arg(
+                    Arg::with_name("ext-case")
+                        .long("ext-case")
+                        .value_name("case")
+                        .takes_value(true)
+                        .possible_values(&["upper", "lower", "keep"])
+                        .default_value("keep")
+                        .help("Case of the extension"),
+                )
+                .arg(&convert_path_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("snake")
+                .about("Convert file and/or directory names to snake_case")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg)
+                .arg(&word_sep_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("kebab")
+                .about("Convert file and/or directory names to kebab-case")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg)
+                .arg(&word_sep_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("camel")
+                .about("Convert file and/or directory names to camelCase")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg)
+                .arg(&word_sep_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("pascal")
+                .about("Convert file and/or directory names to PascalCase")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg)
+                .arg(&word_sep_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("title")
+                .about("Convert file and/or directory names to Title Case")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&normalize_arg)
+                .arg(&locale_arg)
+                .arg(&keep_arg)
+                .arg(&keep_file_arg)
+                .arg(&word_sep_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("trim")
+                .about("Trim and collapse whitespace in file and/or directory names")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg)
+                .arg(&normalize_arg)
+                .arg(
+                    Arg::with_name("strip-before-ext")
+                        .long("strip-before-ext")
+                        .help("Also remove whitespace before the extension, e.g. `file .txt`"),
+                ),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("strip-diacritics")
+                .about("Remove accents from file and/or directory names, e.g. `café` => `cafe`")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("placeholder")
+                        .long("placeholder")
+                        .value_name("text")
+                        .takes_value(true)
+                        .default_value("")
+                        .help("Replace other non-ASCII characters with this instead of dropping them"),
+                )
+                .arg(&convert_path_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("normalize")
+                .about("Normalize file and/or directory names to a Unicode form, e.g. after copying them from macOS")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("form")
+                        .long("form")
+                        .value_name("form")
+                        .takes_value(true)
+                        .possible_values(&["nfc", "nfd"])
+                        .default_value("nfc")
+                        .help("Composed (nfc, as typed on most systems) or decomposed (nfd, as stored by macOS)"),
+                )
+                .arg(&convert_path_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("despace")
+                .about("Replace whitespace in file and/or directory names, e.g. My Cool File.txt => My_Cool_File.txt")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("with")
+                        .long("with")
+                        .value_name("separator")
+                        .takes_value(true)
+                        .possible_values(&["underscore", "dash"])
+                        .default_value("underscore")
+                        .help("Replaces each run of whitespace; whitespace at the ends and before the extension is removed"),
+                )
+                .arg(
+                    Arg::with_name("lower")
+                        .long("lower")
+                        .help("Also lower case the names, e.g. My Cool File.txt => my_cool_file.txt"),
+                )
+                .arg(&convert_path_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("fix-dupext")
+                .about("Collapse repeated extensions in file and/or directory names, e.g. photo.JPG.jpg => photo.jpg")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&convert_path_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("sanitize")
+                .about("Replace characters that are invalid or troublesome on some platforms in file and/or directory names")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("replacement")
+                        .long("replacement")
+                        .value_name("text")
+                        .takes_value(true)
+                        .default_value("_")
+                        .help("Replaces each of <>:\"/\\|?* and control characters, may be empty to remove them. Trailing spaces and dots are always trimmed"),
+                )
+                .arg(&convert_path_arg),
+        ))
+        .subcommand(conversion_args(
+            SubCommand::with_name("sub")
+                .about("Replace matches of a regular expression in file and/or directory names")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("pattern")
+                        .help("Regular expression matched against each name, e.g. `IMG_(\\d+)`")
+                        .required(true)
+                        .validator(|v| Regex::new(&v).map(|_| ()).map_err(|e| e.to_string()))
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("replacement")
+                        .help("Replacement for each match, with `$1` or `${name}` for capture groups")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(convert_path_arg.clone().index(3)),
+        ))
+        .subcommand(
+            SubCommand::with_name("restore-case")
+                .about("Rename entries back to their original names recorded by --backup")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("manifest")
+                        .help("Manifest written by --backup")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(&dry_run_arg)
+                .arg(&yes_arg)
+                .arg(&on_conflict_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Rename the entries renamed by the last conversion back to their previous names")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(&dry_run_arg)
+                .arg(&yes_arg)
+                .arg(&on_conflict_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Apply the renames of a plan written by --plan-json")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("plan")
+                        .help("Plan written by --plan-json, or - to read it from stdin")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(&dry_run_arg)
+                .arg(&preview_arg)
+                .arg(&yes_arg)
+                .arg(&on_conflict_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("rename-from")
+                .about("Apply the renames listed in a CSV or TSV file of old and new paths")
+                .after_help(CONVERT_EXIT_CODES)
+                .arg(
+                    Arg::with_name("mapping")
+                        .help(
+                            "File with an old and a new path on each row, or - to read it from \
+                             stdin. Rows are renamed in order, once all of them are checked",
+                        )
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("tsv")
+                        .long("tsv")
+                        .help("Read tab separated rows, the default for files ending in .tsv"),
+                )
+                .arg(&dry_run_arg)
+                .arg(&preview_arg)
+                .arg(&yes_arg)
+                .arg(&on_conflict_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("template")
+                .alias("rename-template")
+                .about("Rename files from a pattern like `{year}-{month}-{name}.{ext}`")
+                .arg(
+                    Arg::with_name("pattern")
+                        .help(
+                            "Pattern for the new names, using the placeholders {name}, {ext}, \
+                             {year}, {month}, {day}, {date}, {time} (of the modified time), {size}, \
+                             {parent} (directory name) and {n} (counter, e.g. {n:03} for 001)",
+                        )
+                        .required(true)
+                        .index(1),
+                )
+                .arg(path_arg.clone().index(2))
                 .arg(&recursive_arg)
-                .arg(&ignore_files_arg)
-                .arg(&ignore_dirs_arg),
+                .arg(&dry_run_arg)
+                .arg(&preview_arg)
+                .arg(&relative_to_arg)
+                .arg(&yes_arg)
+                .arg(&on_conflict_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
         )
         .subcommand(
-            SubCommand::with_name("lower")
-                .about("Convert files and/or directories to lower case")
-                .arg(&path_arg)
+            SubCommand::with_name("photo-rename")
+                .about("Rename photos by when they were taken, e.g. 2023-05-01_142233.jpg")
+                .after_help(
+                    "The date is read from the EXIF DateTimeOriginal of JPEG and TIFF based raw \
+                     files (CR2, NEF, ARW, DNG, ...), falling back to the modified time. Other \
+                     files are left alone.",
+                )
+                .arg(path_arg.clone().index(1))
+                .arg(
+                    Arg::with_name("pattern")
+                        .long("pattern")
+                        .value_name("pattern")
+                        .takes_value(true)
+                        .default_value("{date}_{time}.{ext}")
+                        .help(
+                            "Pattern for the new names, using the placeholders of template, \
+                             dated by when the photo was taken, and {model} (camera model)",
+                        ),
+                )
                 .arg(&recursive_arg)
+                .arg(&dry_run_arg)
+                .arg(&preview_arg)
+                .arg(&relative_to_arg)
+                .arg(&yes_arg)
+                .arg(&on_conflict_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("tree")
+                .about("Print the directory structure")
+                .arg(&path_arg)
+                .arg(
+                    Arg::with_name("max-depth")
+                        .long("max-depth")
+                        .value_name("n")
+                        .takes_value(true)
+                        .validator(|v| {
+                            v.parse::<usize>()
+                                .map(|_| ())
+                                .map_err(|_| format!("Invalid depth: {}", v))
+                        })
+                        .help("Only descend this many levels below the directory"),
+                )
+                .arg(
+                    Arg::with_name("preview")
+                        .long("preview")
+                        .value_name("style")
+                        .takes_value(true)
+                        .possible_values(&["upper", "lower", "snake", "kebab", "camel", "pascal", "title"])
+                        .help("Show what each entry would be renamed to, e.g. `abc -> ABC`"),
+                )
+                .arg(
+                    Arg::with_name("sizes")
+                        .long("sizes")
+                        .help("Show the size of each file"),
+                )
+                .arg(
+                    Arg::with_name("human")
+                        .long("human")
+                        .help("Show the sizes in K, M, G, ... (powers of 1024) rather than bytes, implies --sizes"),
+                )
+                .arg(
+                    Arg::with_name("extensions")
+                        .long("extensions")
+                        .help("Show the extension of each file, as unique_ext counts it"),
+                )
                 .arg(&ignore_files_arg)
-                .arg(&ignore_dirs_arg),
+                .arg(&ignore_dirs_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg)
+                .arg(&output_arg),
         )
         .subcommand(
             SubCommand::with_name("unique_ext")
                 .about("Find all unique extensions in this directory")
-                .arg(&path_arg),
+                .arg(&path_arg)
+                .arg(&min_size_arg)
+                .arg(&max_size_arg)
+                .arg(&newer_than_arg)
+                .arg(&older_than_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg)
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .possible_values(&["name", "count", "size"])
+                        .default_value("name")
+                        .help("Order extensions by name, by descending file count or by descending total size"),
+                )
+                .arg(&follow_symlinks_arg)
+                .arg(
+                    Arg::with_name("no-recursive")
+                        .long("no-recursive")
+                        .help("Only count the files directly inside the directory"),
+                )
+                .arg(
+                    Arg::with_name("max-depth")
+                        .long("max-depth")
+                        .value_name("n")
+                        .takes_value(true)
+                        .conflicts_with("no-recursive")
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(depth) if depth > 0 => Ok(()),
+                            _ => Err(format!("Invalid depth: {}", v)),
+                        })
+                        .help("Only count the files this many levels below the directory, 1 being its own files"),
+                )
+                .arg(
+                    Arg::with_name("reverse")
+                        .long("reverse")
+                        .help("Reverse the order of --sort, e.g. least common extension first"),
+                )
+                .arg(
+                    Arg::with_name("compound-ext")
+                        .long("compound-ext")
+                        .help("Count extensions like `tar.gz` as one instead of as `gz`"),
+                )
+                .arg(
+                    Arg::with_name("min-count")
+                        .long("min-count")
+                        .value_name("n")
+                        .takes_value(true)
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Leave out extensions found in fewer than n files"),
+                )
+                .arg(
+                    Arg::with_name("roll-rare")
+                        .long("roll-rare")
+                        .requires("min-count")
+                        .help("List the extensions left out by --min-count together as `(other)`"),
+                )
+                .arg(
+                    Arg::with_name("group-by-dir")
+                        .long("group-by-dir")
+                        .help("Count the extensions of each top-level subdirectory separately, followed by all of them combined"),
+                )
+                .arg(
+                    Arg::with_name("ignore-case")
+                        .long("ignore-case")
+                        .help("Count extensions differing only in case as one, e.g. JPG and jpg as jpg"),
+                )
+                .arg(
+                    Arg::with_name("variants")
+                        .long("variants")
+                        .requires("ignore-case")
+                        .help("Also list the spellings of the extensions found in more than one case"),
+                )
+                .arg(
+                    Arg::with_name("show-files")
+                        .long("show-files")
+                        .value_name("ext")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .conflicts_with_all(&["group-by-dir", "min-count", "sizes", "human"])
+                        .help("List the files of each extension, or only those of --show-files=ext, rather than counting them"),
+                )
+                .arg(
+                    Arg::with_name("sizes")
+                        .long("sizes")
+                        .conflicts_with("group-by-dir")
+                        .help("Also list the total size of the files of each extension"),
+                )
+                .arg(
+                    Arg::with_name("human")
+                        .long("human")
+                        .conflicts_with("group-by-dir")
+                        .help("List the sizes in K, M, G, ... (powers of 1024) rather than bytes, implies --sizes"),
+                )
+                .arg(&output_arg)
+                .arg(
+                    Arg::with_name("no-color")
+                        .long("no-color")
+                        .conflicts_with("color")
+                        .help("Disable colored output, like `--color never`"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("categorize")
+                .about("Count files by category (images, audio, video, ...) and extension")
+                .arg(&path_arg)
+                .arg(&output_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("flatten")
+                .about("Move all nested files into a single directory")
+                .arg(
+                    Arg::with_name("src")
+                        .help("Directory to flatten")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("dst")
+                        .help("Directory to move the files into [default: src]")
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("prune")
+                        .long("prune")
+                        .help("Remove source directories left empty after flattening"),
+                )
+                .arg(&dry_run_arg)
+                .arg(&yes_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("prune-empty")
+                .about("Remove the empty directories of a tree, including those that only held empty directories")
+                .arg(&path_arg)
+                .arg(&dry_run_arg)
+                .arg(&yes_arg)
+                .arg(&relative_to_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("organize")
+                .about("Move the files of a directory into subdirectories, by extension into pdf/, jpg/ and none/ or by date into YYYY/MM/")
+                .arg(&path_arg)
+                .arg(
+                    Arg::with_name("by")
+                        .long("by")
+                        .value_name("by")
+                        .takes_value(true)
+                        .possible_values(&["ext", "date"])
+                        .default_value("ext")
+                        .help(
+                            "Group by the lower cased extension, with none/ for files without \
+                             one, or by the year and month of the modified time",
+                        ),
+                )
+                .arg(&dry_run_arg)
+                .arg(&yes_arg)
+                .arg(&relative_to_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("find-dupes")
+                .visible_alias("dedupe")
+                .about("Find files with identical contents, grouped by size then by hash, and the bytes they waste")
+                .arg(&path_arg)
+                .arg(
+                    Arg::with_name("delete")
+                        .long("delete")
+                        .visible_alias("keep-first")
+                        .help("Delete all but the first file of each duplicate group, after confirming"),
+                )
+                .arg(
+                    Arg::with_name("hash-algo")
+                        .long("hash-algo")
+                        .value_name("algo")
+                        .takes_value(true)
+                        .possible_values(&["blake3", "sha256", "md5"])
+                        .default_value("blake3")
+                        .help("Hash algorithm used to compare file contents"),
+                )
+                .arg(
+                    Arg::with_name("print0")
+                        .short("0")
+                        .long("print0")
+                        .help("Print only the paths, each followed by NUL, with an empty entry between groups"),
+                )
+                .arg(&output_arg)
+                .arg(&dry_run_arg)
+                .arg(&yes_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("links")
+                .about("List symlinks with their targets, flagging broken ones")
+                .arg(&path_arg)
+                .arg(
+                    Arg::with_name("broken-only")
+                        .long("broken-only")
+                        .help("Only list links whose target doesn't exist"),
+                )
+                .arg(
+                    Arg::with_name("delete-broken")
+                        .long("delete-broken")
+                        .help("Delete links whose target doesn't exist"),
+                )
+                .arg(&output_arg)
+                .arg(&dry_run_arg)
+                .arg(&yes_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("du")
+                .visible_alias("size")
+                .about("Print the total size of each subdirectory, followed by the grand total")
+                .arg(&path_arg)
+                .arg(
+                    Arg::with_name("human")
+                        .long("human")
+                        .help("Print sizes in K, M, G, ... (powers of 1024) rather than bytes"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .value_name("n")
+                        .takes_value(true)
+                        .default_value("1")
+                        .validator(|v| {
+                            v.parse::<usize>()
+                                .map(|_| ())
+                                .map_err(|_| format!("Invalid depth: {}", v))
+                        })
+                        .help("Report the directories up to this many levels below the directory"),
+                )
+                .arg(&output_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("stat")
+                .about("Print the size, entry counts, timestamps, permissions, deepest path and longest name of a file or directory")
+                .arg(&path_arg)
+                .arg(
+                    Arg::with_name("human")
+                        .long("human")
+                        .help("Print the size in K, M, G, ... (powers of 1024) rather than bytes"),
+                )
+                .arg(&output_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("dedupe-names")
+                .about("Find entries whose names only differ in case, which would clash when converted")
+                .after_help(
+                    "EXIT CODES:
+    0    No clashes were found
+    1    Clashes were found, or an error occurred",
+                )
+                .arg(&path_arg)
+                .arg(&output_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&exclude_dir_arg)
+                .arg(&include_arg)
+                .arg(&exclude_arg)
+                .arg(&skip_hidden_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Keep converting the names of new entries in a directory as they appear")
+                .arg(
+                    Arg::with_name("style")
+                        .possible_values(&["upper", "lower", "snake", "kebab", "camel", "pascal", "title"])
+                        .required(true)
+                        .index(1)
+                        .help("Conversion applied to new names"),
+                )
+                .arg(path_arg.clone().index(2))
+                .arg(
+                    Arg::with_name("once")
+                        .long("once")
+                        .help("Convert the entries already in the directory and exit"),
+                )
+                .arg(
+                    Arg::with_name("debounce")
+                        .long("debounce")
+                        .value_name("ms")
+                        .takes_value(true)
+                        .default_value("1000")
+                        .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Wait until an entry hasn't changed for this long before converting it"),
+                )
+                .arg(&recursive_arg)
+                .arg(&ignore_files_arg)
+                .arg(&ignore_dirs_arg)
+                .arg(&only_ext_arg)
+                .arg(&ignore_ext_arg)
+                .arg(&on_conflict_arg)
+                .arg(&include_hidden_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&skip_hidden_arg),
         )
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print a shell completion script")
+                .after_help(
+                    "E.g. for bash: ram-utils completions bash > ~/.local/share/bash-completion/completions/ram-utils",
+                )
+                .arg(
+                    Arg::with_name("shell")
+                        .required(true)
+                        .possible_values(&Shell::variants())
+                        .case_insensitive(true)
+                        .index(1),
+                ),
+        )
+}
+
+fn main() {
+    let args = match build_cli().get_matches_safe() {
+        Ok(args) => args,
+        // Help and version are written to stdout and exit successfully
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    // Global arguments are only present in the matches of the (sub)command they were passed to
+    let global_args = args.subcommand().1.unwrap_or(&args);
+    let config_path = global_args.value_of("config").or(args.value_of("config"));
+    let no_config = global_args.is_present("no-config") || args.is_present("no-config");
+    let profile = global_args.value_of("profile").or(args.value_of("profile"));
+    let timings = global_args.is_present("timings")
+        || args.is_present("timings")
+        || verbosity(global_args) == Verbosity::VeryVerbose;
+    let start = Instant::now();
 
-    match args.subcommand() {
-        ("upper", Some(sub_args)) => {
-            convert_case_command(sub_args, LetterCase::UpperCase);
+    let mut config = match load_config(config_path.map(Path::new), no_config, profile) {
+        Ok(config) => config,
+        Err(e) => {
+            Logger::new(Verbosity::Normal).error(&format!("Error: Invalid config file: {}", e));
+            process::exit(EXIT_ERROR);
         }
-        ("lower", Some(sub_args)) => {
-            convert_case_command(sub_args, LetterCase::LowerCase);
+    };
+    if config.journal.is_none() {
+        config.journal = default_journal_path();
+    }
+
+    let format = report_format(global_args);
+    if format != ReportFormat::Text && !has_formatted_output(args.subcommand_name().unwrap_or("")) {
+        Logger::new(Verbosity::Normal).warn(&format!(
+            "{} doesn't support --format, printing text",
+            args.subcommand_name().unwrap_or("ram-utils")
+        ));
+    }
+
+    let exit_code = match args.subcommand() {
+        ("upper", Some(sub_args)) => convert_command(
+            sub_args,
+            &config,
+            case_transform(sub_args, LetterCase::UpperCase),
+        ),
+        ("lower", Some(sub_args)) => convert_command(
+            sub_args,
+            &config,
+            case_transform(sub_args, LetterCase::LowerCase),
+        ),
+        ("case", Some(sub_args)) => {
+            let letter_case = |name| match sub_args.value_of(name) {
+                Some("upper") => Some(LetterCase::UpperCase),
+                Some("lower") => Some(LetterCase::LowerCase),
+                _ => None,
+            };
+            let transform = Transform::StemExt {
+                stem: letter_case("stem-case"),
+                ext: letter_case("ext-case"),
+            };
+            convert_command(sub_args, &config, transform)
+        }
+        ("snake", Some(sub_args)) => {
+            convert_command(sub_args, &config, Transform::Style(CaseStyle::Snake))
+        }
+        ("kebab", Some(sub_args)) => {
+            convert_command(sub_args, &config, Transform::Style(CaseStyle::Kebab))
+        }
+        ("camel", Some(sub_args)) => {
+            convert_command(sub_args, &config, Transform::Style(CaseStyle::Camel))
+        }
+        ("pascal", Some(sub_args)) => {
+            convert_command(sub_args, &config, Transform::Style(CaseStyle::Pascal))
+        }
+        ("title", Some(sub_args)) => {
+            convert_command(sub_args, &config, Transform::Style(CaseStyle::Title))
+        }
+        ("trim", Some(sub_args)) => {
+            let transform = Transform::Trim {
+                before_ext: sub_args.is_present("strip-before-ext"),
+            };
+            convert_command(sub_args, &config, transform)
+        }
+        ("strip-diacritics", Some(sub_args)) => {
+            let transform = Transform::StripDiacritics {
+                placeholder: sub_args.value_of("placeholder").unwrap_or("").to_string(),
+            };
+            convert_command(sub_args, &config, transform)
+        }
+        ("normalize", Some(sub_args)) => {
+            let transform = Transform::Normalize(match sub_args.value_of("form") {
+                Some("nfd") => Normalization::Nfd,
+                _ => Normalization::Nfc,
+            });
+            convert_command(sub_args, &config, transform)
+        }
+        ("despace", Some(sub_args)) => {
+            let transform = Transform::Despace {
+                separator: match sub_args.value_of("with") {
+                    Some("dash") => '-',
+                    _ => '_',
+                },
+                lower: sub_args.is_present("lower"),
+            };
+            convert_command(sub_args, &config, transform)
+        }
+        ("fix-dupext", Some(sub_args)) => {
+            convert_command(sub_args, &config, Transform::FixDuplicateExt)
+        }
+        ("sanitize", Some(sub_args)) => {
+            let transform = Transform::Sanitize {
+                replacement: sub_args.value_of("replacement").unwrap_or("_").to_string(),
+            };
+            convert_command(sub_args, &config, transform)
+        }
+        ("sub", Some(sub_args)) => {
+            // Validated when parsing the arguments
+            let pattern = sub_args.value_of("pattern").unwrap_or("");
+            let transform = Transform::Replace {
+                pattern: Regex::new(pattern).expect("invalid pattern"),
+                replacement: sub_args.value_of("replacement").unwrap_or("").to_string(),
+            };
+            convert_command(sub_args, &config, transform)
+        }
+        ("restore-case", Some(sub_args)) => restore_case_command(sub_args, &config),
+        ("undo", Some(sub_args)) => undo_command(sub_args, &config),
+        ("template", Some(sub_args)) => template_command(sub_args, &config, false),
+        ("photo-rename", Some(sub_args)) => template_command(sub_args, &config, true),
+        ("tree", Some(sub_args)) => tree_command(sub_args, &config),
+        ("unique_ext", Some(sub_args)) => find_unique_extensions_command(sub_args, &config),
+        ("categorize", Some(sub_args)) => categorize_command(sub_args, &config),
+        ("flatten", Some(sub_args)) => flatten_command(sub_args, &config),
+        ("prune-empty", Some(sub_args)) => prune_empty_command(sub_args, &config),
+        ("organize", Some(sub_args)) => organize_command(sub_args, &config),
+        ("find-dupes", Some(sub_args)) => find_duplicates_command(sub_args, &config),
+        ("links", Some(sub_args)) => links_command(sub_args, &config),
+        ("du", Some(sub_args)) => du_command(sub_args, &config),
+        ("stat", Some(sub_args)) => stat_command(sub_args, &config),
+        ("dedupe-names", Some(sub_args)) => dedupe_names_command(sub_args, &config),
+        ("apply", Some(sub_args)) => apply_command(sub_args, &config),
+        ("rename-from", Some(sub_args)) => rename_from_command(sub_args, &config),
+        ("watch", Some(sub_args)) => watch_command(sub_args, &config),
+        ("completions", Some(sub_args)) => {
+            // Validated when parsing the arguments
+            let shell = sub_args
+                .value_of("shell")
+                .and_then(|shell| shell.parse().ok())
+                .unwrap_or(Shell::Bash);
+            build_cli().gen_completions_to("ram-utils", shell, &mut io::stdout());
+            0
+        }
+        _ => 0,
+    };
+
+    if timings {
+        // Written to stderr so it never ends up in report output
+        let timings = Timings {
+            elapsed: start.elapsed(),
+            entries: ENTRIES_READ.load(Ordering::Relaxed),
+        };
+        eprintln!("{}", timings);
+    }
+
+    process::exit(exit_code);
+}
+
+/// Returns `ram-utils/journal.tsv` in the user's state directory (`$XDG_STATE_HOME` or
+/// `~/.local/state`)
+fn default_journal_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(dir.join("ram-utils").join("journal.tsv"))
+}
+
+/// Reads the config file (none with `no_config`) with the options of the `profile` table
+/// applied, checking the values clap would otherwise have validated
+fn load_config(
+    path: Option<&Path>,
+    no_config: bool,
+    profile: Option<&str>,
+) -> Result<Config, Error> {
+    let mut config = if no_config {
+        Config::default()
+    } else {
+        find_config(path)?
+    };
+
+    if let Some(name) = profile {
+        match config
+            .profiles
+            .as_mut()
+            .and_then(|profiles| profiles.remove(name))
+        {
+            Some(profile) => config = config.with_profile(profile),
+            None => return Err(err_msg(format!("Unknown profile: {}", name))),
+        }
+    }
+
+    if let Some(ref policy) = config.on_conflict {
+        if parse_conflict_policy(policy).is_none() {
+            return Err(err_msg(format!("Invalid on-conflict policy: {}", policy)));
+        }
+    }
+    if let Some(ref exclude) = config.exclude {
+        glob_set(exclude.iter().map(String::as_str))?;
+    }
+
+    Ok(config)
+}
+
+/// Loads the config file at `path`, or the first default config file found
+///
+/// The default locations are `./ram-utils.toml` followed by `ram-utils/config.toml` in the
+/// user's config directory (`$XDG_CONFIG_HOME` or `~/.config`). A missing default config is
+/// not an error, but a missing config passed explicitly is.
+fn find_config(path: Option<&Path>) -> Result<Config, Error> {
+    if let Some(path) = path {
+        return read_config(path);
+    }
+
+    let mut paths = vec![PathBuf::from("ram-utils.toml")];
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        paths.push(PathBuf::from(dir).join("ram-utils").join("config.toml"));
+    } else if let Some(dir) = env::var_os("HOME") {
+        paths.push(PathBuf::from(dir).join(".config/ram-utils/config.toml"));
+    }
+
+    match paths.iter().find(|path| path.is_file()) {
+        Some(path) => read_config(path),
+        None => Ok(Config::default()),
+    }
+}
+
+fn read_config(path: &Path) -> Result<Config, Error> {
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// Opens the destination of a report: the `--output` file (created or truncated), or stdout
+fn report_output(args: &ArgMatches) -> Result<Box<dyn Write>, Error> {
+    match args.value_of("output") {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .map_err(|e| err_msg(format!("Failed to create {}: {}", path, e)))?;
+            Ok(Box::new(io::BufWriter::new(file)))
+        }
+        None => Ok(Box::new(io::stdout())),
+    }
+}
+
+/// Returns the walk options from the command line, falling back to the config file
+fn walk_options(args: &ArgMatches, config: &Config) -> WalkOptions {
+    let include_hidden = if args.is_present("include-hidden") {
+        true
+    } else if args.is_present("skip-hidden") {
+        false
+    } else {
+        config.include_hidden.unwrap_or(false)
+    };
+
+    WalkOptions {
+        include_hidden,
+        respect_gitignore: args.is_present("respect-gitignore"),
+        // The globs were checked by the arguments' validators and the config's by load_config
+        exclude_dirs: args
+            .values_of("exclude-dir")
+            .map(|names| glob_set(names).expect("invalid glob"))
+            .unwrap_or_default(),
+        include: args
+            .values_of("include")
+            .map(|globs| glob_set(globs).expect("invalid glob"))
+            .unwrap_or_default(),
+        exclude: args
+            .values_of("exclude")
+            .map(|globs| glob_set(globs).expect("invalid glob"))
+            .or_else(|| {
+                let globs = config.exclude.as_ref()?;
+                Some(glob_set(globs.iter().map(String::as_str)).expect("invalid glob"))
+            })
+            .unwrap_or_default(),
+        min_size: args.value_of("min-size").and_then(|v| parse_size(v).ok()),
+        max_size: args.value_of("max-size").and_then(|v| parse_size(v).ok()),
+        newer_than: args
+            .value_of("newer-than")
+            .and_then(|v| parse_time(v, SystemTime::now()).ok()),
+        older_than: args
+            .value_of("older-than")
+            .and_then(|v| parse_time(v, SystemTime::now()).ok()),
+        follow_symlinks: args.is_present("follow-symlinks"),
+        max_depth: if args.is_present("no-recursive") {
+            Some(1)
+        } else {
+            args.value_of("max-depth").and_then(|v| v.parse().ok())
+        },
+    }
+}
+
+/// Returns the verbosity selected by the `--quiet`/`--verbose` flags
+fn verbosity(args: &ArgMatches) -> Verbosity {
+    if args.is_present("quiet") {
+        Verbosity::Quiet
+    } else {
+        match args.occurrences_of("verbose") {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::VeryVerbose,
+        }
+    }
+}
+
+/// Returns the policy for names that are already taken selected by `--on-conflict`
+fn conflict_policy(args: &ArgMatches, config: &Config) -> ConflictPolicy {
+    let policy = match config.on_conflict {
+        Some(ref policy) if args.occurrences_of("on-conflict") == 0 => Some(policy.as_str()),
+        _ => args.value_of("on-conflict"),
+    };
+    policy.and_then(parse_conflict_policy).unwrap_or_default()
+}
+
+/// Parses an `--on-conflict` policy
+fn parse_conflict_policy(policy: &str) -> Option<ConflictPolicy> {
+    match policy {
+        "error" => Some(ConflictPolicy::Error),
+        "skip" => Some(ConflictPolicy::Skip),
+        "number" | "suffix" => Some(ConflictPolicy::Number),
+        "overwrite" => Some(ConflictPolicy::Overwrite),
+        "fail" => Some(ConflictPolicy::Fail),
+        _ => None,
+    }
+}
+
+/// Returns whether to color report output, from `--color`, `--no-color` and the config file
+///
+/// With `auto` (the default) output is only colored when written to a terminal.
+fn color_choice(args: &ArgMatches, config: &Config) -> ColorChoice {
+    match args.value_of("color") {
+        Some("always") => ColorChoice::Always,
+        Some("never") => ColorChoice::Never,
+        _ if args.is_present("no-color") || !config.color.unwrap_or(true) => ColorChoice::Never,
+        _ if io::stdout().is_terminal() => ColorChoice::Auto,
+        _ => ColorChoice::Never,
+    }
+}
+
+/// Returns the transform of `upper` or `lower`, limited to the stem or extension by
+/// `--only-stem` or `--only-extension`
+fn case_transform(args: &ArgMatches, case: LetterCase) -> Transform {
+    if args.is_present("only-stem") {
+        Transform::StemExt {
+            stem: Some(case),
+            ext: None,
         }
-        ("unique_ext", Some(sub_args)) => {
-            let path = Path::new(args.value_of("path").unwrap_or("."));
-            find_unique_extensions_command(path);
+    } else if args.is_present("only-extension") {
+        Transform::StemExt {
+            stem: None,
+            ext: Some(case),
         }
-        _ => {}
+    } else {
+        Transform::Case(case)
     }
 }
 
-fn convert_case_command(args: &ArgMatches, case: LetterCase) {
-    let path = Path::new(args.value_of("path").unwrap_or(""));
+/// Returns the output format selected by `--format`
+fn report_format(args: &ArgMatches) -> ReportFormat {
+    match args.value_of("format") {
+        Some("json") => ReportFormat::Json,
+        Some("csv") => ReportFormat::Csv,
+        _ => ReportFormat::Text,
+    }
+}
+
+/// Returns true if `subcommand` can print JSON and CSV as well as text
+fn has_formatted_output(subcommand: &str) -> bool {
+    matches!(
+        subcommand,
+        "upper"
+            | "lower"
+            | "case"
+            | "snake"
+            | "kebab"
+            | "camel"
+            | "pascal"
+            | "title"
+            | "trim"
+            | "strip-diacritics"
+            | "normalize"
+            | "despace"
+            | "fix-dupext"
+            | "sanitize"
+            | "sub"
+            | "unique_ext"
+            | "stat"
+    )
+}
+
+/// Runs a conversion subcommand, returning the process exit code
+fn convert_command(args: &ArgMatches, config: &Config, transform: Transform) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    log.quiet_skips = args.is_present("quiet-skips");
+
+    // Only the records are written to stdout, errors and warnings still go to stderr
+    let format = report_format(args);
+    if format != ReportFormat::Text {
+        log.out = Box::new(io::sink());
+        log.record = true;
+    }
+
+    let mut keep: Vec<String> = match args.values_of("keep") {
+        Some(values) => values.map(String::from).collect(),
+        None => config.keep.clone().unwrap_or_default(),
+    };
+    if let Some(keep_file) = args.value_of("keep-file") {
+        match fs::read_to_string(keep_file) {
+            Ok(contents) => keep.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|word| !word.is_empty())
+                    .map(String::from),
+            ),
+            Err(e) => {
+                log.error(&format!("Error: Failed to read {}: {}", keep_file, e));
+                return log.summary.exit_code();
+            }
+        }
+    }
+
+    let options = ConvertOptions {
+        recursive: args.is_present("recursive")
+            || args.is_present("max-depth")
+            || config.recursive.unwrap_or(false),
+        ignore_files: args.is_present("ignore-files"),
+        ignore_dirs: args.is_present("ignore-dirs"),
+        only_ext: extension_values(args, "only-ext"),
+        ignore_ext: extension_values(args, "ignore-ext"),
+        keep,
+        word_sep: args.value_of("word-sep").map(String::from),
+        out_sep: args.value_of("out-sep").map(String::from),
+        normalize: match args.value_of("normalize") {
+            Some("nfc") => Some(Normalization::Nfc),
+            Some("nfd") => Some(Normalization::Nfd),
+            _ => None,
+        },
+        locale: match args.value_of("locale") {
+            Some("tr") => CaseLocale::Turkish,
+            _ => CaseLocale::Default,
+        },
+        order: if args.is_present("breadth-first") {
+            TraversalOrder::BreadthFirst
+        } else {
+            TraversalOrder::DepthFirst
+        },
+        on_conflict: conflict_policy(args, config),
+        walk: walk_options(args, config),
+        keep_going: args.is_present("keep-going"),
+    };
+
+    let paths = if args.is_present("stdin") {
+        match read_paths(io::stdin(), args.is_present("null")) {
+            Ok(paths) => paths,
+            Err(e) => {
+                log.error(&format!("Error: Failed to read paths from stdin: {}", e));
+                return log.summary.exit_code();
+            }
+        }
+    } else {
+        vec![PathBuf::from(args.value_of("path").unwrap_or(""))]
+    };
+
+    log.relative_to = match args.value_of("relative-to") {
+        Some(base) => Some(PathBuf::from(base)),
+        None if paths.len() == 1 => display_base(&paths[0]),
+        None => None,
+    };
+
+    if let Some(into) = args.value_of("into") {
+        let copied = copy_converted(&paths, Path::new(into), &transform, &options, &mut log);
+        return match copied {
+            _ if log.summary.errors > 0 => EXIT_ERROR,
+            0 => EXIT_NOTHING_TO_DO,
+            _ => 0,
+        };
+    }
+
+    let move_paths = args.value_of("apply-to") == Some("paths");
+    let mut plan = Vec::new();
+    for path in &paths {
+        if move_paths && path.is_dir() {
+            plan.extend(plan_path_moves(path, &transform, &options, &mut log));
+        } else {
+            plan.extend(plan_entry(path, &transform, &options, &mut log));
+        }
+    }
+
+    if args.is_present("parents") {
+        let root = args.value_of("parents-root").map(PathBuf::from);
+        match plan_parents(&paths, root.as_deref(), &transform, &options, &mut log) {
+            Ok(parents) => plan.extend(parents),
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                return log.summary.exit_code();
+            }
+        }
+    }
+
+    if args.is_present("plan-json") {
+        let result = plan_entries(&plan).and_then(|entries| {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            serde_json::to_writer_pretty(&mut out, &entries)?;
+            Ok(writeln!(out)?)
+        });
+        return match result {
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                EXIT_ERROR
+            }
+            Ok(()) => log.summary.planned_exit_code(plan.len()),
+        };
+    }
+
+    if args.is_present("dry-run") && format != ReportFormat::Text {
+        let result = plan_entries(&plan).and_then(|planned| {
+            write_run_records(&mut io::stdout().lock(), &format, &planned, &log)
+        });
+        if let Err(e) = result {
+            log.error(&format!("Error: {}", e));
+        }
+        return log.summary.planned_exit_code(plan.len());
+    }
+
+    if args.is_present("dry-run") {
+        let preview = args.value_of("preview").and_then(|n| n.parse().ok());
+        log_plan(&plan, preview, &mut log);
+        return log.summary.planned_exit_code(plan.len());
+    }
+
+    // Each rename is confirmed separately instead
+    let mut interactive = args.is_present("interactive");
+    if !plan.is_empty()
+        && !interactive
+        && !confirm_changes(args, &format!("rename {}", entries(plan.len())), &mut log)
+    {
+        return log.summary.exit_code();
+    }
+
+    let mut manifest = match args.value_of("backup").map(fs::File::create).transpose() {
+        Ok(manifest) => manifest.map(io::BufWriter::new),
+        Err(e) => {
+            log.error(&format!(
+                "Error: Failed to create the backup manifest: {}",
+                e
+            ));
+            return log.summary.exit_code();
+        }
+    };
+
+    // Only replaced once the first rename is made, so `undo` still undoes the last conversion
+    // that renamed anything
+    let mut journal_path = config
+        .journal
+        .as_ref()
+        .filter(|_| !args.is_present("no-journal"));
+    let mut journal = None;
+
+    // Renamed up front, then recorded below in plan order
+    let threads = args
+        .value_of("threads")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1);
+    let mut renamed_in_parallel = if threads > 1 {
+        Some(apply_renames_parallel(&plan, &options, threads, &mut log).into_iter())
+    } else {
+        None
+    };
+
+    // Only shown instead of the summary-only output on a terminal, it'd garble anything else
+    let mut progress = (log.verbosity == Verbosity::Normal
+        && !interactive
+        && format == ReportFormat::Text
+        && io::stdout().is_terminal()
+        && io::stderr().is_terminal())
+    .then(|| Progress::new(plan.len(), Box::new(io::stderr())));
+
+    for (from, to) in &plan {
+        if let Some(progress) = progress.as_mut() {
+            progress.inc();
+        }
+
+        if interactive {
+            let (from_name, to_name) = (log.path(from), log.path(to));
+            match ask_rename(
+                &mut io::stdin().lock(),
+                &mut io::stdout(),
+                &from_name,
+                &to_name,
+            ) {
+                Ok(RenameAnswer::Yes) => {}
+                Ok(RenameAnswer::All) => interactive = false,
+                Ok(RenameAnswer::No) => {
+                    log.summary.skipped += 1;
+                    continue;
+                }
+                Ok(RenameAnswer::Quit) => break,
+                Err(e) => {
+                    log.error(&format!("Error: {}", e));
+                    break;
+                }
+            }
+        }
+
+        if move_paths {
+            if let Err(e) = fs::create_dir_all(to.parent().unwrap_or(Path::new("."))) {
+                log.error(&format!(
+                    "Error: Failed to create the directory of {:?}: {}",
+                    to, e
+                ));
+                if !options.keep_going {
+                    break;
+                }
+                continue;
+            }
+        }
+
+        let result = match renamed_in_parallel.as_mut() {
+            Some(results) => match results.next() {
+                Some(Some(result)) => result,
+                // Not attempted after a failure that ended the run
+                _ => continue,
+            },
+            None => apply_rename(from, to, options.on_conflict, &mut log),
+        };
+
+        match result {
+            Ok(Some(to)) => {
+                // Written as each rename happens, so an interrupted run can still be restored
+                if let Some(manifest) = manifest.as_mut() {
+                    let result =
+                        write_manifest_entry(manifest, &to, from).and_then(|_| manifest.flush());
+                    if let Err(e) = result {
+                        log.error(&format!(
+                            "Error: Failed to write the backup manifest: {}",
+                            e
+                        ));
+                    }
+                }
+
+                if let Some(path) = journal_path.take() {
+                    match create_journal(path) {
+                        Ok(created) => journal = Some(created),
+                        Err(e) => log.warn(&format!("Not recording the renames for undo: {}", e)),
+                    }
+                }
+                if let Some(journal) = journal.as_mut() {
+                    let result = path::absolute(&to)
+                        .and_then(|to| Ok((to, path::absolute(from)?)))
+                        .and_then(|(to, from)| write_manifest_entry(journal, &to, &from))
+                        .and_then(|_| journal.flush());
+                    if let Err(e) = result {
+                        log.warn(&format!("Failed to write the undo journal: {}", e));
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                // Cleared so the error gets a line of its own, the bar is redrawn below it
+                if let Some(progress) = progress.as_mut() {
+                    progress.finish();
+                }
+                log.fail(from, &e);
+                if options.stops_on(&e) && renamed_in_parallel.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(progress) = progress.as_mut() {
+        progress.finish();
+    }
+
+    if move_paths {
+        remove_emptied_dirs(&plan, &paths, &mut log);
+    }
+
+    if format != ReportFormat::Text {
+        let result = write_run_records(&mut io::stdout().lock(), &format, &log.renamed, &log);
+        if let Err(e) = result {
+            log.error(&format!("Error: {}", e));
+        }
+    }
+
+    let summary = log.summary.to_string();
+    log.info(&summary);
+    if options.keep_going {
+        log.report_failures();
+    }
+    log.summary.exit_code()
+}
+
+/// Writes the `renames` of a conversion, made or planned, as JSON (with the counts and errors
+/// of `log`) or CSV
+fn write_run_records<W: Write>(
+    out: &mut W,
+    format: &ReportFormat,
+    renames: &[PlanEntry],
+    log: &Logger,
+) -> Result<(), Error> {
+    match format {
+        ReportFormat::Json => write_run_json(out, renames, &log.summary, &log.error_messages),
+        _ => write_renames_csv(out, renames),
+    }
+}
+
+/// Returns the values of a repeatable extension option, lower cased and without a leading `.`
+fn extension_values(args: &ArgMatches, name: &str) -> Vec<String> {
+    args.values_of(name)
+        .map(|values| {
+            values
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Asks whether to go ahead and `action` (e.g. `rename 42 entries`) unless `--yes` was passed
+///
+/// Without a terminal to prompt on, nothing is changed unless `--yes` is passed.
+fn confirm_changes(args: &ArgMatches, action: &str, log: &mut Logger) -> bool {
+    if args.is_present("yes") {
+        return true;
+    }
+
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        log.error(&format!(
+            "Error: Refusing to {} without --yes when not running interactively",
+            action
+        ));
+        return false;
+    }
+
+    match confirm(&mut io::stdin().lock(), &mut io::stdout(), action) {
+        Ok(true) => true,
+        Ok(false) => {
+            log.info("Aborted");
+            false
+        }
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            false
+        }
+    }
+}
+
+/// Prompts `About to <action>. Proceed? [y/N]` and reads the answer, which defaults to no
+fn confirm<R: BufRead, W: Write>(input: &mut R, out: &mut W, action: &str) -> io::Result<bool> {
+    write!(out, "About to {}. Proceed? [y/N] ", action)?;
+    out.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// Prompts `Rename <from> => <to>? [y/n/a/q]` and reads the answer, which defaults to no
+///
+/// The end of the input quits, so nothing is renamed without an answer.
+fn ask_rename<R: BufRead, W: Write>(
+    input: &mut R,
+    out: &mut W,
+    from: &str,
+    to: &str,
+) -> io::Result<RenameAnswer> {
+    write!(out, "Rename {} => {}? [y/n/a/q] ", from, to)?;
+    out.flush()?;
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Ok(RenameAnswer::Quit);
+    }
+
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => RenameAnswer::Yes,
+        "a" | "all" => RenameAnswer::All,
+        "q" | "quit" => RenameAnswer::Quit,
+        _ => RenameAnswer::No,
+    })
+}
+
+/// Runs `restore-case`, returning the process exit code
+fn restore_case_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let manifest = Path::new(args.value_of("manifest").unwrap_or(""));
+    restore_manifest(args, config, manifest, &mut log)
+}
+
+/// Runs `undo`, restoring the names recorded in the journal and removing it once they all are
+fn undo_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+
+    let journal = match config.journal {
+        Some(ref journal) if journal.exists() => journal,
+        _ => {
+            log.info("Nothing to undo");
+            return EXIT_NOTHING_TO_DO;
+        }
+    };
+
+    let exit_code = restore_manifest(args, config, journal, &mut log);
+
+    // Kept if the restore was declined or failed, so it can be tried again
+    let summary = &log.summary;
+    let restored = summary.errors == 0 && summary.files + summary.dirs + summary.skipped > 0;
+    if !args.is_present("dry-run") && restored {
+        if let Err(e) = fs::remove_file(journal) {
+            log.warn(&format!("Failed to remove {:?}: {}", journal, e));
+        }
+    }
+    exit_code
+}
+
+/// Creates (or truncates) the undo journal, along with its directory
+fn create_journal(path: &Path) -> io::Result<io::BufWriter<fs::File>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::File::create(path).map(io::BufWriter::new)
+}
+
+/// Restores the names recorded in `manifest`, returning the process exit code
+fn restore_manifest(args: &ArgMatches, config: &Config, manifest: &Path, log: &mut Logger) -> i32 {
+    let dry_run = args.is_present("dry-run");
+
+    let renames = match fs::File::open(manifest)
+        .map_err(Error::from)
+        .and_then(|file| read_manifest(io::BufReader::new(file)))
+    {
+        Ok(renames) => renames,
+        Err(e) => {
+            log.error(&format!(
+                "Error: Failed to read {}: {}",
+                manifest.display(),
+                e
+            ));
+            return log.summary.exit_code();
+        }
+    };
+
+    if !dry_run
+        && !renames.is_empty()
+        && !confirm_changes(args, &format!("restore {}", entries(renames.len())), log)
+    {
+        return log.summary.exit_code();
+    }
+
+    restore_names(&renames, dry_run, conflict_policy(args, config), log);
+
+    if !dry_run {
+        let summary = log.summary.to_string();
+        log.info(&summary);
+    }
+    log.summary.exit_code()
+}
+
+fn apply_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let plan_path = args.value_of("plan").unwrap_or("-");
+    let dry_run = args.is_present("dry-run");
+
+    let result = if plan_path == "-" {
+        read_plan(io::stdin())
+    } else {
+        fs::File::open(plan_path)
+            .map_err(Error::from)
+            .and_then(|file| read_plan(io::BufReader::new(file)))
+    };
+    let planned = match result {
+        Ok(planned) => planned,
+        Err(e) => {
+            log.error(&format!("Error: Failed to read {}: {}", plan_path, e));
+            return log.summary.exit_code();
+        }
+    };
+
+    let plan = checked_plan(planned, &mut log);
+
+    if dry_run {
+        let preview = args.value_of("preview").and_then(|n| n.parse().ok());
+        log_plan(&plan, preview, &mut log);
+        return log.summary.planned_exit_code(plan.len());
+    }
+
+    if !plan.is_empty()
+        && !confirm_changes(args, &format!("rename {}", entries(plan.len())), &mut log)
+    {
+        return log.summary.exit_code();
+    }
+
+    let on_conflict = conflict_policy(args, config);
+    for (from, to) in &plan {
+        if let Err(e) = apply_rename(from, to, on_conflict, &mut log) {
+            log.error(&format!("Error: {}", e));
+            if on_conflict.stops_on(&e) {
+                break;
+            }
+        }
+    }
+
+    let summary = log.summary.to_string();
+    log.info(&summary);
+    log.summary.exit_code()
+}
+
+fn rename_from_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let mapping_path = args.value_of("mapping").unwrap_or("-");
+    let dry_run = args.is_present("dry-run");
+    let delimiter = if args.is_present("tsv") || mapping_path.ends_with(".tsv") {
+        b'\t'
+    } else {
+        b','
+    };
+
+    let result = if mapping_path == "-" {
+        read_mapping(io::stdin(), delimiter)
+    } else {
+        fs::File::open(mapping_path)
+            .map_err(Error::from)
+            .and_then(|file| read_mapping(file, delimiter))
+    };
+    let rows = match result {
+        Ok(rows) => rows,
+        Err(e) => {
+            log.error(&format!("Error: Failed to read {}: {}", mapping_path, e));
+            return log.summary.exit_code();
+        }
+    };
+
+    let on_conflict = conflict_policy(args, config);
+    let problems = check_mapping(&rows, on_conflict);
+    if !problems.is_empty() {
+        for problem in &problems {
+            log.error(&format!("Error: {}", problem));
+        }
+        log.info("Nothing was renamed");
+        return log.summary.exit_code();
+    }
+
+    if dry_run {
+        let plan: Vec<_> = rows
+            .iter()
+            .map(|row| (row.from.clone(), row.to.clone()))
+            .collect();
+        let preview = args.value_of("preview").and_then(|n| n.parse().ok());
+        log_plan(&plan, preview, &mut log);
+        return log.summary.planned_exit_code(plan.len());
+    }
+
+    if !rows.is_empty()
+        && !confirm_changes(args, &format!("rename {}", entries(rows.len())), &mut log)
+    {
+        return log.summary.exit_code();
+    }
+
+    let (mut applied, mut failed) = (0, 0);
+    for row in &rows {
+        match apply_rename(&row.from, &row.to, on_conflict, &mut log) {
+            Ok(Some(_)) => applied += 1,
+            Ok(None) => {}
+            Err(e) => {
+                failed += 1;
+                log.error(&format!("Error: Line {}: {}", row.line, e));
+                if on_conflict.stops_on(&e) {
+                    break;
+                }
+            }
+        }
+    }
+
+    log.info(&format!(
+        "Applied {} of {} rows; {} failed",
+        applied,
+        rows.len(),
+        failed
+    ));
+    log.summary.exit_code()
+}
+
+/// Runs `template`, or `photo-rename` with `photos`, which only renames photos and dates them
+/// by their EXIF data
+fn template_command(args: &ArgMatches, config: &Config, photos: bool) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or(""));
+    log.relative_to = args
+        .value_of("relative-to")
+        .map(PathBuf::from)
+        .or_else(|| display_base(path));
+    let dry_run = args.is_present("dry-run");
+
+    let template = match parse_template(args.value_of("pattern").unwrap_or("")) {
+        Ok(template) => template,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.exit_code();
+        }
+    };
+
+    if !path.exists() {
+        log.error(&format!(
+            "File/Directory does not exist: {}",
+            path.display()
+        ));
+        return log.summary.exit_code();
+    }
+
+    let recursive = args.is_present("recursive") || config.recursive.unwrap_or(false);
+    let on_conflict = conflict_policy(args, config);
+    let values = if photos {
+        TemplateValues::from_photo
+    } else {
+        TemplateValues::from_path
+    };
+    let plan =
+        template_files(path, recursive, &walk_options(args, config)).and_then(|mut files| {
+            if photos {
+                files.retain(|file| is_photo(file));
+            }
+            template_plan(&files, &template, values, on_conflict, &mut log)
+        });
+
+    let plan = match plan {
+        Ok(plan) => plan,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.exit_code();
+        }
+    };
+
+    if !dry_run
+        && !plan.is_empty()
+        && !confirm_changes(args, &format!("rename {}", entries(plan.len())), &mut log)
+    {
+        return log.summary.exit_code();
+    }
+
+    if dry_run {
+        let preview = args.value_of("preview").and_then(|n| n.parse().ok());
+        log_plan(&plan, preview, &mut log);
+        return log.summary.planned_exit_code(plan.len());
+    }
+
+    for (from, to) in plan {
+        if let Err(e) = apply_rename(&from, &to, on_conflict, &mut log) {
+            log.error(&format!("Error: {}", e));
+            if on_conflict.stops_on(&e) {
+                break;
+            }
+        }
+    }
+
+    let summary = log.summary.to_string();
+    log.info(&summary);
+    log.summary.exit_code()
+}
+
+/// Writes the `variants` of `unique_ext --variants` after the text report, if `show`
+fn write_variants<W: Write>(
+    out: &mut W,
+    variants: &BTreeMap<String, Vec<String>>,
+    show: bool,
+) -> io::Result<()> {
+    if show {
+        write_extension_variants(out, variants)
+    } else {
+        Ok(())
+    }
+}
+
+fn find_unique_extensions_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(Verbosity::Normal);
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+    let options = walk_options(args, config);
+
+    if !path.exists() || !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    let color = color_choice(args, config);
+
+    let format = report_format(args);
+
+    // Reports written to a file are never colored
+    let mut out: Box<dyn WriteColor> = if args.is_present("output") {
+        match report_output(args) {
+            Ok(file) => Box::new(NoColor::new(file)),
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                return log.summary.report_exit_code();
+            }
+        }
+    } else {
+        Box::new(StandardStream::stdout(color))
+    };
+
+    let compound: Vec<String> = if !args.is_present("compound-ext") {
+        Vec::new()
+    } else if let Some(ref compound) = config.compound_ext {
+        compound.clone()
+    } else {
+        DEFAULT_COMPOUND_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect()
+    };
+
+    let ignore_case = args.is_present("ignore-case");
+    let show_variants = args.is_present("variants");
+    let human = args.is_present("human");
+    let show_sizes = args.is_present("sizes") || human;
+    let sizes = if show_sizes || args.value_of("sort") == Some("size") {
+        // Unreadable entries are reported by the counting walk instead
+        let mut quiet =
+            Logger::with_writers(Verbosity::Quiet, Box::new(io::sink()), Box::new(io::sink()));
+        match find_extension_sizes(path, &options, &compound, &mut quiet) {
+            Ok(sizes) if ignore_case => Some(fold_extension_case(sizes, |a, b| *a += b)),
+            Ok(sizes) => Some(sizes),
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                return log.summary.report_exit_code();
+            }
+        }
+    } else {
+        None
+    };
+
+    let sort = match args.value_of("sort") {
+        Some("count") => ExtensionSort::Count,
+        Some("size") => ExtensionSort::Size(sizes.clone().unwrap_or_default()),
+        _ => ExtensionSort::Name,
+    };
+    let sort = if args.is_present("reverse") {
+        ExtensionSort::Reversed(Box::new(sort))
+    } else {
+        sort
+    };
+
+    let min_count = args.value_of("min-count").and_then(|n| n.parse().ok());
+    let roll_rare = args.is_present("roll-rare");
+
+    if args.is_present("show-files") {
+        let only = args.value_of("show-files");
+        let mut files = match find_extension_files(path, &options, &compound, only, &mut log) {
+            Ok(files) => files,
+            Err(_) => {
+                log.error("Failed to find unique extensions");
+                return log.summary.report_exit_code();
+            }
+        };
+        let variants = extension_variants(&files);
+        if ignore_case {
+            files = fold_extension_case(files, |paths, mut more| {
+                paths.append(&mut more);
+                paths.sort();
+            });
+        }
+
+        let skipped = log.summary.errors;
+        let result = match format {
+            ReportFormat::Text => write_extension_files(&mut out, &files, &sort)
+                .and_then(|_| write_variants(&mut out, &variants, show_variants))
+                .map_err(Error::from),
+            ReportFormat::Json => write_extension_files_json(&mut out, &files, &sort),
+            ReportFormat::Csv => write_extension_files_csv(&mut out, &files, &sort),
+        };
+        if let Err(e) = result.and_then(|_| Ok(out.flush()?)) {
+            log.error(&format!("Error: {}", e));
+        }
+
+        if skipped > 0 {
+            log.warn(&format!(
+                "{} unreadable, the list is incomplete",
+                entries(skipped)
+            ));
+        }
+    } else if args.is_present("group-by-dir") {
+        let mut groups = match find_unique_extensions_by_dir(path, &options, &compound, &mut log) {
+            Ok(groups) => groups,
+            Err(_) => {
+                log.error("Failed to find unique extensions");
+                return log.summary.report_exit_code();
+            }
+        };
+        let mut combined = combine_extensions(&groups);
+        let variants = extension_variants(&combined);
+        if ignore_case {
+            for group in groups.iter_mut() {
+                let extensions = mem::take(&mut group.extensions);
+                group.extensions = fold_extension_case(extensions, |a, b| *a += b);
+            }
+            combined = fold_extension_case(combined, |a, b| *a += b);
+        }
+        if let Some(min_count) = min_count {
+            for group in groups.iter_mut() {
+                group.extensions = drop_rare_extensions(&group.extensions, min_count, roll_rare);
+            }
+            combined = drop_rare_extensions(&combined, min_count, roll_rare);
+        }
+
+        let skipped = log.summary.errors;
+        let result = match format {
+            ReportFormat::Text => write_extensions_by_dir(&mut out, &groups, &combined, &sort)
+                .and_then(|_| write_variants(&mut out, &variants, show_variants))
+                .map_err(Error::from),
+            ReportFormat::Json => write_extensions_by_dir_json(&mut out, &groups, &sort),
+            ReportFormat::Csv => write_extensions_by_dir_csv(&mut out, &groups, &sort),
+        };
+        if let Err(e) = result.and_then(|_| Ok(out.flush()?)) {
+            log.error(&format!("Error: {}", e));
+        }
+
+        if skipped > 0 {
+            log.warn(&format!(
+                "{} unreadable, the counts are incomplete",
+                entries(skipped)
+            ));
+        }
+    } else if let Ok(mut extensions) = find_unique_extensions(path, &options, &compound, &mut log) {
+        let variants = extension_variants(&extensions);
+        if ignore_case {
+            extensions = fold_extension_case(extensions, |a, b| *a += b);
+        }
+        if let Some(min_count) = min_count {
+            extensions = drop_rare_extensions(&extensions, min_count, roll_rare);
+        }
+        let sizes = sizes.filter(|_| show_sizes).map(|sizes| ExtensionSizes {
+            sizes: drop_rare_sizes(&sizes, &extensions),
+            human,
+        });
+
+        let skipped = log.summary.errors;
+        let sizes = sizes.as_ref();
+        let result = match format {
+            ReportFormat::Text => write_extensions(&mut out, &extensions, &sort, sizes)
+                .and_then(|_| write_variants(&mut out, &variants, show_variants))
+                .map_err(Error::from),
+            ReportFormat::Json => write_extensions_json(&mut out, &extensions, &sort, sizes),
+            ReportFormat::Csv => write_extensions_csv(&mut out, &extensions, &sort, sizes),
+        };
+        if let Err(e) = result.and_then(|_| Ok(out.flush()?)) {
+            log.error(&format!("Error: {}", e));
+        }
+
+        if skipped > 0 {
+            log.warn(&format!(
+                "{} unreadable, the counts are incomplete",
+                entries(skipped)
+            ));
+        }
+    } else {
+        log.error("Failed to find unique extensions");
+    }
+    log.summary.report_exit_code()
+}
+
+fn categorize_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(Verbosity::Normal);
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    // Reports written to a file are never colored
+    let mut out: Box<dyn WriteColor> = if args.is_present("output") {
+        match report_output(args) {
+            Ok(file) => Box::new(NoColor::new(file)),
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                return log.summary.report_exit_code();
+            }
+        }
+    } else {
+        Box::new(StandardStream::stdout(color_choice(args, config)))
+    };
+
+    let options = walk_options(args, config);
+    let result = find_unique_extensions(path, &options, &[], &mut log).and_then(|extensions| {
+        let categories = categorize(&extensions, config.categories.as_ref());
+        write_categories(&mut out, &categories)?;
+        Ok(out.flush()?)
+    });
+    if let Err(e) = result {
+        log.error(&format!("Error: {}", e));
+    }
+    log.summary.report_exit_code()
+}
+
+/// Exit code of a command that moved or removed `n` entries
+fn moved_exit_code(n: usize) -> i32 {
+    if n > 0 {
+        0
+    } else {
+        EXIT_NOTHING_TO_DO
+    }
+}
+
+fn flatten_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let src = Path::new(args.value_of("src").unwrap_or(""));
+    let dst = Path::new(
+        args.value_of("dst")
+            .or_else(|| args.value_of("src"))
+            .unwrap_or(""),
+    );
+
+    if !src.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            src.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    let options = FlattenOptions {
+        dry_run: args.is_present("dry-run"),
+        prune: args.is_present("prune"),
+        walk: walk_options(args, config),
+    };
+
+    if !options.dry_run {
+        // Plan the moves with a dry run first to know how many files would be moved
+        let dry_run = FlattenOptions {
+            dry_run: true,
+            prune: false,
+            walk: walk_options(args, config),
+        };
+        let count = match flatten(src, dst, &dry_run, &mut Logger::new(Verbosity::Quiet)) {
+            Ok(moves) => moves.len(),
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                return log.summary.report_exit_code();
+            }
+        };
+
+        if count > 0 && !confirm_changes(args, &format!("move {} files", count), &mut log) {
+            return log.summary.report_exit_code();
+        }
+    }
+
+    match flatten(src, dst, &options, &mut log) {
+        Ok(moves) => {
+            log.info(&format!("Moved {} files", moves.len()));
+            moved_exit_code(moves.len())
+        }
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            EXIT_ERROR
+        }
+    }
+}
+
+fn prune_empty_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+    log.relative_to = args
+        .value_of("relative-to")
+        .map(PathBuf::from)
+        .or_else(|| Some(path.to_path_buf()));
+    let dry_run = args.is_present("dry-run");
+    let options = walk_options(args, config);
+
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    if !dry_run {
+        let count = match prune_empty_dirs(path, true, &options, &mut Logger::new(Verbosity::Quiet))
+        {
+            Ok(dirs) => dirs.len(),
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                return log.summary.report_exit_code();
+            }
+        };
+
+        if count > 0 && !confirm_changes(args, &format!("remove {} directories", count), &mut log) {
+            return log.summary.report_exit_code();
+        }
+    }
+
+    match prune_empty_dirs(path, dry_run, &options, &mut log) {
+        Ok(dirs) if dry_run => {
+            log.info(&format!("Would remove {} directories", dirs.len()));
+            moved_exit_code(dirs.len())
+        }
+        Ok(dirs) => {
+            log.info(&format!("Removed {} directories", dirs.len()));
+            moved_exit_code(dirs.len())
+        }
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            EXIT_ERROR
+        }
+    }
+}
+
+fn organize_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+    log.relative_to = args
+        .value_of("relative-to")
+        .map(PathBuf::from)
+        .or_else(|| Some(path.to_path_buf()));
+
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    let options = OrganizeOptions {
+        dry_run: args.is_present("dry-run"),
+        by: match args.value_of("by") {
+            Some("date") => OrganizeBy::Date,
+            _ => OrganizeBy::Ext,
+        },
+        walk: walk_options(args, config),
+    };
+
+    if !options.dry_run {
+        // Plan the moves with a dry run first to know how many files would be moved
+        let dry_run = OrganizeOptions {
+            dry_run: true,
+            by: options.by,
+            walk: walk_options(args, config),
+        };
+        let count = match organize(path, &dry_run, &mut Logger::new(Verbosity::Quiet)) {
+            Ok(moves) => moves.len(),
+            Err(e) => {
+                log.error(&format!("Error: {}", e));
+                return log.summary.report_exit_code();
+            }
+        };
+
+        if count > 0 && !confirm_changes(args, &format!("move {} files", count), &mut log) {
+            return log.summary.report_exit_code();
+        }
+    }
+
+    match organize(path, &options, &mut log) {
+        Ok(moves) if options.dry_run => {
+            log.info(&format!("Would move {} files", moves.len()));
+            moved_exit_code(moves.len())
+        }
+        Ok(moves) => {
+            log.info(&format!("Moved {} files", moves.len()));
+            moved_exit_code(moves.len())
+        }
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            EXIT_ERROR
+        }
+    }
+}
+
+fn find_duplicates_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+    let options = walk_options(args, config);
+
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    let mut out = match report_output(args) {
+        Ok(out) => out,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
+    };
+
+    let algo = match args.value_of("hash-algo") {
+        Some("sha256") => HashAlgo::Sha256,
+        Some("md5") => HashAlgo::Md5,
+        _ => HashAlgo::Blake3,
+    };
+
+    let groups = match find_duplicates(path, &options, |file, limit| algo.hash_file(file, limit)) {
+        Ok(groups) => groups,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
+    };
+
+    let result = if args.is_present("print0") {
+        write_duplicates_null(&mut out, &groups)
+    } else {
+        write_duplicates(&mut out, &groups, algo)
+    };
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        log.error(&format!("Error: {}", e));
+        return log.summary.report_exit_code();
+    }
+
+    if args.is_present("delete") {
+        let dry_run = args.is_present("dry-run");
+        let count: usize = groups.iter().map(|group| group.paths.len() - 1).sum();
+        if !dry_run
+            && count > 0
+            && !confirm_changes(args, &format!("delete {} files", count), &mut log)
+        {
+            return log.summary.report_exit_code();
+        }
+
+        if let Err(e) = delete_duplicates(&groups, dry_run, &mut log) {
+            log.error(&format!("Error: {}", e));
+        }
+    }
+    log.summary.report_exit_code()
+}
+
+fn links_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    let mut out = match report_output(args) {
+        Ok(out) => out,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
+    };
+
+    let mut links = match find_links(path, &walk_options(args, config)) {
+        Ok(links) => links,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
+    };
 
-    if !path.exists() {
-        eprintln!("File/Directory does not exist");
-        return;
+    if args.is_present("broken-only") {
+        links.retain(|link| link.broken);
     }
 
-    if path.is_file() {
-        if let Err(e) = convert_file_or_dir(path, &case) {
-            eprintln!("Error: {}", e);
-            return;
-        }
+    if let Err(e) = write_links(&mut out, &links).and_then(|_| out.flush()) {
+        log.error(&format!("Error: {}", e));
+        return log.summary.report_exit_code();
     }
 
-    if path.is_dir() {
-        if args.is_present("recursive") {
-            if let Err(e) = convert_children(
-                path,
-                &case,
-                args.is_present("ignore-files"),
-                args.is_present("ignore-dirs"),
-            ) {
-                eprintln!("Error: {}", e);
-            }
+    if args.is_present("delete-broken") {
+        let dry_run = args.is_present("dry-run");
+        let count = links.iter().filter(|link| link.broken).count();
+        if !dry_run
+            && count > 0
+            && !confirm_changes(args, &format!("delete {} broken links", count), &mut log)
+        {
+            return log.summary.report_exit_code();
         }
 
-        if let Err(e) = convert_file_or_dir(path, &case) {
-            eprintln!("Error: {}", e);
-            return;
+        if let Err(e) = delete_broken_links(&links, dry_run, &mut log) {
+            log.error(&format!("Error: {}", e));
         }
     }
+    log.summary.report_exit_code()
 }
 
-fn convert_children(
-    path: &Path,
-    case: &LetterCase,
-    ignore_files: bool,
-    ignore_dirs: bool,
-) -> Result<(), Error> {
-    let entries = fs::read_dir(path)?;
+fn du_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or("."));
 
-    for entry in entries {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
 
-        if file_type.is_dir() && !ignore_dirs {
-            convert_children(&entry.path(), case, ignore_files, ignore_dirs)?;
-            convert_file_or_dir(&entry.path(), case)?;
+    let mut out = match report_output(args) {
+        Ok(out) => out,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
         }
+    };
 
-        if (file_type.is_file() || file_type.is_symlink()) && !ignore_files {
-            convert_file_or_dir(&entry.path(), case)?;
+    let depth = args
+        .value_of("depth")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1);
+    let dirs = match find_dir_sizes(path, depth, &walk_options(args, config), &mut log) {
+        Ok(dirs) => dirs,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
         }
+    };
+
+    let skipped = log.summary.errors;
+    let result = write_dir_sizes(&mut out, &dirs, args.is_present("human"));
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        log.error(&format!("Error: {}", e));
     }
 
-    Ok(())
+    if skipped > 0 {
+        log.warn(&format!(
+            "{} unreadable, the sizes are incomplete",
+            entries(skipped)
+        ));
+    }
+    log.summary.report_exit_code()
 }
 
-/// Converts the final component in a path to the specified letter case
-///
-/// E.g.
-/// `/home/ralph/test/12345/abcd` => `/home/ralph/test/12345/ABCD`
-/// `/foo/bar/baz.zip` => `/foo/bar/BAZ.ZIP`
-fn convert_file_or_dir(path: &Path, case: &LetterCase) -> Result<(), Error> {
-    let filename = path
-        .file_name()
-        .unwrap_or(OsStr::new(""))
-        .to_str()
-        .unwrap_or("");
+/// Runs `stat`, returning the process exit code
+fn stat_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or("."));
 
-    if filename.is_empty() {
-        return Ok(());
+    if fs::symlink_metadata(path).is_err() {
+        log.error(&format!(
+            "File/Directory does not exist: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
     }
 
-    let target_filename = match case {
-        LetterCase::UpperCase => filename.to_uppercase(),
-        LetterCase::LowerCase => filename.to_lowercase(),
+    let mut out = match report_output(args) {
+        Ok(out) => out,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
+    };
+
+    let stats = match path_stats(path, &walk_options(args, config), &mut log) {
+        Ok(stats) => stats,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
     };
 
-    let target_path = path
-        .parent()
-        .unwrap_or(Path::new("."))
-        .join(target_filename);
+    let skipped = log.summary.errors;
+    let fields = path_stat_fields(&stats, args.is_present("human"));
+    let result = match report_format(args) {
+        ReportFormat::Text => write_path_stats(&mut out, &fields).map_err(Error::from),
+        ReportFormat::Json => write_path_stats_json(&mut out, &fields),
+        ReportFormat::Csv => write_path_stats_csv(&mut out, &fields),
+    };
+    if let Err(e) = result.and_then(|_| Ok(out.flush()?)) {
+        log.error(&format!("Error: {}", e));
+    }
 
-    println!("Converting {:?} => {:?}", path, target_path);
-    fs::rename(path, target_path)?;
-    Ok(())
+    if skipped > 0 {
+        log.warn(&format!(
+            "{} unreadable, the report is incomplete",
+            entries(skipped)
+        ));
+    }
+    log.summary.report_exit_code()
 }
 
-fn find_unique_extensions_command(path: &Path) {
-    if !path.exists() || !path.is_dir() {
-        eprintln!(
+/// Runs `dedupe-names`, returning the process exit code
+fn dedupe_names_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+
+    if !path.is_dir() {
+        log.error(&format!(
             "Directory does not exist or is not a valid directory path: {}",
             path.display()
-        );
-        return;
+        ));
+        return EXIT_ERROR;
     }
 
-    if let Ok(extensions) = find_unique_extensions(path) {
-        let mut exts: Vec<&String> = extensions.keys().collect();
-        exts.sort();
-        for ext in exts {
-            println!("{} ({} files)", ext, extensions[ext]);
+    let result = report_output(args).and_then(|mut out| {
+        let clashes = find_name_clashes(path, &walk_options(args, config))?;
+        write_name_clashes(&mut out, &clashes)?;
+        out.flush()?;
+        Ok(clashes)
+    });
+
+    match result {
+        Ok(ref clashes) if clashes.is_empty() => 0,
+        Ok(clashes) => {
+            let count: usize = clashes.iter().map(|clash| clash.names.len()).sum();
+            log.info(&format!(
+                "{} would clash in {} directories",
+                entries(count),
+                clashes.len()
+            ));
+            EXIT_ERROR
+        }
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            EXIT_ERROR
         }
-    } else {
-        eprintln!("Failed to find unique extensions");
     }
 }
 
-fn find_unique_extensions(path: &Path) -> Result<HashMap<String, u32>, Error> {
-    let mut res = HashMap::new();
+fn watch_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(verbosity(args));
+    let path = PathBuf::from(args.value_of("path").unwrap_or("."));
 
-    let entries = fs::read_dir(path)?;
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
 
-    for entry in entries {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
+    let transform = match args.value_of("style").and_then(style_transform) {
+        Some(transform) => transform,
+        None => return EXIT_USAGE,
+    };
+    let options = ConvertOptions {
+        recursive: args.is_present("recursive") || config.recursive.unwrap_or(false),
+        ignore_files: args.is_present("ignore-files"),
+        ignore_dirs: args.is_present("ignore-dirs"),
+        only_ext: extension_values(args, "only-ext"),
+        ignore_ext: extension_values(args, "ignore-ext"),
+        on_conflict: conflict_policy(args, config),
+        // Without a --max-depth, a recursive watch converts at any depth
+        walk: walk_options(args, config),
+        ..Default::default()
+    };
+    let debounce = Duration::from_millis(
+        args.value_of("debounce")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1000),
+    );
 
-        if file_type.is_dir() {
-            let child_entries = find_unique_extensions(&entry.path())?;
-            for (ext, count) in child_entries.iter() {
-                let c = res.entry(String::from(ext)).or_insert(0);
-                *c += count;
+    // Entries which arrived before the watch started are converted straight away
+    match convert_dir_plan(&path, &transform, &options, &mut log) {
+        Ok(plan) => {
+            for (from, to) in plan {
+                if let Err(e) = apply_rename(&from, &to, options.on_conflict, &mut log) {
+                    log.error(&format!("Error: {}", e));
+                    if options.on_conflict.stops_on(&e) {
+                        return log.summary.report_exit_code();
+                    }
+                }
             }
         }
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
+    }
 
-        if file_type.is_file() || file_type.is_symlink() {
-            if let Some(ext) = entry.path().extension() {
-                let e = String::from(ext.to_str().unwrap());
-                let count = res.entry(e).or_insert(0);
-                *count += 1;
+    if !args.is_present("once") {
+        let (sender, events) = mpsc::channel();
+        let _watcher = match start_watcher(&path, options.recursive, sender.clone()) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log.error(&format!("Error: Failed to watch {:?}: {}", path, e));
+                return log.summary.report_exit_code();
             }
+        };
+
+        // Stop on Ctrl-C so pending entries are dropped and the summary is still printed
+        let result = ctrlc::set_handler(move || {
+            let _ = sender.send(WatchEvent::Stop);
+        });
+        if let Err(e) = result {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
+        }
+
+        log.info(&format!("Watching {:?}, press Ctrl-C to stop", path));
+        watch(&path, &events, &transform, &options, debounce, &mut log);
+    }
+
+    let summary = log.summary.to_string();
+    log.info(&summary);
+    log.summary.report_exit_code()
+}
+
+fn tree_command(args: &ArgMatches, config: &Config) -> i32 {
+    let mut log = Logger::new(Verbosity::Normal);
+    let path = Path::new(args.value_of("path").unwrap_or("."));
+
+    if !path.is_dir() {
+        log.error(&format!(
+            "Directory does not exist or is not a valid directory path: {}",
+            path.display()
+        ));
+        return log.summary.report_exit_code();
+    }
+
+    let options = TreeOptions {
+        ignore_files: args.is_present("ignore-files"),
+        ignore_dirs: args.is_present("ignore-dirs"),
+        preview: args.value_of("preview").and_then(style_transform),
+        sizes: args.is_present("sizes") || args.is_present("human"),
+        human: args.is_present("human"),
+        extensions: args.is_present("extensions"),
+        walk: walk_options(args, config),
+    };
+
+    let mut out = match report_output(args) {
+        Ok(out) => out,
+        Err(e) => {
+            log.error(&format!("Error: {}", e));
+            return log.summary.report_exit_code();
         }
+    };
+
+    if let Err(e) = write_tree(&mut out, path, &options).and_then(|_| Ok(out.flush()?)) {
+        log.error(&format!("Error: {}", e));
     }
-    Ok(res)
+    log.summary.report_exit_code()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
+    use std::cell::RefCell;
     use std::fs::File;
     use std::path::PathBuf;
+    use std::rc::Rc;
 
-    #[test]
-    fn test_convert_file() {
-        let lower_path = env::temp_dir().join("test.file");
-        let upper_path = env::temp_dir().join("TEST.FILE");
+    /// An in-memory writer whose contents can be inspected after being handed to a `Logger`
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
 
-        if lower_path.exists() {
-            fs::remove_file(&lower_path).unwrap();
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
         }
 
-        if upper_path.exists() {
-            fs::remove_file(&upper_path).unwrap();
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
+    }
+
+    fn quiet_logger() -> Logger {
+        Logger::new(Verbosity::Quiet)
+    }
 
-        // -- Test to upper case
-        let _f = File::create(&lower_path).unwrap();
-        convert_file_or_dir(&lower_path, &LetterCase::UpperCase).unwrap();
+    #[test]
+    fn test_exclude_dirs() {
+        let root = env::temp_dir().join("ram-utils-exclude-dir-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("node_modules/left-pad")).unwrap();
+        fs::create_dir_all(root.join("src/node_modules")).unwrap();
+        fs::create_dir_all(root.join("build-debug")).unwrap();
+        File::create(root.join("index.js")).unwrap();
+        File::create(root.join("node_modules/left-pad/index.js")).unwrap();
+        File::create(root.join("src/main.ts")).unwrap();
+        File::create(root.join("src/node_modules/dep.js")).unwrap();
+        File::create(root.join("build-debug/app.map")).unwrap();
 
-        assert_eq!(upper_path.exists(), true);
+        let walk = WalkOptions {
+            exclude_dirs: glob_set(vec!["node_modules", "build*"]).unwrap(),
+            ..Default::default()
+        };
 
-        fs::remove_file(&upper_path).unwrap();
+        // -- Nothing inside an excluded directory is counted, at any depth
+        let extensions = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(extensions.get("js"), Some(&1));
+        assert_eq!(extensions.get("ts"), Some(&1));
+        assert_eq!(extensions.get("map"), None);
 
-        // -- Test to lower case
-        let _f = File::create(&upper_path).unwrap();
-        convert_file_or_dir(&upper_path, &LetterCase::LowerCase).unwrap();
+        // -- ... or renamed, including the excluded directories themselves
+        let options = ConvertOptions {
+            recursive: true,
+            walk,
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+        let renamed: Vec<&Path> = plan
+            .iter()
+            .map(|(from, _)| from.strip_prefix(&root).unwrap())
+            .collect();
+        assert_eq!(
+            renamed,
+            vec![
+                Path::new("index.js"),
+                Path::new("src/main.ts"),
+                Path::new("src"),
+            ]
+        );
 
-        assert_eq!(lower_path.exists(), true);
+        // -- Files with an excluded name aren't affected
+        let walk = WalkOptions {
+            exclude_dirs: glob_set(vec!["index.js"]).unwrap(),
+            ..Default::default()
+        };
+        let extensions =
+            find_unique_extensions(&root.join("src"), &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(extensions.get("js"), Some(&1));
 
-        fs::remove_file(&lower_path).unwrap();
+        let _ = fs::remove_dir_all(&root);
     }
 
     #[test]
-    fn test_convert_children() {
-        let root = env::temp_dir().join("ram-utils-convert-test-convert-children");
+    fn test_include_exclude() {
+        let root = env::temp_dir().join("ram-utils-include-exclude-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("Photos")).unwrap();
+        fs::create_dir_all(root.join("node_modules/Pkg")).unwrap();
+        for name in &[
+            "Photos/A.JPG",
+            "Photos/B.png",
+            "C.jpg",
+            "node_modules/Pkg/D.jpg",
+        ] {
+            File::create(root.join(name)).unwrap();
+        }
+
+        let matches = build_cli().get_matches_from(vec![
+            "ram-utils",
+            "unique_ext",
+            "--include",
+            "*.jpg",
+            "--include",
+            "*.JPG",
+            "--exclude",
+            "node_modules/**",
+            ".",
+        ]);
+        let args = matches.subcommand_matches("unique_ext").unwrap();
+        let walk = walk_options(args, &Config::default());
 
-        let mut lower_paths: Vec<PathBuf> = Vec::new();
-        let mut upper_paths: Vec<PathBuf> = Vec::new();
+        // -- Invalid globs are usage errors rather than matching nothing
+        for arg in &["--exclude-dir", "--include", "--exclude"] {
+            assert!(build_cli()
+                .get_matches_from_safe(vec!["ram-utils", "upper", *arg, "[bad", "."])
+                .is_err());
+        }
 
-        for name in ["one", "two", "three"].iter() {
-            let lower_dir = root.join(name);
-            let upper_dir = root.join(name.to_uppercase());
+        // -- Patterns match the path below the walked directory, at any depth
+        let extensions = find_unique_extensions(&root, &walk, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(extensions.get("jpg"), Some(&1));
+        assert_eq!(extensions.get("JPG"), Some(&1));
+        assert_eq!(extensions.get("png"), None);
 
-            let lower_file = lower_dir.with_extension("file");
-            let upper_file = upper_dir.with_extension("FILE");
+        // -- They compose with the recursive option of the conversions
+        let options = ConvertOptions {
+            recursive: true,
+            walk,
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::LowerCase);
+        let plan = plan_entry(&root, &transform, &options, &mut quiet_logger());
+        assert_eq!(
+            plan,
+            vec![
+                (root.join("C.jpg"), root.join("c.jpg")),
+                (root.join("Photos/A.JPG"), root.join("Photos/a.jpg")),
+                (root.join("Photos"), root.join("photos")),
+            ]
+        );
 
-            lower_paths.push(lower_file);
-            upper_paths.push(upper_file);
+        fs::remove_dir_all(&root).unwrap();
+    }
 
-            lower_paths.push(lower_dir);
-            upper_paths.push(upper_dir);
-        }
+    #[test]
+    fn test_find_extensions_hidden() {
+        let root = env::temp_dir().join("ram-utils-test-find-extensions-hidden");
 
         if root.exists() {
             fs::remove_dir_all(&root).unwrap();
         }
 
-        // -- Test to upper case
-        fs::create_dir(&root).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::File::create(root.join(".git").join("index.pack")).unwrap();
+        fs::File::create(root.join("main.rs")).unwrap();
+
+        let exts = find_unique_extensions(&root, &WalkOptions::default(), &[], &mut quiet_logger())
+            .unwrap();
+        assert_eq!(exts.len(), 1);
+        assert_eq!(exts["rs"], 1);
+
+        let options = WalkOptions {
+            include_hidden: true,
+            ..Default::default()
+        };
+        let exts = find_unique_extensions(&root, &options, &[], &mut quiet_logger()).unwrap();
+        assert_eq!(exts.len(), 2);
+        assert_eq!(exts["pack"], 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_confirm() {
+        let answer = |input: &str| {
+            let mut out = Vec::new();
+            let confirmed = confirm(&mut input.as_bytes(), &mut out, "rename 2 entries").unwrap();
+            assert_eq!(out, b"About to rename 2 entries. Proceed? [y/N] ");
+            confirmed
+        };
+
+        assert!(answer("y\n"));
+        assert!(answer("Yes\r\n"));
+        assert!(!answer("n\n"));
+        assert!(!answer("\n"));
+        assert!(!answer(""));
+    }
+
+    #[test]
+    fn test_ask_rename() {
+        let answer = |input: &str| {
+            let mut out = Vec::new();
+            let answer = ask_rename(&mut input.as_bytes(), &mut out, "A.txt", "a.txt").unwrap();
+            assert_eq!(out, b"Rename A.txt => a.txt? [y/n/a/q] ");
+            answer
+        };
+
+        assert_eq!(answer("y\n"), RenameAnswer::Yes);
+        assert_eq!(answer("All\r\n"), RenameAnswer::All);
+        assert_eq!(answer("q\n"), RenameAnswer::Quit);
+        assert_eq!(answer("n\n"), RenameAnswer::No);
+        assert_eq!(answer("\n"), RenameAnswer::No);
+        assert_eq!(answer(""), RenameAnswer::Quit);
+
+        let error = build_cli()
+            .get_matches_from_safe(vec!["ram-utils", "lower", "-i", "-y", "."])
+            .unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::ArgumentConflict);
+    }
 
-        for path in &lower_paths {
-            if path.is_dir() {
-                fs::create_dir(path).unwrap();
-            } else {
-                File::create(path).unwrap();
+    #[test]
+    fn test_logger_verbosity() {
+        let root = env::temp_dir().join("ram-utils-convert-test-verbosity");
+
+        let verbosities = [
+            Verbosity::Quiet,
+            Verbosity::Normal,
+            Verbosity::Verbose,
+            Verbosity::VeryVerbose,
+        ];
+        for verbosity in verbosities.iter() {
+            if root.exists() {
+                fs::remove_dir_all(&root).unwrap();
             }
-        }
 
-        convert_children(&root, &LetterCase::UpperCase, false, false).unwrap();
+            fs::create_dir_all(&root).unwrap();
+            File::create(root.join("lower.txt")).unwrap();
+            File::create(root.join("UPPER.TXT")).unwrap();
+
+            let out = SharedBuffer::default();
+            let err = SharedBuffer::default();
+            let mut log =
+                Logger::with_writers(*verbosity, Box::new(out.clone()), Box::new(err.clone()));
 
-        for path in &upper_paths {
-            assert_eq!(path.exists(), true);
+            let options = ConvertOptions {
+                recursive: true,
+                ..Default::default()
+            };
+            convert_entry(
+                &root.join("missing"),
+                &Transform::Case(LetterCase::UpperCase),
+                &options,
+                &mut log,
+            );
+            convert_children(
+                &root,
+                &Transform::Case(LetterCase::UpperCase),
+                &options,
+                &mut log,
+            )
+            .unwrap();
+            let summary = log.summary.to_string();
+            log.info(&summary);
+
+            let out = out.contents();
+            assert!(err.contents().contains("does not exist"));
+            assert_eq!(log.summary.files, 1);
+
+            match verbosity {
+                Verbosity::Quiet => assert_eq!(out, ""),
+                Verbosity::Normal => assert_eq!(out, format!("{}\n", summary)),
+                Verbosity::Verbose => {
+                    assert_eq!(out.lines().count(), 2);
+                    assert!(out.contains("Converting"));
+                    assert!(!out.contains("Skipping"));
+                }
+                Verbosity::VeryVerbose => {
+                    assert_eq!(out.lines().count(), 3);
+                    assert!(out.contains("Converting"));
+                    assert!(out.contains("Skipping"));
+                }
+            }
         }
 
         fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_timings() {
+        let timings = Timings {
+            elapsed: Duration::from_millis(1500),
+            entries: 3000,
+        };
+        assert_eq!(
+            timings.to_string(),
+            "Took 1.500s; read 3000 entries (2000 entries/s)"
+        );
+
+        let instant = Timings {
+            elapsed: Duration::from_secs(0),
+            entries: 1,
+        };
+        assert_eq!(
+            instant.to_string(),
+            "Took 0.000s; read 1 entry (0 entries/s)"
+        );
+
+        // -- Entries read by any traversal are counted
+        let root = env::temp_dir().join("ram-utils-timings-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        File::create(root.join("dir").join("one.txt")).unwrap();
+        File::create(root.join("two.txt")).unwrap();
+
+        let before = ENTRIES_READ.load(Ordering::Relaxed);
+        walk(&root, &WalkOptions::default(), &mut |_, _| Ok(())).unwrap();
+        assert!(ENTRIES_READ.load(Ordering::Relaxed) >= before + 3);
+
+        let matches = build_cli().get_matches_from(vec!["ram-utils", "tree", "--timings", "."]);
+        let sub_args = matches.subcommand_matches("tree").unwrap();
+        assert!(sub_args.is_present("timings"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_report_output() {
+        let root = env::temp_dir().join("ram-utils-report-output-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let path = root.join("dupes.txt");
+        fs::write(&path, "old contents that should be truncated").unwrap();
+
+        let output_args = |path: &Path| {
+            App::new("test")
+                .arg(Arg::with_name("output").long("output").takes_value(true))
+                .get_matches_from(vec!["test", "--output", path.to_str().unwrap()])
+        };
+        let args = output_args(&path);
+
+        let groups = vec![DuplicateGroup {
+            size: 11,
+            paths: vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")],
+        }];
+
+        let mut out = report_output(&args).unwrap();
+        write_duplicates(&mut out, &groups, HashAlgo::Blake3).unwrap();
+        out.flush().unwrap();
+        drop(out);
+
+        let mut expected = Vec::new();
+        write_duplicates(&mut expected, &groups, HashAlgo::Blake3).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        let args = output_args(&root.join("missing/dir.txt"));
+        assert!(report_output(&args).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_name_clashes() {
+        let root = env::temp_dir().join("ram-utils-name-clash-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("nested/Photos")).unwrap();
+        fs::create_dir_all(root.join("nested/photos")).unwrap();
+        File::create(root.join("File.txt")).unwrap();
+        File::create(root.join("file.TXT")).unwrap();
+        File::create(root.join("other.txt")).unwrap();
+        File::create(root.join("nested/Photos/file.txt")).unwrap();
+
+        let clashes = find_name_clashes(&root, &WalkOptions::default()).unwrap();
+        assert_eq!(
+            clashes,
+            vec![
+                NameClash {
+                    dir: root.clone(),
+                    names: vec![PathBuf::from("File.txt"), PathBuf::from("file.TXT")],
+                },
+                NameClash {
+                    dir: root.join("nested"),
+                    names: vec![PathBuf::from("Photos"), PathBuf::from("photos")],
+                },
+            ]
+        );
+
+        let mut out = Vec::new();
+        write_name_clashes(&mut out, &clashes[..1]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{}:\n  File.txt\n  file.TXT\n", root.display())
+        );
+
+        // -- Clashes make the command fail, so it can gate a conversion in scripts
+        let run = |path: &Path| {
+            let matches = build_cli().get_matches_from(vec![
+                "ram-utils",
+                "dedupe-names",
+                "-q",
+                "-o",
+                root.with_extension("out").to_str().unwrap(),
+                path.to_str().unwrap(),
+            ]);
+            let sub_args = matches.subcommand_matches("dedupe-names").unwrap();
+            dedupe_names_command(sub_args, &Config::default())
+        };
+        assert_eq!(run(&root), EXIT_ERROR);
+        assert_eq!(run(&root.join("nested/Photos")), 0);
+
+        let _ = fs::remove_file(root.with_extension("out"));
+        fs::remove_dir_all(&root).unwrap();
+    }
 
-        // -- Test to lower case
-        fs::create_dir(&root).unwrap();
+    #[test]
+    fn test_apply_to_paths() {
+        let root = env::temp_dir().join("ram-utils-apply-to-paths-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("Photos/Summer")).unwrap();
+        fs::create_dir_all(root.join("PHOTOS/Summer")).unwrap();
+        fs::create_dir_all(root.join("photos/winter")).unwrap();
+        fs::create_dir_all(root.join("Docs/Clash")).unwrap();
+        fs::create_dir_all(root.join("docs/clash")).unwrap();
+        fs::write(root.join("Photos/Summer/IMG.JPG"), "img").unwrap();
+        fs::write(root.join("PHOTOS/Summer/Beach.PNG"), "beach").unwrap();
+        fs::write(root.join("photos/winter/Snow.jpg"), "snow").unwrap();
+        fs::write(root.join("Top.TXT"), "top").unwrap();
+        fs::write(root.join("Docs/Clash/A.txt"), "upper").unwrap();
+        fs::write(root.join("docs/clash/a.txt"), "lower").unwrap();
+
+        let matches = build_cli().get_matches_from(vec![
+            "ram-utils",
+            "lower",
+            "-r",
+            "-q",
+            "-y",
+            "--apply-to",
+            "paths",
+            "--on-conflict",
+            "number",
+            root.to_str().unwrap(),
+        ]);
+        let sub_args = matches.subcommand_matches("lower").unwrap();
+        let transform = Transform::Case(LetterCase::LowerCase);
+        assert_eq!(convert_command(sub_args, &Config::default(), transform), 0);
 
-        for path in &upper_paths {
-            if path.is_dir() {
-                fs::create_dir(path).unwrap();
-            } else {
-                File::create(path).unwrap();
+        // -- Every component is converted, merging directories with the same converted name
+        let mut files = Vec::new();
+        walk(&root, &WalkOptions::default(), &mut |path, file_type| {
+            if file_type.is_file() {
+                let contents = fs::read_to_string(path).unwrap();
+                files.push((path.strip_prefix(&root).unwrap().to_path_buf(), contents));
             }
+            Ok(())
+        })
+        .unwrap();
+        files.sort();
+        let expected: Vec<(PathBuf, String)> = vec![
+            ("docs/clash/a.txt", "lower"),
+            ("docs/clash/a_1.txt", "upper"),
+            ("photos/summer/beach.png", "beach"),
+            ("photos/summer/img.jpg", "img"),
+            ("photos/winter/snow.jpg", "snow"),
+            ("top.txt", "top"),
+        ]
+        .into_iter()
+        .map(|(path, contents)| (PathBuf::from(path), contents.to_string()))
+        .collect();
+        assert_eq!(files, expected);
+
+        // -- The emptied source directories are removed
+        for dir in &["Photos", "PHOTOS", "Docs"] {
+            assert!(!has_exact_name(&root.join(dir)), "{}", dir);
         }
 
-        convert_children(&root, &LetterCase::LowerCase, false, false).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_apply_plan() {
+        let root = env::temp_dir().join("ram-utils-apply-plan-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file.txt"), "file").unwrap();
+        fs::write(root.join("moved.txt"), "moved").unwrap();
+        fs::write(root.join("kind.txt"), "kind").unwrap();
 
-        for path in &lower_paths {
-            assert_eq!(path.exists(), true);
-        }
+        let transform = Transform::Case(LetterCase::UpperCase);
+        let options = ConvertOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let plan = convert_dir_plan(&root, &transform, &options, &mut quiet_logger()).unwrap();
+        let mut entries = plan_entries(&plan).unwrap();
+        // The plan lists directories after their entries, reversing it checks the sorting
+        entries.reverse();
+        let plan_path = env::temp_dir().join("ram-utils-apply-plan-test.json");
+        fs::write(&plan_path, serde_json::to_string(&entries).unwrap()).unwrap();
+
+        // -- Entries changed since planning are skipped
+        fs::rename(root.join("moved.txt"), root.join("Moved.txt")).unwrap();
+        fs::remove_file(root.join("kind.txt")).unwrap();
+        fs::create_dir(root.join("kind.txt")).unwrap();
+
+        let apply = |extra: &[&str]| {
+            let mut argv = vec!["ram-utils", "apply", "-q", "-y"];
+            argv.extend_from_slice(extra);
+            argv.push(plan_path.to_str().unwrap());
+            let matches = build_cli().get_matches_from(argv);
+            apply_command(
+                matches.subcommand_matches("apply").unwrap(),
+                &Config::default(),
+            )
+        };
+
+        // -- A dry run changes nothing
+        assert_eq!(apply(&["--dry-run"]), 0);
+        assert!(has_exact_name(&root.join("dir/file.txt")));
+
+        assert_eq!(apply(&[]), 0);
+        assert_eq!(
+            fs::read_to_string(root.join("DIR/FILE.TXT")).unwrap(),
+            "file"
+        );
+        assert!(has_exact_name(&root.join("Moved.txt")));
+        assert!(root.join("kind.txt").is_dir());
+
+        // -- Applying it again finds nothing left to rename
+        assert_eq!(apply(&[]), EXIT_NOTHING_TO_DO);
 
+        // -- Conflicts are handled as with the conversion commands
+        let conflict = vec![PlanEntry {
+            from: root.join("Moved.txt").to_string_lossy().into_owned(),
+            to: root.join("DIR").to_string_lossy().into_owned(),
+            kind: String::from("file"),
+            valid_utf8: true,
+        }];
+        fs::write(&plan_path, serde_json::to_string(&conflict).unwrap()).unwrap();
+        assert_eq!(apply(&[]), EXIT_ERROR);
+        assert_eq!(apply(&["--on-conflict", "number"]), 0);
+        assert_eq!(fs::read_to_string(root.join("DIR_1")).unwrap(), "moved");
+
+        fs::remove_file(&plan_path).unwrap();
         fs::remove_dir_all(&root).unwrap();
     }
 
     #[test]
-    fn test_convert_children_ignores() {
-        let root = env::temp_dir().join("ram-utils-convert-test-ignores");
+    fn test_convert_dry_run() {
+        let root = env::temp_dir().join("ram-utils-convert-dry-run-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("Dir")).unwrap();
+        File::create(root.join("Dir/File.txt")).unwrap();
+        File::create(root.join("Skipped.md")).unwrap();
 
-        let lower_dir = root.join("test");
-        let upper_dir = root.join("TEST");
+        let run = |subcommand: &str, extra: &[&str]| {
+            let mut argv = vec!["ram-utils", subcommand, "-q", "-r", "--dry-run"];
+            argv.extend_from_slice(extra);
+            argv.push(root.to_str().unwrap());
+            let matches = build_cli().get_matches_from(argv);
+            let transform = match subcommand {
+                "upper" => Transform::Case(LetterCase::UpperCase),
+                _ => Transform::Case(LetterCase::LowerCase),
+            };
+            convert_command(
+                matches.subcommand_matches(subcommand).unwrap(),
+                &Config::default(),
+                transform,
+            )
+        };
 
-        let lower_file = &lower_dir.with_extension("file");
-        let upper_file = &upper_dir.with_extension("FILE");
+        // -- Nothing is renamed, with or without the filters of a real run
+        assert_eq!(run("upper", &[]), 0);
+        assert_eq!(run("lower", &["--only-ext", "txt", "--preview", "1"]), 0);
+        assert!(has_exact_name(&root.join("Dir/File.txt")));
+        assert!(has_exact_name(&root.join("Skipped.md")));
 
-        if root.exists() {
-            fs::remove_dir_all(&root).unwrap();
-        }
+        // -- The plan is the one a real run would carry out
+        let options = ConvertOptions {
+            recursive: true,
+            only_ext: vec![String::from("txt")],
+            ..Default::default()
+        };
+        let transform = Transform::Case(LetterCase::LowerCase);
+        let plan = plan_entry(&root, &transform, &options, &mut quiet_logger());
+        assert_eq!(
+            plan,
+            vec![
+                (root.join("Dir/File.txt"), root.join("Dir/file.txt")),
+                (root.join("Dir"), root.join("dir")),
+            ]
+        );
+
+        let error = build_cli()
+            .get_matches_from_safe(vec![
+                "ram-utils",
+                "upper",
+                "--dry-run",
+                "--into",
+                "out",
+                ".",
+            ])
+            .unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::ArgumentConflict);
 
-        // -- Test ignore file
-        fs::create_dir_all(&lower_dir).unwrap();
-        fs::File::create(&lower_file).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_on_conflict() {
+        let root = env::temp_dir().join("ram-utils-on-conflict-test");
+        let reset = || {
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            for name in &["A.txt", "a.txt", "Z.txt"] {
+                File::create(root.join(name)).unwrap();
+            }
+        };
+        let run_with = |policy: &str, extra: &[&str]| {
+            let mut argv = vec![
+                "ram-utils",
+                "lower",
+                "-q",
+                "-y",
+                "-r",
+                "--on-conflict",
+                policy,
+            ];
+            argv.extend_from_slice(extra);
+            argv.push(root.to_str().unwrap());
+            let matches = build_cli().get_matches_from(argv);
+            convert_command(
+                matches.subcommand_matches("lower").unwrap(),
+                &Config::default(),
+                Transform::Case(LetterCase::LowerCase),
+            )
+        };
+        let run = |policy: &str| run_with(policy, &[]);
+        let run_keep_going = |policy: &str| run_with(policy, &["--keep-going"]);
+
+        // -- fail stops at the first conflict, leaving the later renames undone
+        reset();
+        assert_eq!(run("fail"), EXIT_ERROR);
+        assert!(has_exact_name(&root.join("A.txt")));
+        assert!(has_exact_name(&root.join("Z.txt")));
+
+        // -- error fails the rename, which ends the run like any other failure
+        reset();
+        assert_eq!(run("error"), EXIT_ERROR);
+        assert!(has_exact_name(&root.join("A.txt")));
+        assert!(has_exact_name(&root.join("Z.txt")));
+
+        // -- Unless keeping going, which renames the others, a partial failure
+        reset();
+        assert_eq!(run_keep_going("error"), EXIT_PARTIAL);
+        assert!(has_exact_name(&root.join("A.txt")));
+        assert!(has_exact_name(&root.join("z.txt")));
 
-        convert_children(&root, &LetterCase::UpperCase, true, false).unwrap();
+        // -- While fail still stops at the conflict
+        reset();
+        assert_eq!(run_keep_going("fail"), EXIT_ERROR);
+        assert!(has_exact_name(&root.join("Z.txt")));
 
-        assert_eq!(upper_dir.exists(), true);
-        assert_eq!(lower_file.exists(), true);
+        // -- suffix is another name for number
+        reset();
+        assert_eq!(run("suffix"), 0);
+        assert!(has_exact_name(&root.join("a_1.txt")));
+        assert!(has_exact_name(&root.join("z.txt")));
 
         fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_case_transform() {
+        let transform = |argv: Vec<&str>| {
+            let matches = build_cli().get_matches_from(argv);
+            let args = matches.subcommand_matches("lower").unwrap();
+            case_transform(args, LetterCase::LowerCase)
+        };
+
+        assert!(matches!(
+            transform(vec!["ram-utils", "lower", "--only-stem", "."]),
+            Transform::StemExt {
+                stem: Some(LetterCase::LowerCase),
+                ext: None,
+            }
+        ));
+        assert!(matches!(
+            transform(vec!["ram-utils", "lower", "--only-extension", "."]),
+            Transform::StemExt {
+                stem: None,
+                ext: Some(LetterCase::LowerCase),
+            }
+        ));
+        assert!(matches!(
+            transform(vec!["ram-utils", "lower", "."]),
+            Transform::Case(LetterCase::LowerCase)
+        ));
+
+        let error = build_cli()
+            .get_matches_from_safe(vec![
+                "ram-utils",
+                "lower",
+                "--only-stem",
+                "--only-extension",
+                ".",
+            ])
+            .unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::ArgumentConflict);
+    }
 
-        // -- Test ignore directory
-        fs::create_dir_all(&lower_dir).unwrap();
-        fs::File::create(&lower_file).unwrap();
+    #[test]
+    fn test_undo() {
+        let root = env::temp_dir().join("ram-utils-undo-test");
+        let journal_path = env::temp_dir().join("ram-utils-undo-test-journal/journal.tsv");
+        let _ = fs::remove_dir_all(&root);
+        let _ = fs::remove_file(&journal_path);
+        fs::create_dir_all(root.join("DIR")).unwrap();
+        File::create(root.join("DIR/FILE.TXT")).unwrap();
+        File::create(root.join("OTHER.TXT")).unwrap();
+        File::create(root.join("CHANGED.TXT")).unwrap();
+
+        let config = Config {
+            journal: Some(journal_path.clone()),
+            ..Default::default()
+        };
+        let run = |argv: Vec<&str>| {
+            let matches = build_cli().get_matches_from(argv);
+            match matches.subcommand() {
+                ("undo", Some(sub_args)) => undo_command(sub_args, &config),
+                (_, Some(sub_args)) => {
+                    convert_command(sub_args, &config, Transform::Case(LetterCase::LowerCase))
+                }
+                _ => unreachable!(),
+            }
+        };
+        let root_str = root.to_str().unwrap();
+
+        assert_eq!(
+            run(vec!["ram-utils", "undo", "-q", "-y"]),
+            EXIT_NOTHING_TO_DO
+        );
+
+        assert_eq!(
+            run(vec!["ram-utils", "lower", "-q", "-y", "-r", root_str]),
+            0
+        );
+        assert!(has_exact_name(&root.join("dir/file.txt")));
+        let journal = fs::read_to_string(&journal_path).unwrap();
+        assert_eq!(journal.lines().count(), 4);
+
+        // -- Runs that rename nothing, or don't journal, keep the journal of the last conversion
+        assert_eq!(
+            run(vec!["ram-utils", "lower", "-q", "-y", "-r", root_str]),
+            EXIT_NOTHING_TO_DO
+        );
+        File::create(root.join("NEW.TXT")).unwrap();
+        let new_file = root.join("NEW.TXT");
+        let new_file = new_file.to_str().unwrap();
+        assert_eq!(
+            run(vec![
+                "ram-utils",
+                "lower",
+                "-q",
+                "-y",
+                "--no-journal",
+                new_file
+            ]),
+            0
+        );
+        assert_eq!(fs::read_to_string(&journal_path).unwrap(), journal);
+
+        // -- As do runs whose every rename fails
+        File::create(root.join("CLASH.TXT")).unwrap();
+        File::create(root.join("clash.txt")).unwrap();
+        let clash = root.join("CLASH.TXT");
+        assert_eq!(
+            run(vec![
+                "ram-utils",
+                "lower",
+                "-q",
+                "-y",
+                clash.to_str().unwrap()
+            ]),
+            EXIT_ERROR
+        );
+        assert_eq!(fs::read_to_string(&journal_path).unwrap(), journal);
 
-        convert_children(&root, &LetterCase::UpperCase, false, true).unwrap();
+        // -- Entries renamed again since are skipped, the rest restored in reverse
+        fs::rename(root.join("changed.txt"), root.join("Changed.txt")).unwrap();
+        assert_eq!(
+            run(vec!["ram-utils", "undo", "-q", "--dry-run"]),
+            EXIT_NOTHING_TO_DO
+        );
+        assert!(has_exact_name(&root.join("dir/file.txt")));
+
+        assert_eq!(run(vec!["ram-utils", "undo", "-q", "-y"]), 0);
+        assert!(has_exact_name(&root.join("DIR/FILE.TXT")));
+        assert!(has_exact_name(&root.join("OTHER.TXT")));
+        assert!(has_exact_name(&root.join("Changed.txt")));
+        assert!(has_exact_name(&root.join("new.txt")));
 
-        assert_eq!(lower_dir.exists(), true);
-        assert_eq!(upper_file.exists(), true);
+        // -- The journal is only undone once
+        assert!(!journal_path.exists());
+        assert_eq!(
+            run(vec!["ram-utils", "undo", "-q", "-y"]),
+            EXIT_NOTHING_TO_DO
+        );
 
         fs::remove_dir_all(&root).unwrap();
+        fs::remove_dir_all(journal_path.parent().unwrap()).unwrap();
     }
 
     #[test]
-    fn test_convert_dir_recursive() {
-        let root = env::temp_dir().join("ram-utils-convert-test-recursive");
-        let lower_file = root.join("test").join("bar").join("baz.file");
-        let upper_file = root.join("TEST").join("BAR").join("BAZ.FILE");
+    fn test_load_config() {
+        let root = env::temp_dir().join("ram-utils-test-config");
 
         if root.exists() {
             fs::remove_dir_all(&root).unwrap();
         }
 
-        fs::create_dir_all(&lower_file.parent().unwrap()).unwrap();
-        fs::File::create(&lower_file).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("config.toml");
+        fs::write(
+            &path,
+            "recursive = true\ninclude-hidden = true\ncolor = false\nkeep = [\"NASA\"]\n\
+             compound-ext = [\"tar.gz\"]\n",
+        )
+        .unwrap();
+
+        let config = load_config(Some(&path), false, None).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                recursive: Some(true),
+                include_hidden: Some(true),
+                color: Some(false),
+                keep: Some(vec![String::from("NASA")]),
+                compound_ext: Some(vec![String::from("tar.gz")]),
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            load_config(Some(&path), true, None).unwrap(),
+            Config::default()
+        );
+        assert!(load_config(Some(&root.join("missing.toml")), false, None).is_err());
+
+        fs::write(&path, "[categories]\ndata = [\"xyz\"]\n").unwrap();
+        let categories = load_config(Some(&path), false, None)
+            .unwrap()
+            .categories
+            .unwrap();
+        assert_eq!(categories["data"], vec![String::from("xyz")]);
+
+        fs::write(&path, "unknown-option = 1\n").unwrap();
+        assert!(load_config(Some(&path), false, None).is_err());
+
+        // -- Profiles override the options of the rest of the file
+        fs::write(
+            &path,
+            "recursive = true\non-conflict = \"skip\"\nexclude = [\"*.tmp\"]\n\
+             [profiles.photos]\non-conflict = \"number\"\nkeep = [\"IMG\"]\n",
+        )
+        .unwrap();
+        let config = load_config(Some(&path), false, Some("photos")).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                recursive: Some(true),
+                keep: Some(vec![String::from("IMG")]),
+                exclude: Some(vec![String::from("*.tmp")]),
+                on_conflict: Some(String::from("number")),
+                ..Default::default()
+            }
+        );
+        assert!(load_config(Some(&path), false, Some("missing")).is_err());
 
-        convert_children(&root, &LetterCase::UpperCase, false, false).unwrap();
+        // -- Options passed on the command line take precedence
+        let matches = build_cli().get_matches_from(vec!["ram-utils", "upper", "x"]);
+        let args = matches.subcommand_matches("upper").unwrap();
+        assert_eq!(conflict_policy(args, &config), ConflictPolicy::Number);
+        let matches =
+            build_cli().get_matches_from(vec!["ram-utils", "upper", "--on-conflict", "error", "x"]);
+        let args = matches.subcommand_matches("upper").unwrap();
+        assert_eq!(conflict_policy(args, &config), ConflictPolicy::Error);
 
-        assert_eq!(upper_file.exists(), true);
+        fs::write(&path, "on-conflict = \"sometimes\"\n").unwrap();
+        assert!(load_config(Some(&path), false, None).is_err());
 
         fs::remove_dir_all(&root).unwrap();
     }
 
     #[test]
-    fn test_find_extensions() {
-        let root = env::temp_dir().join("ram-utils-test-find-extensions");
+    fn test_convert_exit_codes() {
+        let root = env::temp_dir().join("ram-utils-exit-code-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("DONE/SUB")).unwrap();
+        fs::create_dir_all(root.join("MIXED")).unwrap();
+        File::create(root.join("DONE/A.TXT")).unwrap();
+        File::create(root.join("DONE/SUB/B.TXT")).unwrap();
+        File::create(root.join("MIXED/C.TXT")).unwrap();
+        File::create(root.join("MIXED/d.txt")).unwrap();
 
-        if root.exists() {
-            fs::remove_dir_all(&root).unwrap();
-        }
+        let run = |args: &[&str]| {
+            let mut argv = vec!["ram-utils", "upper", "-q", "-y"];
+            argv.extend_from_slice(args);
+            let matches = build_cli().get_matches_from(argv);
+            let sub_args = matches.subcommand_matches("upper").unwrap();
+            convert_command(
+                sub_args,
+                &Config::default(),
+                Transform::Case(LetterCase::UpperCase),
+            )
+        };
+        let path = |name: &str| root.join(name).to_str().unwrap().to_string();
 
-        let extensions = ["foo", "bar", "baz123"];
-        for ext in extensions {
-            let mut filepath = root.join("testfile");
-            filepath.set_extension(ext);
-            fs::create_dir_all(&filepath.parent().unwrap()).unwrap();
-            fs::File::create(&filepath).unwrap();
-        }
+        // -- Everything is already upper case
+        assert_eq!(run(&["-r", &path("DONE")]), EXIT_NOTHING_TO_DO);
+
+        // -- Some entries are renamed
+        assert_eq!(run(&["-r", &path("MIXED")]), 0);
+        assert!(root.join("MIXED/D.TXT").exists());
+        assert_eq!(run(&["-r", &path("MIXED")]), EXIT_NOTHING_TO_DO);
 
-        let exts = find_unique_extensions(&root).unwrap();
-        for (ext, count) in exts.iter() {
-            assert!(extensions.contains(&ext.as_str()));
-            assert_eq!(*count, 1);
+        // -- Errors take precedence
+        assert_eq!(run(&[&path("missing")]), EXIT_ERROR);
+
+        // -- Copies follow the same codes
+        let into = path("COPIES");
+        assert_eq!(run(&["--into", &into, &path("MIXED/D.TXT")]), 0);
+        let nothing = path("EMPTY");
+        fs::create_dir_all(&nothing).unwrap();
+        assert_eq!(run(&["--into", &into, &nothing]), EXIT_NOTHING_TO_DO);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_restore_case() {
+        let root = env::temp_dir().join("ram-utils-restore-case-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("Photos/Summer Trip")).unwrap();
+        fs::write(root.join("Photos/Summer Trip/Beach.jpg"), "beach").unwrap();
+        fs::write(root.join("Photos/notes.TXT"), "notes").unwrap();
+        fs::write(root.join("Photos/README"), "readme").unwrap();
+
+        let manifest = root.join("manifest.tsv");
+        let matches = build_cli().get_matches_from(vec![
+            "ram-utils",
+            "upper",
+            "-r",
+            "-q",
+            "-y",
+            "--backup",
+            manifest.to_str().unwrap(),
+            root.join("Photos").to_str().unwrap(),
+        ]);
+        let sub_args = matches.subcommand_matches("upper").unwrap();
+        let transform = Transform::Case(LetterCase::UpperCase);
+        assert_eq!(convert_command(sub_args, &Config::default(), transform), 0);
+        assert!(root.join("PHOTOS/SUMMER TRIP/BEACH.JPG").exists());
+
+        // -- Only renamed entries are recorded, in the order they were renamed
+        let entries = read_manifest(io::BufReader::new(File::open(&manifest).unwrap())).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    root.join("Photos/Summer Trip/BEACH.JPG"),
+                    root.join("Photos/Summer Trip/Beach.jpg")
+                ),
+                (
+                    root.join("Photos/SUMMER TRIP"),
+                    root.join("Photos/Summer Trip")
+                ),
+                (root.join("Photos/NOTES.TXT"), root.join("Photos/notes.TXT")),
+                (root.join("PHOTOS"), root.join("Photos")),
+            ]
+        );
+
+        // -- A dry run renames nothing
+        let mut log = quiet_logger();
+        restore_names(&entries, true, ConflictPolicy::Error, &mut log);
+        assert!(root.join("PHOTOS").exists());
+
+        // -- Original names are restored exactly
+        let matches = build_cli().get_matches_from(vec![
+            "ram-utils",
+            "restore-case",
+            "-q",
+            "-y",
+            manifest.to_str().unwrap(),
+        ]);
+        let sub_args = matches.subcommand_matches("restore-case").unwrap();
+        assert_eq!(restore_case_command(sub_args, &Config::default()), 0);
+        let mut names = Vec::new();
+        walk(&root, &WalkOptions::default(), &mut |path, _| {
+            names.push(path.strip_prefix(&root).unwrap().to_path_buf());
+            Ok(())
+        })
+        .unwrap();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "Photos",
+                "Photos/README",
+                "Photos/Summer Trip",
+                "Photos/Summer Trip/Beach.jpg",
+                "Photos/notes.TXT",
+                "manifest.tsv",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("Photos/Summer Trip/Beach.jpg")).unwrap(),
+            "beach"
+        );
+
+        // -- Entries renamed again since are skipped
+        fs::rename(root.join("Photos/notes.TXT"), root.join("Photos/notes.md")).unwrap();
+        let entries = vec![
+            (root.join("Photos/notes.TXT"), root.join("Photos/NOTES.TXT")),
+            (root.join("Photos/README"), root.join("Photos/readme")),
+        ];
+        let mut log = quiet_logger();
+        restore_names(&entries, false, ConflictPolicy::Error, &mut log);
+        assert_eq!((log.summary.files, log.summary.skipped), (1, 1));
+        assert!(root.join("Photos/notes.md").exists());
+        assert!(has_exact_name(&root.join("Photos/readme")));
+        assert!(!has_exact_name(&root.join("Photos/README")));
+
+        // -- Malformed manifests are rejected
+        assert!(read_manifest(&b"no tab here\n"[..]).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_build_cli() {
+        let args = build_cli().get_matches_from(vec!["ram-utils", "upper", "-r", "-vv", "photos"]);
+        let (name, sub_args) = args.subcommand();
+        let sub_args = sub_args.unwrap();
+        assert_eq!(name, "upper");
+        assert_eq!(sub_args.value_of("path"), Some("photos"));
+        assert!(sub_args.is_present("recursive"));
+        assert_eq!(verbosity(sub_args), Verbosity::VeryVerbose);
+
+        // -- Global options are available to subcommands wherever they are passed
+        let args = build_cli().get_matches_from(vec![
+            "ram-utils",
+            "-q",
+            "--color",
+            "always",
+            "unique_ext",
+            "--sort",
+            "count",
+            ".",
+        ]);
+        let sub_args = args.subcommand_matches("unique_ext").unwrap();
+        assert_eq!(verbosity(sub_args), Verbosity::Quiet);
+        assert_eq!(sub_args.value_of("sort"), Some("count"));
+        assert_eq!(
+            color_choice(sub_args, &Config::default()),
+            ColorChoice::Always
+        );
+
+        let args = build_cli().get_matches_from(vec!["ram-utils", "lower", "-v", "a.txt"]);
+        let sub_args = args.subcommand_matches("lower").unwrap();
+        assert_eq!(verbosity(sub_args), Verbosity::Verbose);
+
+        // -- `dedupe --keep-first` is `find-dupes --delete`
+        let args = build_cli().get_matches_from(vec!["ram-utils", "dedupe", "--keep-first", "."]);
+        let sub_args = args.subcommand_matches("find-dupes").unwrap();
+        assert!(sub_args.is_present("delete"));
+        assert_eq!(
+            color_choice(sub_args, &Config::default()),
+            ColorChoice::Never
+        );
+
+        assert!(build_cli()
+            .get_matches_from_safe(vec!["ram-utils", "upper", "-q", "-v", "a.txt"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(vec!["ram-utils", "--color", "sometimes", "tree"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_walk_options_precedence() {
+        let app = App::new("test")
+            .arg(Arg::with_name("include-hidden").long("include-hidden"))
+            .arg(Arg::with_name("skip-hidden").long("skip-hidden"));
+
+        let include = Config {
+            include_hidden: Some(true),
+            ..Default::default()
+        };
+
+        // -- Test built-in default
+        let args = app.clone().get_matches_from(vec!["test"]);
+        assert!(!walk_options(&args, &Config::default()).include_hidden);
+
+        // -- Test config overrides the default
+        assert!(walk_options(&args, &include).include_hidden);
+
+        // -- Test command line overrides the config
+        let args = app.clone().get_matches_from(vec!["test", "--skip-hidden"]);
+        assert!(!walk_options(&args, &include).include_hidden);
+
+        let args = app.get_matches_from(vec!["test", "--include-hidden"]);
+        assert!(walk_options(&args, &Config::default()).include_hidden);
+    }
+
+    #[test]
+    fn test_rename_from() {
+        let root = env::temp_dir().join("ram-utils-rename-from-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("My Cool File.txt"), "a").unwrap();
+        fs::write(root.join("other.txt"), "b").unwrap();
+
+        let mapping = root.join("mapping.tsv");
+        let write_mapping = |rows: &[(&str, &str)]| {
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|(from, to)| {
+                    format!(
+                        "{}\t{}\n",
+                        root.join(from).display(),
+                        root.join(to).display()
+                    )
+                })
+                .collect();
+            fs::write(&mapping, rows.concat()).unwrap();
+        };
+        let rename_from = |extra: &[&str]| {
+            let mut argv = vec!["ram-utils", "rename-from", "-q", "-y"];
+            argv.extend_from_slice(extra);
+            argv.push(mapping.to_str().unwrap());
+            let matches = build_cli().get_matches_from(argv);
+            let matches = matches.subcommand_matches("rename-from").unwrap();
+            rename_from_command(matches, &Config::default())
+        };
+
+        // -- Nothing is renamed if any row can't be applied
+        write_mapping(&[("My Cool File.txt", "cool.txt"), ("other.txt", "cool.txt")]);
+        assert_eq!(rename_from(&[]), EXIT_ERROR);
+        assert!(root.join("My Cool File.txt").exists());
+        assert!(root.join("other.txt").exists());
+
+        write_mapping(&[
+            ("My Cool File.txt", "cool.txt"),
+            ("other.txt", "My Cool File.txt"),
+        ]);
+        assert_eq!(rename_from(&["--dry-run"]), 0);
+        assert!(!root.join("cool.txt").exists());
+
+        assert_eq!(rename_from(&[]), 0);
+        assert_eq!(fs::read_to_string(root.join("cool.txt")).unwrap(), "a");
+        assert_eq!(
+            fs::read_to_string(root.join("My Cool File.txt")).unwrap(),
+            "b"
+        );
+        assert!(!root.join("other.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_completions() {
+        for shell in &Shell::variants() {
+            let mut script = Vec::new();
+            build_cli().gen_completions_to("ram-utils", shell.parse().unwrap(), &mut script);
+            let script = String::from_utf8(script).unwrap();
+            assert!(script.contains("fix-dupext"), "{}", shell);
+            assert!(script.contains("on-conflict"), "{}", shell);
         }
+    }
+
+    #[test]
+    fn test_convert_max_depth() {
+        let root = env::temp_dir().join("ram-utils-convert-max-depth-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        File::create(root.join("a/one.txt")).unwrap();
+        File::create(root.join("a/b/two.txt")).unwrap();
+        File::create(root.join("a/b/c/three.txt")).unwrap();
+
+        let matches = build_cli().get_matches_from(vec![
+            "ram-utils",
+            "upper",
+            "-q",
+            "-y",
+            "--no-journal",
+            "--max-depth",
+            "2",
+            root.join("a").to_str().unwrap(),
+        ]);
+        let sub_args = matches.subcommand_matches("upper").unwrap();
+        assert_eq!(
+            convert_command(
+                sub_args,
+                &Config::default(),
+                Transform::Case(LetterCase::UpperCase)
+            ),
+            0
+        );
+
+        assert!(has_exact_name(&root.join("A/ONE.TXT")));
+        assert!(has_exact_name(&root.join("A/B/TWO.TXT")));
+        assert!(has_exact_name(&root.join("A/B/C")));
+        assert!(has_exact_name(&root.join("A/B/C/three.txt")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_hidden_skipped_by_default() {
+        // Already upper case, as converting the directory renames it too
+        let root = env::temp_dir().join("RAM-UTILS-HIDDEN-DEFAULT-TEST");
+        let reset = || {
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join(".git/refs")).unwrap();
+            File::create(root.join(".git/config")).unwrap();
+            File::create(root.join(".env")).unwrap();
+            File::create(root.join("notes.txt")).unwrap();
+        };
+        let run = |args: Vec<&str>| {
+            let matches = build_cli().get_matches_from(args);
+            let config = Config::default();
+            match matches.subcommand() {
+                ("upper", Some(sub_args)) => {
+                    convert_command(sub_args, &config, Transform::Case(LetterCase::UpperCase))
+                }
+                ("organize", Some(sub_args)) => organize_command(sub_args, &config),
+                ("prune-empty", Some(sub_args)) => prune_empty_command(sub_args, &config),
+                ("watch", Some(sub_args)) => watch_command(sub_args, &config),
+                _ => unreachable!(),
+            }
+        };
+        let path = root.to_str().unwrap();
+        let hidden_untouched = || {
+            has_exact_name(&root.join(".git/config"))
+                && has_exact_name(&root.join(".git/refs"))
+                && has_exact_name(&root.join(".env"))
+        };
+
+        // -- Hidden entries and everything in hidden directories are left alone by every
+        // walking command, even the ones that move or remove entries
+        reset();
+        run(vec![
+            "ram-utils",
+            "upper",
+            "-q",
+            "-y",
+            "--no-journal",
+            "-r",
+            path,
+        ]);
+        assert!(hidden_untouched());
+        assert!(has_exact_name(&root.join("NOTES.TXT")));
+
+        reset();
+        run(vec![
+            "ram-utils",
+            "watch",
+            "upper",
+            "-q",
+            "-r",
+            "--once",
+            path,
+        ]);
+        assert!(hidden_untouched());
+        assert!(has_exact_name(&root.join("NOTES.TXT")));
+
+        reset();
+        run(vec!["ram-utils", "organize", "-q", "-y", path]);
+        assert!(hidden_untouched());
+        assert!(has_exact_name(&root.join("txt/notes.txt")));
+
+        reset();
+        run(vec!["ram-utils", "prune-empty", "-q", "-y", path]);
+        assert!(hidden_untouched());
+
+        // -- Unless asked to include them
+        reset();
+        run(vec![
+            "ram-utils",
+            "upper",
+            "-q",
+            "-y",
+            "--no-journal",
+            "-r",
+            "--include-hidden",
+            path,
+        ]);
+        assert!(has_exact_name(&root.join(".GIT/CONFIG")));
 
         fs::remove_dir_all(&root).unwrap();
     }